@@ -0,0 +1,286 @@
+//! Running playbooks from Rust, without the command line.
+//!
+//! ```no_run
+//! use chgops::{NoObserver, Workspace};
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut workspace = Workspace::open("./playbooks/workspace2")?
+//!     .with_playbook("playbook")
+//!     .with_stage("dev")
+//!     .with_args([("CONFIRM", "continue")]);
+//! workspace.load()?;
+//! for task in workspace.plan()?.tasks {
+//!     println!("{} ({})", task.name, task.task_type);
+//! }
+//! let report = workspace.run(NoObserver)?;
+//! println!("{} tasks failed", report.summary.failed_counter);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each `Workspace` keeps its own collections, task types, facts, secrets and history, so several
+//! can be loaded and run one after the other or on different threads. The log messages of a load or
+//! run follow the filter of its workspace, `CHGOPS_LOG` plus the `template` and `timing` targets at
+//! `Verbose::VVV`, and mask the secrets of that workspace only.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use crate::collections::dx::context::RunContext;
+use crate::collections::dx::mock::MockRegistry;
+use crate::collections::dx::observer::RunObserver;
+use crate::collections::dx::report::RunReport;
+use crate::collections::dx::task_registry::TaskDefinition;
+use crate::collections::dx::vault::Vault;
+use crate::collections::dx::ChgOpsWorkspace;
+use crate::collections::Verbose;
+
+/// A task the loaded playbook will run, in order.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedTask {
+    pub name: String,
+    pub task_type: String,
+    pub register: Option<String>,
+    /// the command as mocks match it
+    pub command: String,
+}
+
+/// What a run of the loaded playbook will do, without running anything.
+#[derive(Debug, Clone, Serialize)]
+pub struct Plan {
+    pub playbook: String,
+    pub stage: Option<String>,
+    pub change_id: Option<String>,
+    pub tasks: Vec<PlannedTask>,
+}
+
+/// A workspace opened to run one of its playbooks.
+///
+/// Runs are non-interactive unless [`interactive`](Self::interactive) is set: prompts and pauses
+/// read their answers from the arguments.
+pub struct Workspace {
+    workspace: ChgOpsWorkspace,
+    stage: Option<String>,
+    arguments: HashMap<String, String>,
+    interactive: bool,
+    mocks: Option<MockRegistry>,
+    loaded: bool,
+}
+
+impl Workspace {
+    /// Opens the workspace folder, e.g. `./playbooks/workspace2`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Workspace, Box<dyn Error>> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(format!("Workspace not found: {}", path.display()).into());
+        }
+        let mut workspace = ChgOpsWorkspace::new();
+        workspace.workspace_path = path.to_string_lossy().to_string();
+        workspace.history = false;
+        Ok(Workspace {
+            workspace,
+            stage: None,
+            arguments: HashMap::new(),
+            interactive: false,
+            mocks: None,
+            loaded: false,
+        })
+    }
+
+    /// The playbook to run, by name without `.yaml`.
+    pub fn with_playbook(mut self, name: &str) -> Workspace {
+        self.workspace.playbook_name = name.to_string();
+        self.loaded = false;
+        self
+    }
+
+    /// The stage whose `vars/<stage>` folder is merged, also passed as the `STAGE` argument.
+    pub fn with_stage(mut self, stage: &str) -> Workspace {
+        self.stage = Some(stage.to_string());
+        self.loaded = false;
+        self
+    }
+
+    /// Arguments of the run, as `--arguments` gives them on the command line.
+    pub fn with_args<K: Into<String>, V: Into<String>>(mut self, arguments: impl IntoIterator<Item = (K, V)>) -> Workspace {
+        self.arguments.extend(arguments.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// The change ticket the run belongs to, as `--change-id`.
+    pub fn with_change_id(mut self, change_id: &str) -> Workspace {
+        self.workspace.change_id = change_id.to_string();
+        self
+    }
+
+    /// Answers tasks from `mocks` instead of executing them.
+    pub fn with_mocks(mut self, mocks: MockRegistry) -> Workspace {
+        self.mocks = Some(mocks);
+        self
+    }
+
+    /// Verbosity of the run, as `-q` or `-v` on the command line; `Verbose::VVV` also turns on the
+    /// `template` and `timing` log targets.
+    pub fn with_verbose(mut self, verbose: Verbose) -> Workspace {
+        self.workspace.verbose = verbose;
+        self
+    }
+
+    /// Decrypts the encrypted vars files with `vault`, e.g. `Vault::with_password`.
+    pub fn with_vault(mut self, vault: Vault) -> Workspace {
+        self.workspace.vault = vault;
+        self
+    }
+
+    /// Records the run in `.chgops/runs` of the current directory, where `chgops history` lists it.
+    /// Library runs are not recorded unless this is set.
+    pub fn with_history(mut self, history: bool) -> Workspace {
+        self.workspace.history = history;
+        self
+    }
+
+    /// Adds a task type to the ones this workspace parses its playbook with.
+    pub fn with_task(mut self, definition: TaskDefinition) -> Workspace {
+        self.workspace.tasks.register(definition);
+        self.loaded = false;
+        self
+    }
+
+    /// Lets prompts and pauses wait for the terminal.
+    pub fn interactive(mut self, interactive: bool) -> Workspace {
+        self.interactive = interactive;
+        self
+    }
+
+    /// Resolves the collections, validates the sources, renders the vars and parses the playbook.
+    pub fn load(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.workspace.playbook_name.is_empty() {
+            return Err("No playbook selected, see Workspace::with_playbook".into());
+        }
+        self.workspace.arguments = self.stage.as_ref().map(|stage| format!("STAGE={}", stage)).unwrap_or_default();
+        self.workspace.load()?;
+        self.loaded = true;
+        Ok(())
+    }
+
+    fn ensure_loaded(&mut self) -> Result<(), Box<dyn Error>> {
+        match self.loaded {
+            true => Ok(()),
+            false => self.load(),
+        }
+    }
+
+    /// The tasks a run would execute; fails when the change rules refuse the run.
+    pub fn plan(&mut self) -> Result<Plan, Box<dyn Error>> {
+        self.ensure_loaded()?;
+        self.workspace.validate_change()?;
+        Ok(Plan {
+            playbook: self.workspace.playbook.name.clone(),
+            stage: self.stage.clone(),
+            change_id: self.workspace.effective_change_id(),
+            tasks: self.workspace.playbook.tasks.iter().map(|task| {
+                let info = task.info();
+                PlannedTask {
+                    name: info.name,
+                    task_type: info.task_type,
+                    register: info.register,
+                    command: task.command_text(),
+                }
+            }).collect(),
+        })
+    }
+
+    /// Runs the playbook, telling `observer` about it as it happens; `observer` is the only one the
+    /// run has, nothing is written to the console by a sink.
+    ///
+    /// Fails when the run cannot start; failed tasks are reported in the [`RunReport`].
+    pub fn run(&mut self, observer: impl RunObserver + 'static) -> Result<RunReport, Box<dyn Error>> {
+        let mut context = self.run_context()?;
+        context.observers.add(Box::new(observer));
+        self.execute(&mut context)
+    }
+
+    /// The context a [`run`](Self::run) starts with: the loaded facts, the arguments and the mocks,
+    /// without observers.
+    pub fn run_context(&mut self) -> Result<RunContext, Box<dyn Error>> {
+        self.ensure_loaded()?;
+        let mut context = self.workspace.run_context()?;
+        context.options.non_interactive = !self.interactive;
+        context.options.arguments.extend(self.arguments.clone());
+        if let Some(mocks) = self.mocks.clone() {
+            context.mocks = mocks;
+        }
+        Ok(context)
+    }
+
+    /// Runs the playbook in `context`, which holds the facts, with their secret values, and the mock
+    /// calls of the run afterwards.
+    pub fn execute(&mut self, context: &mut RunContext) -> Result<RunReport, Box<dyn Error>> {
+        if !self.loaded {
+            return Err("The workspace changed since the context was made, see Workspace::run_context".into());
+        }
+        self.workspace.execute(context)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::dx::task_registry::PlaybookTask;
+    use crate::collections::dx::PlaybookSummary;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl RunObserver for Recorder {
+        fn task_start(&mut self, task: &dyn PlaybookTask) {
+            self.0.lock().unwrap().push(format!("start {}", task.info().name));
+        }
+
+        fn task_end(&mut self, task: &dyn PlaybookTask) {
+            self.0.lock().unwrap().push(format!("end {}", task.info().name));
+        }
+
+        fn playbook_end(&mut self, summary: &PlaybookSummary) {
+            self.0.lock().unwrap().push(format!("summary {}", summary.failed_counter));
+        }
+    }
+
+    fn workspace(name: &str, playbook: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("chgops-api-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(path.join("vars")).unwrap();
+        std::fs::write(path.join("vars/vars.yaml"), "vars: { greeting: hello }\n").unwrap();
+        std::fs::write(path.join("playbook.yaml"), playbook).unwrap();
+        path
+    }
+
+    fn runs() -> usize {
+        std::fs::read_dir(ChgOpsWorkspace::new().runs_path()).map(|entries| entries.count()).unwrap_or(0)
+    }
+
+    #[test]
+    fn a_library_run_only_tells_its_observer_and_keeps_no_history() {
+        let path = workspace("run", "name: api\nsettings:\n  name: api\ntasks:\n  - dx.core.bash:\n      name: greet\n      command: \"echo {{ vars.greeting }}\"\n      vars:\n        resource: \"\"\n");
+        let events = Arc::new(Mutex::new(vec![]));
+        let before = runs();
+
+        let mut workspace = Workspace::open(&path).unwrap().with_playbook("playbook");
+        let report = workspace.run(Recorder(events.clone())).unwrap();
+
+        assert_eq!(report.summary.failed_counter, 0);
+        assert_eq!(*events.lock().unwrap(), vec!["start greet", "end greet", "summary 0"]);
+        assert_eq!(runs(), before);
+    }
+
+    #[test]
+    fn a_workspace_that_does_not_load_is_an_error() {
+        let path = workspace("broken", "name: api\nsettings:\n  name: api\ntasks:\n  - dx.core.bsh:\n      name: typo\n");
+
+        assert!(Workspace::open(path.join("missing")).is_err());
+        assert!(Workspace::open(&path).unwrap().load().is_err());
+        let err = Workspace::open(&path).unwrap().with_playbook("playbook").load().unwrap_err();
+        assert!(err.to_string().contains("dx.core.bsh"), "{}", err);
+    }
+}
@@ -1,18 +1,25 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use crate::collections::dx::{azure::cli::AzCli, PlaybookCommand, PlaybookCommandTrait, PlaybookCommandOutput};
+use crate::collections::dx::context::RunContext;
+use crate::collections::Verbose;
+use crate::collections::dx::vault::Secrets;
+use crate::collections::dx::schema::task_schema;
+use crate::collections::dx::task_registry::{parse_command, TaskCommand, TaskDefinition, TaskField, TaskRegistry};
 use serde_yaml::Value as YamlValue;
-use crate::{print_error, print_info, print_success, print_banner_yellow, print_banner_green, print_banner_red, print_banner_blue};
 // use crate::{print_error, print_warning, print_info, print_success, print_banner_yellow, print_banner_green, print_banner_red, print_banner_blue};
-#[derive(Debug, Deserialize, Serialize)]
-pub enum AzureTasks {
-    #[serde(rename = "dx.azure.login")]
-    AzureLoginTask(AzureLoginTask),
-
-    #[serde(rename = "dx.azure.cli")]
-    AzureCliTask(AzureCliTask)
+pub fn register(registry: &mut TaskRegistry) {
+    registry
+        .register(TaskDefinition::new("dx.azure.login", "Logs in to Azure with a service principal", parse_command::<AzureLoginVars, YamlValue>)
+            .schema(task_schema::<AzureLoginVars, serde_json::Value>)
+            .command(TaskField::required("command", "object", "client_id, secret and tenant of the service principal")))
+        .register(TaskDefinition::new("dx.azure.cli", "Runs an az command", parse_command::<String, AzureCliVars>)
+            .schema(task_schema::<String, AzureCliVars>)
+            .command(TaskField::required("command", "string", "az arguments, e.g. account show"))
+            .var(TaskField::required("resource", "any", "free form data kept with the task")));
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct AzureLoginVars {
     pub client_id: String,
     pub secret: String,
@@ -22,7 +29,7 @@ pub struct AzureLoginVars {
 pub type AzureLoginTask = PlaybookCommand<AzureLoginVars, YamlValue>;
 
 impl PlaybookCommandTrait for AzureLoginTask {
-    fn execute(&mut self) {
+    fn execute(&mut self, _context: &mut RunContext) {
         self.output = PlaybookCommandOutput::new();
         self.output.set_start_time();
 
@@ -49,51 +56,30 @@ impl PlaybookCommandTrait for AzureLoginTask {
 
     }
 
-    fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
-        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***", 
-            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
-            self.output.status,
-            self.output.success,
-            self.output.failed,
-            self.output.skipped,
-            self.output.changed
-        );
-        if verbose.len() >= 1 {
-            print_info!("Task details: {:?}", self);
-            //print_info!("Command: {}", command_str);
-        }
-        if verbose.len() >= 2 {
-            print_banner_yellow!("=== Output Obj ===");
-            print_info!("{:?}", self.output);
-        }
-        else {
-            if self.output.stdout != "" {
-                print_banner_green!("=== Output ===");
-                print_success!("{}", self.output.stdout);
-            }
-            if self.output.stderr != "" {
-                print_banner_red!("=== Errors ===");
-                print_error!("{}", self.output.stderr);
-            }
-        }
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
         self.output.clone()
     }
+
+    fn secrets(&self) -> Vec<String> {
+        vec![self.command.secret.clone()]
+    }
 }
 
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
 pub struct AzureCliVars {
+    #[schemars(with = "serde_json::Value")]
     pub resource: YamlValue,
 }
 
 pub type AzureCliTask = PlaybookCommand<String, AzureCliVars>;
 
 impl PlaybookCommandTrait for AzureCliTask {
-    fn execute(&mut self) {
+    fn execute(&mut self, _context: &mut RunContext) {
         self.output = PlaybookCommandOutput::new();
         self.output.set_start_time();
 
@@ -121,34 +107,8 @@ impl PlaybookCommandTrait for AzureCliTask {
         self.output.set_end_time();
     }
 
-    fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
-        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***", 
-            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
-            self.output.status,
-            self.output.success,
-            self.output.failed,
-            self.output.skipped,
-            self.output.changed
-        );
-        if verbose.len() >= 1 {
-            print_info!("Task details: {:?}", self);
-            //print_info!("Command: {}", command_str);
-        }
-        if verbose.len() >= 2 {
-            print_banner_yellow!("=== Output Obj ===");
-            print_info!("{:?}", self.output);
-        }
-        else {
-            if self.output.stdout != "" {
-                print_banner_green!("=== Output ===");
-                print_success!("{}", self.output.stdout);
-            }
-            if self.output.stderr != "" {
-                print_banner_red!("=== Errors ===");
-                print_error!("{}", self.output.stderr);
-            }
-        }
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
@@ -156,27 +116,8 @@ impl PlaybookCommandTrait for AzureCliTask {
     }
 }
 
-
-
-impl PlaybookCommandTrait for AzureTasks {
-    fn execute(&mut self) {
-        match self {
-            AzureTasks::AzureLoginTask(task) => task.execute(),
-            AzureTasks::AzureCliTask(task) => task.execute(),
-        }
-    }
-
-    fn display(&self, verbose: Option<String>) {
-        match self {
-            AzureTasks::AzureLoginTask(task) => task.display(verbose),
-            AzureTasks::AzureCliTask(task) => task.display(verbose),
-        }
+impl TaskCommand for AzureLoginVars {
+    fn text(&self) -> String {
+        format!("login {}", self.client_id)
     }
-
-    fn output(&self) -> PlaybookCommandOutput {
-        match self {
-            AzureTasks::AzureLoginTask(task) => task.output(),
-            AzureTasks::AzureCliTask(task) => task.output(),
-        }
-    }
-}
\ No newline at end of file
+}
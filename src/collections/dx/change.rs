@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+
+/// Change ticket a playbook is executed under, declared as `settings.change`.
+#[derive(Debug, Deserialize, Default, Serialize, Clone, JsonSchema)]
+pub struct ChangeTicket {
+    pub id: String,
+    pub title: Option<String>,
+    pub risk: Option<String>,
+    pub requester: Option<String>,
+    #[serde(default)]
+    pub approvers: Vec<String>,
+    pub window_start: Option<DateTime<Utc>>,
+    pub window_end: Option<DateTime<Utc>>,
+}
+
+impl ChangeTicket {
+    pub fn in_window(&self, now: DateTime<Utc>) -> bool {
+        if let Some(start) = self.window_start {
+            if now < start {
+                return false;
+            }
+        }
+        if let Some(end) = self.window_end {
+            if now > end {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn display(&self) {
+        println!("Change: {} #####################################", self.id);
+        println!("\tTitle: {}", self.title.clone().unwrap_or_default());
+        println!("\tRisk: {}", self.risk.clone().unwrap_or_default());
+        println!("\tRequester: {}", self.requester.clone().unwrap_or_default());
+        println!("\tApprovers: {}", self.approvers.join(", "));
+        println!("\tWindow: {} - {}",
+            self.window_start.map(|t| t.to_rfc3339()).unwrap_or("open".to_string()),
+            self.window_end.map(|t| t.to_rfc3339()).unwrap_or("open".to_string())
+        );
+    }
+}
+
+/// Looks up the classification of `stage` in `common_vars.validation`, e.g. "Higher" for "prd".
+///
+/// Stages are matched against their key, name or short name:
+/// `p: ["Production", "prd", 3, "h"]` with `stage_classifications.h: ["Higher", "hr", 1]`.
+pub fn stage_classification(facts: &JsonValue, stage: &str) -> Option<String> {
+    let validation = facts.get("common_vars")?.get("validation")?;
+    let stages = validation.get("stages")?.as_object()?;
+
+    let class_key = stages.iter().find_map(|(key, definition)| {
+        let definition = definition.as_array()?;
+        let names = [Some(key.as_str()), definition.first()?.as_str(), definition.get(1)?.as_str()];
+        if names.iter().flatten().any(|name| name.eq_ignore_ascii_case(stage)) {
+            definition.get(3)?.as_str().map(|c| c.to_string())
+        } else {
+            None
+        }
+    })?;
+
+    let classification = validation.get("stage_classifications")?.get(&class_key)?.as_array()?;
+    classification.first()?.as_str().map(|c| c.to_string())
+}
+
+/// Checks that a run is allowed by its change ticket.
+///
+/// Runs are refused outside the change window, and stages classified as "Higher" need a
+/// `--change-id` matching the playbook change.
+pub fn validate(change: Option<&ChangeTicket>, classification: Option<&str>, change_id: &str, now: DateTime<Utc>) -> Result<(), String> {
+    let higher = classification.map(|c| c.eq_ignore_ascii_case("Higher")).unwrap_or(false);
+
+    let change = match change {
+        Some(change) => change,
+        None => {
+            if higher {
+                return Err("Stage is classified as Higher and the playbook declares no settings.change".to_string());
+            }
+            return Ok(());
+        }
+    };
+
+    if !change.in_window(now) {
+        return Err(format!(
+            "Change {} is outside its window ({} - {}), now is {}",
+            change.id,
+            change.window_start.map(|t| t.to_rfc3339()).unwrap_or("open".to_string()),
+            change.window_end.map(|t| t.to_rfc3339()).unwrap_or("open".to_string()),
+            now.to_rfc3339()
+        ));
+    }
+
+    if higher {
+        if change_id.is_empty() {
+            return Err(format!("Stage is classified as Higher, run with --change-id {}", change.id));
+        }
+        if change_id != change.id {
+            return Err(format!("--change-id {} does not match the playbook change {}", change_id, change.id));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn time(value: &str) -> DateTime<Utc> {
+        value.parse().unwrap()
+    }
+
+    fn ticket() -> ChangeTicket {
+        ChangeTicket {
+            id: "CHG-1234".to_string(),
+            window_start: Some(time("2024-06-01T20:00:00Z")),
+            window_end: Some(time("2024-06-01T23:00:00Z")),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn stages_are_classified_by_key_name_or_short_name() {
+        let facts = json!({
+            "common_vars": {
+                "validation": {
+                    "stages": {
+                        "d": ["Development", "dev", 1, "l"],
+                        "p": ["Production", "prd", 3, "h"]
+                    },
+                    "stage_classifications": {
+                        "l": ["Lower", "lr", 0],
+                        "h": ["Higher", "hr", 1]
+                    }
+                }
+            }
+        });
+
+        assert_eq!(stage_classification(&facts, "prd").as_deref(), Some("Higher"));
+        assert_eq!(stage_classification(&facts, "p").as_deref(), Some("Higher"));
+        assert_eq!(stage_classification(&facts, "production").as_deref(), Some("Higher"));
+        assert_eq!(stage_classification(&facts, "dev").as_deref(), Some("Lower"));
+        assert_eq!(stage_classification(&facts, "tst"), None);
+        assert_eq!(stage_classification(&json!({}), "prd"), None);
+    }
+
+    #[test]
+    fn runs_are_refused_outside_the_change_window() {
+        let change = ticket();
+
+        assert!(validate(Some(&change), None, "", time("2024-06-01T21:00:00Z")).is_ok());
+        assert!(validate(Some(&change), None, "", time("2024-06-01T19:59:59Z")).unwrap_err().contains("outside its window"));
+        assert!(validate(Some(&change), None, "", time("2024-06-02T00:00:00Z")).is_err());
+        assert!(validate(None, None, "", time("2024-06-02T00:00:00Z")).is_ok());
+    }
+
+    #[test]
+    fn higher_stages_need_the_matching_change_id() {
+        let change = ticket();
+        let now = time("2024-06-01T21:00:00Z");
+
+        assert!(validate(Some(&change), Some("Higher"), "CHG-1234", now).is_ok());
+        assert!(validate(Some(&change), Some("higher"), "", now).unwrap_err().contains("run with --change-id CHG-1234"));
+        assert!(validate(Some(&change), Some("Higher"), "CHG-1", now).unwrap_err().contains("does not match"));
+        assert!(validate(None, Some("Higher"), "CHG-1234", now).unwrap_err().contains("no settings.change"));
+        assert!(validate(Some(&change), Some("Lower"), "", now).is_ok());
+    }
+}
@@ -0,0 +1,332 @@
+use serde::{Deserialize, Serialize};
+use semver::{Version, VersionReq};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::collections::dx::{config_proc, files_and_dirs};
+use crate::collections::dx::dependencies::CollectionDependency;
+
+/// Manifest file at the root of every collection folder.
+pub const MANIFEST_FILE: &str = "collection.yaml";
+
+/// Folder of a collection holding its test playbooks.
+pub const TESTS_FOLDER: &str = "tests";
+
+/// Whether a namespace or collection name is letters, digits, `_` and `-`, safe as a folder name.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Whether a yaml file of a collection holds vars, as opposed to the manifest or a test.
+pub fn is_vars_file(file: &str) -> bool {
+    !file.ends_with(&format!("/{}", MANIFEST_FILE)) && !file.contains(&format!("/{}/", TESTS_FOLDER))
+}
+
+/// Types a collection can declare for its exported variables.
+pub const VARIABLE_TYPES: [&str; 6] = ["string", "number", "bool", "list", "object", "any"];
+
+/// A variable the collection provides to the workspaces using it.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct CollectionVariable {
+    #[serde(rename = "type", default = "default_type")]
+    pub var_type: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub required: bool,
+    pub default: Option<serde_yaml::Value>,
+    /// the value is masked wherever the run shows it, also spelled `no_log`
+    #[serde(default, alias = "no_log")]
+    pub secret: bool,
+}
+
+fn default_type() -> String {
+    "any".to_string()
+}
+
+/// A playbook fragment the collection ships for workspaces to copy or include.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct CollectionTaskTemplate {
+    pub name: String,
+    pub description: Option<String>,
+    pub path: String,
+}
+
+/// Content of `collection.yaml`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct CollectionManifest {
+    pub namespace: String,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<CollectionDependency>,
+    #[serde(default)]
+    pub variables: BTreeMap<String, CollectionVariable>,
+    #[serde(default)]
+    pub tasks: Vec<CollectionTaskTemplate>,
+}
+
+impl CollectionManifest {
+    pub fn new(namespace: &str, name: &str) -> CollectionManifest {
+        CollectionManifest {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            version: "0.1.0".to_string(),
+            description: Some(format!("The {}.{} collection", namespace, name)),
+            ..Default::default()
+        }
+    }
+
+    /// Fully qualified name, e.g. `dx.azure`.
+    pub fn full_name(&self) -> String {
+        format!("{}.{}", self.namespace, self.name)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, Box<dyn Error>> {
+        let yaml = serde_yaml::to_string(self)?;
+        Ok(format!("#!chgops.collection\n{}", yaml.trim_start_matches("---\n")))
+    }
+
+    /// Checks the manifest against the folder it sits in, returning every problem found.
+    pub fn validate(&self, collection_folder: &Path) -> Vec<String> {
+        let mut errors = vec![];
+
+        if !is_valid_name(&self.namespace) {
+            errors.push(format!("namespace {} is not valid, expected letters, digits, '_' and '-'", self.namespace));
+        }
+        if !is_valid_name(&self.name) {
+            errors.push(format!("name {} is not valid, expected letters, digits, '_' and '-'", self.name));
+        }
+        if let Some(folder_name) = collection_folder.file_name().map(|n| n.to_string_lossy().to_string()) {
+            if folder_name != self.name {
+                errors.push(format!("name {} does not match the folder {}", self.name, folder_name));
+            }
+        }
+        if let Some(namespace_name) = collection_folder.parent().and_then(|p| p.file_name()).map(|n| n.to_string_lossy().to_string()) {
+            if namespace_name != self.namespace {
+                errors.push(format!("namespace {} does not match the folder {}", self.namespace, namespace_name));
+            }
+        }
+        if let Err(e) = Version::parse(&self.version) {
+            errors.push(format!("version {} is not a semantic version: {}", self.version, e));
+        }
+
+        for dependency in self.dependencies.iter() {
+            if let Err(e) = dependency.namespace_and_name() {
+                errors.push(e.to_string());
+            }
+            if dependency.name == self.full_name() {
+                errors.push(format!("{} depends on itself", dependency.name));
+            }
+            if let Some(requirement) = &dependency.version {
+                if Version::parse(requirement).is_err() && VersionReq::parse(requirement).is_err() {
+                    errors.push(format!("dependency {} has an invalid version requirement: {}", dependency.name, requirement));
+                }
+            }
+        }
+
+        let vars = collection_vars(collection_folder).unwrap_or_else(|e| {
+            errors.push(format!("reading vars: {}", e));
+            serde_yaml::Mapping::new()
+        });
+        for (name, variable) in self.variables.iter() {
+            if !VARIABLE_TYPES.contains(&variable.var_type.as_str()) {
+                errors.push(format!("variable {} has an unknown type {}. Supported types: {}", name, variable.var_type, VARIABLE_TYPES.join(", ")));
+                continue;
+            }
+            match vars.get(&serde_yaml::Value::String(name.clone())).or(variable.default.as_ref()) {
+                Some(value) if !type_matches(&variable.var_type, value) => {
+                    errors.push(format!("variable {} is declared as {} but is {}", name, variable.var_type, type_name(value)));
+                },
+                None if variable.required => errors.push(format!("required variable {} is not defined in the collection vars", name)),
+                _ => {},
+            }
+        }
+
+        for task in self.tasks.iter() {
+            if !collection_folder.join(&task.path).exists() {
+                errors.push(format!("task template {} not found: {}", task.name, task.path));
+            }
+        }
+
+        errors
+    }
+
+    pub fn display(&self, collection_folder: &Path) {
+        println!("Collection: {} #####################################", self.full_name());
+        println!("\tVersion: {}", self.version);
+        println!("\tDescription: {}", self.description.clone().unwrap_or_default());
+        println!("\tAuthors: {}", self.authors.join(", "));
+        println!("\tPath: {:?}", collection_folder);
+        for dependency in self.dependencies.iter() {
+            println!("\tDependency: {} {}", dependency.name, dependency.version.clone().unwrap_or("*".to_string()));
+        }
+        for (name, variable) in self.variables.iter() {
+            let required = if variable.required { " (required)" } else { "" };
+            println!("\tVariable: {}: {}{} {}", name, variable.var_type, required, variable.description.clone().unwrap_or_default());
+        }
+        for task in self.tasks.iter() {
+            println!("\tTask template: {} ({}) {}", task.name, task.path, task.description.clone().unwrap_or_default());
+        }
+        println!("#############################################");
+    }
+}
+
+pub fn type_name(value: &serde_yaml::Value) -> &'static str {
+    match value {
+        serde_yaml::Value::Null => "null",
+        serde_yaml::Value::Bool(_) => "bool",
+        serde_yaml::Value::Number(_) => "number",
+        serde_yaml::Value::String(_) => "string",
+        serde_yaml::Value::Sequence(_) => "list",
+        serde_yaml::Value::Mapping(_) => "object",
+    }
+}
+
+pub fn type_matches(var_type: &str, value: &serde_yaml::Value) -> bool {
+    var_type == "any" || var_type == type_name(value)
+}
+
+/// Reads `collection.yaml` from a collection folder, `None` when the collection has no manifest.
+pub fn read_manifest(collection_folder: &Path) -> Result<Option<CollectionManifest>, Box<dyn Error>> {
+    let path = collection_folder.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let yaml = config_proc::read_yaml(path.to_str().unwrap())?;
+    let manifest = serde_yaml::from_value(yaml).map_err(|e| format!("Reading {:?}: {}", path, e))?;
+    Ok(Some(manifest))
+}
+
+/// Top level keys of every vars file in the collection, later files overriding earlier ones.
+pub fn collection_vars(collection_folder: &Path) -> Result<serde_yaml::Mapping, Box<dyn Error>> {
+    let mut vars = serde_yaml::Mapping::new();
+    for file in files_and_dirs::find_files_by_regex(collection_folder.to_string_lossy().to_string(), r".*\.yaml$")? {
+        if !is_vars_file(&file) {
+            continue;
+        }
+        let content = fs::read_to_string(&file)?;
+        if let Ok(serde_yaml::Value::Mapping(mapping)) = serde_yaml::from_str::<serde_yaml::Value>(&content) {
+            for (key, value) in mapping {
+                vars.insert(key, value);
+            }
+        }
+    }
+    Ok(vars)
+}
+
+/// Every `<namespace>/<collection>` folder under `collections_path`, sorted by name.
+pub fn discover(collections_path: &Path) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+    let mut collections = vec![];
+    if !collections_path.is_dir() {
+        return Ok(collections);
+    }
+    for namespace in fs::read_dir(collections_path)? {
+        let namespace = namespace?.path();
+        if !namespace.is_dir() {
+            continue;
+        }
+        for collection in fs::read_dir(&namespace)? {
+            let collection = collection?.path();
+            if collection.is_dir() {
+                let name = format!(
+                    "{}.{}",
+                    namespace.file_name().unwrap().to_string_lossy(),
+                    collection.file_name().unwrap().to_string_lossy()
+                );
+                collections.push((name, collection));
+            }
+        }
+    }
+    collections.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(collections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collection(name: &str, vars: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chgops-collection-{}-{}", name, std::process::id())).join("acme/tools");
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join(TESTS_FOLDER)).unwrap();
+        fs::write(path.join("vars.yaml"), vars).unwrap();
+        fs::write(path.join(TESTS_FOLDER).join("smoke.yaml"), "tags: not vars\n").unwrap();
+        path
+    }
+
+    #[test]
+    fn the_shipped_collections_have_valid_manifests() {
+        for folder in ["./collections/dx/azure", "./collections/demo/basic"] {
+            let folder = Path::new(folder);
+            let manifest = read_manifest(folder).unwrap().unwrap();
+            assert_eq!(manifest.validate(folder), Vec::<String>::new(), "{}", manifest.full_name());
+        }
+    }
+
+    #[test]
+    fn a_manifest_is_checked_against_its_folder_vars_and_templates() {
+        let folder = collection("invalid", "tags: [a, b]\nowner: ops\n");
+        let manifest: CollectionManifest = serde_yaml::from_str(r#"
+namespace: acme
+name: tool
+version: "1.0"
+dependencies:
+  - name: acme.tool
+  - name: nodots
+  - name: dx.azure
+    version: "one"
+variables:
+  tags: { type: object }
+  owner: { type: string }
+  region: { type: string, required: true }
+  size: { type: integer }
+  zone: { type: string, required: true, default: 1 }
+tasks:
+  - name: web
+    path: blueprints/web
+"#).unwrap();
+
+        assert_eq!(manifest.validate(&folder), vec![
+            "name tool does not match the folder tools",
+            "version 1.0 is not a semantic version: unexpected end of input while parsing minor version number",
+            "acme.tool depends on itself",
+            "Invalid collection name: nodots. Expected format: namespace.collection",
+            "dependency dx.azure has an invalid version requirement: one",
+            "required variable region is not defined in the collection vars",
+            "variable size has an unknown type integer. Supported types: string, number, bool, list, object, any",
+            "variable tags is declared as object but is list",
+            "variable zone is declared as string but is number",
+            "task template web not found: blueprints/web",
+        ]);
+    }
+
+    #[test]
+    fn a_new_manifest_is_written_and_read_back() {
+        let folder = collection("new", "owner: ops\n");
+        let mut manifest = CollectionManifest::new("acme", "tools");
+        manifest.variables.insert("owner".to_string(), CollectionVariable { var_type: "string".to_string(), secret: true, ..Default::default() });
+        fs::write(folder.join(MANIFEST_FILE), manifest.to_yaml().unwrap()).unwrap();
+
+        let read = read_manifest(&folder).unwrap().unwrap();
+        assert_eq!((read.full_name(), read.version.as_str()), ("acme.tools".to_string(), "0.1.0"));
+        assert!(read.variables["owner"].secret);
+        assert_eq!(read.validate(&folder), Vec::<String>::new());
+        assert!(read_manifest(folder.parent().unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn collection_vars_leave_out_the_manifest_and_the_tests() {
+        let folder = collection("vars", "owner: ops\n");
+        fs::write(folder.join(MANIFEST_FILE), CollectionManifest::new("acme", "tools").to_yaml().unwrap()).unwrap();
+
+        let vars = collection_vars(&folder).unwrap();
+        assert_eq!(vars.iter().map(|(key, _)| key.as_str().unwrap()).collect::<Vec<_>>(), vec!["owner"]);
+
+        let collections = discover(folder.parent().unwrap().parent().unwrap()).unwrap();
+        assert_eq!(collections, vec![("acme.tools".to_string(), folder.clone())]);
+    }
+}
@@ -7,8 +7,11 @@ use std::fs;
 use tera::{Tera, Context};
 use crate::collections::dx::yaml_handler;
 use super::{core::filters, files_and_dirs};
+use super::provenance::Provenance;
+use super::vault::{Secrets, Vault};
+use std::collections::HashMap;
 use std::path::Path;
-use crate::print_error;
+use crate::{log_trace, print_error};
 
 // use crate::{print_error, print_info, print_success, print_warning};
 
@@ -65,20 +68,59 @@ pub fn yaml_to_string(yaml: &serde_yaml::Value) -> Result<String, Box<dyn Error>
 
 
 
-/// Processes a template string using the provided context and returns the rendered result.
-pub fn process_template(template_str: &str, context: &Context) -> Result<String, tera::Error> {
+// a template or its result on one short line, for the traces
+fn abbreviate(text: &str) -> String {
+    let line = text.trim().replace('\n', "\\n");
+    match line.char_indices().nth(120) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line,
+    }
+}
+
+/// Renders a template string with the engine functions and filters, without reporting errors.
+///
+/// Each render is traced with its duration under the `template` log target.
+pub fn render_template(template_str: &str, context: &Context) -> Result<String, tera::Error> {
+    render_template_masked(template_str, context, &Secrets::default())
+}
+
+// a filter writing its value out, with the secrets masked in the value first
+fn masked(filter: fn(&JsonValue, &HashMap<String, JsonValue>) -> tera::Result<JsonValue>, secrets: &Secrets) -> impl tera::Filter {
+    let secrets = secrets.clone();
+    move |value: &JsonValue, args: &HashMap<String, JsonValue>| {
+        let mut value = value.clone();
+        secrets.mask_json(&mut value);
+        filter(&value, args)
+    }
+}
+
+/// [`render_template`] where `as_yaml` and `as_json` never write the `secrets`.
+pub fn render_template_masked(template_str: &str, context: &Context, secrets: &Secrets) -> Result<String, tera::Error> {
+    let start = std::time::Instant::now();
     let mut tera = Tera::default();
-    let _ = tera.add_raw_template("process_template", &template_str);
+    tera.add_raw_template("process_template", template_str)?;
 
     tera.register_function("current_time", filters::current_time());
     tera.register_function("env_var", filters::env_var());
     tera.register_filter("filter1", filters::filter1);
     tera.register_filter("filter2", filters::filter2);
-    tera.register_filter("as_yaml", filters::as_yaml);
-    tera.register_filter("as_json", filters::as_json);
+    tera.register_filter("as_yaml", masked(filters::as_yaml, secrets));
+    tera.register_filter("as_json", masked(filters::as_json, secrets));
     tera.register_filter("as_base64", filters::as_base64);
 
-    match tera.render("process_template", &context) {
+    let rendered = tera.render("process_template", context);
+    log_trace!("template", "{} -> {} in {:?}",
+        abbreviate(template_str),
+        rendered.as_ref().map(|r| abbreviate(r)).unwrap_or_else(|e| format!("error {}", e)),
+        start.elapsed());
+    rendered
+}
+
+/// Processes a template string using the provided context and returns the rendered result.
+///
+/// `as_yaml` and `as_json` mask the `secrets` in what they write.
+pub fn process_template(template_str: &str, context: &Context, secrets: &Secrets) -> Result<String, tera::Error> {
+    match render_template_masked(template_str, context, secrets) {
         Ok(rendered) => {
             // Use the rendered template
             Ok(rendered)
@@ -92,6 +134,18 @@ pub fn process_template(template_str: &str, context: &Context) -> Result<String,
     }
 }
 
+/// The message of an error followed by the messages of its sources.
+pub fn error_chain(error: &dyn Error) -> String {
+    let mut messages = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(e) = source {
+        messages.push(e.to_string());
+        source = e.source();
+    }
+    messages.dedup();
+    messages.join(": ")
+}
+
 /// Merges two YAML values recursively.
 pub fn merge_yaml(a: &mut YamlValue, b: YamlValue) {
     match (a, b) {
@@ -107,21 +161,37 @@ pub fn merge_yaml(a: &mut YamlValue, b: YamlValue) {
 }
 
 
+/// Writes an intermediate step of the pipeline to the `--dump-intermediates` folder, when one is given,
+/// with the `secrets` masked.
+pub fn dump_intermediate(dump_dir: Option<&str>, name: &str, content: &str, secrets: &Secrets) -> Result<(), Box<dyn Error>> {
+    match dump_dir {
+        Some(dir) if !dir.is_empty() => files_and_dirs::write_file(&format!("{}/{}", dir.trim_end_matches('/'), name), &secrets.mask(content)),
+        _ => Ok(()),
+    }
+}
+
 /// Processes configuration files by merging them, resolving references, and rendering templates.
-pub fn process_configuration_files(collections_files: Vec<String>, workplace_files: Vec<String>) -> Result<yaml_rust2::Yaml, Box<dyn std::error::Error>> {
-    //println!("Processing configuration files...");
+///
+/// Everything happens in memory; `vault` decrypts the encrypted files and `dump_dir` receives
+/// `merged.yaml` and `final.yaml` for debugging, with the decrypted values masked.
+pub fn process_configuration_files(collections_files: Vec<String>, workplace_files: Vec<String>, dump_dir: Option<&str>, vault: &Vault) -> Result<yaml_rust2::Yaml, Box<dyn std::error::Error>> {
     let file_paths: Vec<String> = collections_files.into_iter().chain(workplace_files).collect();
-    let _yaml: yaml_rust2::Yaml = yaml_handler::load(file_paths, "./temp/merged.yaml")?;
+    let merged: yaml_rust2::Yaml = yaml_handler::load(file_paths.clone(), vault)?;
 
-    let file_data = files_and_dirs::read_file("./temp/merged.yaml")?;
-    let json: JsonValue = yaml_handler::yaml_to_json(&file_data).unwrap();
-    //println!("JSON: {:#?}", json);
-    //let tera_context = Context::from_value(json)?;
+    let file_data = yaml_handler::yaml_to_string_pretty(&merged)?;
+    let mut secrets = vault.secrets(&YamlValue::Null);
+    secrets.add_named(&serde_yaml::from_str(&file_data).unwrap_or_default());
+    dump_intermediate(dump_dir, "merged.yaml", &file_data, &secrets)?;
+    let json: JsonValue = yaml_handler::yaml_to_json(&file_data)?;
     let tera_context = Context::from_serialize(json)?;
-    //println!("tera_context: {:#?}", tera_context);
-    //println!("file_data: {:#?}", file_data);
-    let _r = process_template(&file_data, &tera_context)?;
-    let _o = files_and_dirs::write_file("./temp/final.yaml", &_r)?;
+    let _r = match render_template(&file_data, &tera_context) {
+        Ok(rendered) => rendered,
+        Err(e) => return Err(locate_template_error(&file_paths, vault, &tera_context, e)),
+    };
+    let rendered = serde_yaml::from_str(&_r).unwrap_or_default();
+    secrets.extend(&vault.secrets(&rendered));
+    secrets.add_named(&rendered);
+    dump_intermediate(dump_dir, "final.yaml", &_r, &secrets)?;
 
     let merged_yaml = yaml_handler::load_yaml(&_r)?;
 
@@ -129,8 +199,21 @@ pub fn process_configuration_files(collections_files: Vec<String>, workplace_fil
 }
 
 
+/// Reports a failed render of the merged vars against the vars file that set the failing template.
+fn locate_template_error(file_paths: &[String], vault: &Vault, context: &Context, error: tera::Error) -> Box<dyn Error> {
+    let located = Provenance::from_files(file_paths, vault).ok().and_then(|p| p.failing_template(context));
+    let message = match located {
+        Some((path, origin, reason)) => format!("{}: rendering {}: {}", origin, path, reason),
+        None => format!("Rendering the merged vars: {}", error_chain(&error)),
+    };
+    Box::new(std::io::Error::new(std::io::ErrorKind::InvalidData, message))
+}
+
+
 /// Loads a YAML file from the specified file path and returns the parsed YAML value.
-pub fn process_playbook(file_path: &str, current_config_yaml: yaml_rust2::Yaml) -> Result<String, Box<dyn Error>> {
+///
+/// `dump_dir` receives `playbook_s1.yaml` with the `secrets` masked.
+pub fn process_playbook(file_path: &str, current_config_yaml: yaml_rust2::Yaml, dump_dir: Option<&str>, secrets: &Secrets) -> Result<String, Box<dyn Error>> {
     let file_data = files_and_dirs::read_file(file_path)?;
     let mut merged_yaml = current_config_yaml.clone();
 
@@ -142,9 +225,8 @@ pub fn process_playbook(file_path: &str, current_config_yaml: yaml_rust2::Yaml)
             print_error!("{}", e);
         }
     }
-    let _playbook_yaml_file_out = files_and_dirs::write_file("./temp/playbook_s1.yaml", &yaml_handler::yaml_to_string(&merged_yaml)?)?;
-    
-    let template = files_and_dirs::read_file("./temp/playbook_s1.yaml")?;
+    let template = yaml_handler::yaml_to_string(&merged_yaml)?;
+    dump_intermediate(dump_dir, "playbook_s1.yaml", &template, secrets)?;
 
     Ok(template)
 }
@@ -158,7 +240,7 @@ pub fn extract_object_path_from_handlebars(handlebars: &str) -> String {
     }
     // extract the path from inside the handlebars, with a regex
     let re = regex::Regex::new(r"\{\{(.*)\}\}").unwrap();
-    let caps = re.captures(&handlebars).unwrap();
+    let caps = re.captures(handlebars).unwrap();
     path = caps.get(1).unwrap().as_str().to_string();
     path = path.trim().to_string();
     path
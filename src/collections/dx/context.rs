@@ -0,0 +1,50 @@
+// state of one playbook run, handed to every task as it executes
+
+use crate::collections::dx::mock::MockRegistry;
+use crate::collections::dx::observer::Observers;
+use crate::collections::{log, Verbose};
+use crate::collections::dx::{config_proc, Facts, PlaybookCommandOutput, RunOptions, Settings};
+
+/// What a task can read and change while it executes: the facts, the playbook settings,
+/// the engine options, the mocks answering tasks, the observers and the log filter of the run.
+///
+/// Each run owns its context, so several playbooks can run in one process without sharing facts.
+#[derive(Debug, Default)]
+pub struct RunContext {
+    pub facts: Facts,
+    pub settings: Settings,
+    /// `-q` or the number of `-v`
+    pub verbose: Verbose,
+    /// show the before/after difference of every changed resource
+    pub diff: bool,
+    pub options: RunOptions,
+    pub mocks: MockRegistry,
+    /// collections of the workspace as (`namespace.name`, folder), where module tasks are found
+    pub collections: Vec<(String, String)>,
+    /// told about the run as it happens
+    pub observers: Observers,
+    /// the `CHGOPS_LOG` targets shown while the run executes, its messages mask the secrets of the facts
+    pub log_filter: log::LogFilter,
+}
+
+impl RunContext {
+    pub fn new(facts: Facts) -> RunContext {
+        RunContext {
+            facts,
+            ..Default::default()
+        }
+    }
+
+    /// Renders a template string with the current facts; `as_json` and `as_yaml` mask the secrets.
+    pub fn render(&self, template: &str) -> Result<String, tera::Error> {
+        config_proc::process_template(template, &self.facts.context, &self.facts.secrets.read().unwrap())
+    }
+
+    /// Makes a task output available to the next tasks as `register`: its data, or its stdout.
+    pub fn register(&mut self, register: &str, output: &PlaybookCommandOutput) {
+        match &output.data {
+            Some(data) => self.facts.context.insert(register, data),
+            None => self.facts.context.insert(register, &output.stdout),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// reading answers from the terminal for the interactive tasks
+
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use lazy_static::lazy_static;
+
+lazy_static! {
+    /// The lines of stdin, read by a single thread started on the first read. A read that times out
+    /// leaves the pending line in the channel for the next one instead of a second thread racing for stdin.
+    static ref LINES: Mutex<Receiver<String>> = {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            loop {
+                let mut line = String::new();
+                match stdin.read_line(&mut line) {
+                    Ok(read) if read > 0 => {
+                        if sender.send(line.trim_end_matches(['\r', '\n']).to_string()).is_err() {
+                            break;
+                        }
+                    },
+                    _ => break,
+                }
+            }
+        });
+        Mutex::new(receiver)
+    };
+}
+
+/// Reads a line from stdin, returning `None` when the timeout elapses first or stdin is closed.
+pub fn read_line(timeout: Option<Duration>) -> Option<String> {
+    let lines = LINES.lock().unwrap();
+    match timeout {
+        Some(timeout) => lines.recv_timeout(timeout).ok(),
+        None => lines.recv().ok(),
+    }
+}
+
+/// Reads a line without echoing it to the terminal.
+pub fn read_secret(prompt: &str) -> Option<String> {
+    rpassword::prompt_password(prompt).ok()
+}
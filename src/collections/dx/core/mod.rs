@@ -1,3 +1,4 @@
 pub mod shell;
 pub mod tasks;
-pub mod filters;
\ No newline at end of file
+pub mod filters;
+pub mod input;
\ No newline at end of file
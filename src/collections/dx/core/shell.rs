@@ -3,12 +3,32 @@
 pub const BASH_COMMAND: &str = "sh";
 pub const CMD_COMMAND: &str = "cmd";
 
-use std::process::{Command, Output};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use crate::collections::dx::observer::OutputStream;
+
 pub trait ShellTrait {
     fn execute(&self) -> Result<Output, std::io::Error>;
+    /// Executes the command, handing every line of its output to `on_chunk` as it is written.
+    fn execute_streaming(&self, on_chunk: &mut dyn FnMut(OutputStream, &str)) -> Result<Output, std::io::Error>;
     fn display(&self, output: Output);
 }
 
+// sends the lines of a child pipe to the thread running the command
+fn forward_lines<R: Read + Send + 'static>(pipe: R, stream: OutputStream, sender: mpsc::Sender<(OutputStream, Vec<u8>)>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut line = vec![];
+        while reader.read_until(b'\n', &mut line).unwrap_or(0) > 0 {
+            if sender.send((stream, std::mem::take(&mut line))).is_err() {
+                break;
+            }
+        }
+    })
+}
+
 pub struct Shell {
     shell: String,
     command: String,
@@ -31,6 +51,43 @@ impl ShellTrait for Shell {
             .output()
     }
 
+    fn execute_streaming(&self, on_chunk: &mut dyn FnMut(OutputStream, &str)) -> Result<Output, std::io::Error> {
+        let mut child = Command::new(&self.shell)
+            .arg("-c")
+            .arg(&self.command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let (sender, receiver) = mpsc::channel();
+        let mut readers = vec![];
+        if let Some(stdout) = child.stdout.take() {
+            readers.push(forward_lines(stdout, OutputStream::Stdout, sender.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            readers.push(forward_lines(stderr, OutputStream::Stderr, sender.clone()));
+        }
+        drop(sender);
+
+        let (mut stdout, mut stderr) = (vec![], vec![]);
+        for (stream, line) in receiver {
+            on_chunk(stream, &String::from_utf8_lossy(&line));
+            match stream {
+                OutputStream::Stdout => stdout.extend(line),
+                OutputStream::Stderr => stderr.extend(line),
+            }
+        }
+        for reader in readers {
+            let _ = reader.join();
+        }
+
+        Ok(Output {
+            status: child.wait()?,
+            stdout,
+            stderr,
+        })
+    }
+
     fn display(&self, output: Output) {
         let stdout = String::from_utf8_lossy(&output.stdout);
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -55,6 +112,10 @@ impl ShellTrait for Bash {
         self.shell.execute()
     }
 
+    fn execute_streaming(&self, on_chunk: &mut dyn FnMut(OutputStream, &str)) -> Result<Output, std::io::Error> {
+        self.shell.execute_streaming(on_chunk)
+    }
+
     fn display(&self, output: Output) {
         self.shell.display(output)
     }
@@ -78,6 +139,10 @@ impl ShellTrait for WinCmd {
         self.shell.execute()
     }
 
+    fn execute_streaming(&self, on_chunk: &mut dyn FnMut(OutputStream, &str)) -> Result<Output, std::io::Error> {
+        self.shell.execute_streaming(on_chunk)
+    }
+
     fn display(&self, output: Output) {
         self.shell.display(output)
     }
@@ -1,49 +1,76 @@
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use serde_yaml::Value as YamlValue;
 use crate::collections::dx::config_proc;
 use crate::collections::dx::core::shell::Bash;
 use crate::collections::dx::core::shell::WinCmd;
 use crate::collections::dx::core::shell::ShellTrait;
-use crate::collections::dx::{PlaybookCommand, PlaybookCommandTrait, PlaybookCommandOutput};
-use crate::collections::dx::FACTS;
+use crate::collections::dx::{PlaybookCommand, PlaybookCommandTrait, PlaybookCommandOutput, PlaybookTaskInfo};
+use crate::collections::dx::schema::task_schema;
+use crate::collections::dx::task_registry::{parse_command, TaskDefinition, TaskField, TaskRegistry};
+use crate::collections::dx::core::input;
+use crate::collections::dx::context::RunContext;
+use crate::collections::Verbose;
+use crate::collections::dx::vault::Secrets;
+use crate::collections::dx::observer::{MessageLevel, RunObserver};
+use std::time::Duration;
 
-use crate::{print_error, print_warning, print_info, print_success, print_banner_yellow, print_banner_green, print_banner_red, print_banner_blue};
+use crate::print_error;
+use colored::Colorize;
 
-// register task execution here:
-#[derive(Debug, Deserialize, Serialize)]
-pub enum CoreTasks {
-    #[serde(rename = "dx.core.bash")]
-    BashCommandTask(BashCommandTask),
-
-    #[serde(rename = "dx.core.wincmd")]
-    WinCmdCommandTask(WinCmdCommandTask),
 
-    #[serde(rename = "dx.core.print")]
-    PrintCommandTask(PrintCommandTask),
-}
-
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
 pub struct BashCommandVars {
+    #[schemars(with = "serde_json::Value")]
     pub resource: YamlValue,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
 pub struct WinCmdCommandVars {
+    #[schemars(with = "serde_json::Value")]
     pub resource: YamlValue,
 }
 
-#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
 pub struct PrintCommandVars {
+    #[schemars(with = "serde_json::Value")]
     pub resource: YamlValue,
 }
 
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct PauseCommandVars {
+    pub message: Option<String>,
+    /// seconds to wait for an answer before applying `on_timeout`
+    pub timeout: Option<u64>,
+    /// "continue" (default) or "abort"
+    pub on_timeout: Option<String>,
+    /// argument read instead of the terminal in non-interactive mode, e.g. CONFIRM=continue
+    pub argument: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct PromptCommandVars {
+    pub question: String,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub secret: bool,
+    /// argument read instead of the terminal in non-interactive mode, defaults to the register name
+    pub argument: Option<String>,
+}
+
 pub type BashCommandTask = PlaybookCommand<String, BashCommandVars>;
 pub type WinCmdCommandTask = PlaybookCommand<String, WinCmdCommandVars>;
 pub type PrintCommandTask = PlaybookCommand<Option<String>, PrintCommandVars>;
+pub type PauseCommandTask = PlaybookCommand<Option<String>, PauseCommandVars>;
+pub type PromptCommandTask = PlaybookCommand<Option<String>, PromptCommandVars>;
+
+fn render_text(context: &RunContext, text: &str) -> String {
+    context.render(text).unwrap_or(text.to_string())
+}
 
 
 impl PlaybookCommandTrait for BashCommandTask {
-    fn execute(&mut self) {
+    fn execute(&mut self, context: &mut RunContext) {
         self.output = PlaybookCommandOutput::new();
         self.output.set_start_time();
 
@@ -57,7 +84,9 @@ impl PlaybookCommandTrait for BashCommandTask {
         }
                 
         let bash = Bash::new(&self.command);
-        let output = bash.execute().expect("Failed to execute command");
+        let info = self.info(&self.task_type);
+        let output = bash.execute_streaming(&mut |stream, chunk| context.observers.task_output(&info, stream, chunk))
+            .expect("Failed to execute command");
 
 
         self.output.stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -72,34 +101,8 @@ impl PlaybookCommandTrait for BashCommandTask {
         self.output.set_end_time();
     }
 
-    fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
-        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***", 
-            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
-            self.output.status,
-            self.output.success,
-            self.output.failed,
-            self.output.skipped,
-            self.output.changed
-        );
-        if verbose.len() >= 1 {
-            print_info!("Task details: {:?}", self);
-            //print_info!("Command: {}", command_str);
-        }
-        if verbose.len() >= 2 {
-            print_banner_yellow!("=== Output Obj ===");
-            print_info!("{:?}", self.output);
-        }
-        else {
-            if self.output.stdout != "" {
-                print_banner_green!("=== Output ===");
-                print_success!("{}", self.output.stdout);
-            }
-            if self.output.stderr != "" {
-                print_banner_red!("=== Errors ===");
-                print_error!("{}", self.output.stderr);
-            }
-        }
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
@@ -108,7 +111,7 @@ impl PlaybookCommandTrait for BashCommandTask {
 }
 
 impl PlaybookCommandTrait for WinCmdCommandTask {
-    fn execute(&mut self) {
+    fn execute(&mut self, context: &mut RunContext) {
         self.output = PlaybookCommandOutput::new();
         self.output.set_start_time();
 
@@ -123,7 +126,9 @@ impl PlaybookCommandTrait for WinCmdCommandTask {
         }
 
         let wincmd = WinCmd::new(&self.command);
-        let output = wincmd.execute().expect("Failed to execute command");
+        let info = self.info(&self.task_type);
+        let output = wincmd.execute_streaming(&mut |stream, chunk| context.observers.task_output(&info, stream, chunk))
+            .expect("Failed to execute command");
 
 
         self.output.stdout = String::from_utf8_lossy(&output.stdout).to_string();
@@ -139,34 +144,8 @@ impl PlaybookCommandTrait for WinCmdCommandTask {
 
     }
     
-    fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
-        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***", 
-            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
-            self.output.status,
-            self.output.success,
-            self.output.failed,
-            self.output.skipped,
-            self.output.changed
-        );
-        if verbose.len() >= 1 {
-            print_info!("Task details: {:?}", self);
-            //print_info!("Command: {}", command_str);
-        }
-        if verbose.len() >= 2 {
-            print_banner_yellow!("=== Output Obj ===");
-            print_info!("{:?}", self.output);
-        }
-        else {
-            if self.output.stdout != "" {
-                print_banner_green!("=== Output ===");
-                print_success!("{}", self.output.stdout);
-            }
-            if self.output.stderr != "" {
-                print_banner_red!("=== Errors ===");
-                print_error!("{}", self.output.stderr);
-            }
-        }
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
@@ -175,7 +154,7 @@ impl PlaybookCommandTrait for WinCmdCommandTask {
 }
 
 impl PlaybookCommandTrait for PrintCommandTask {
-    fn execute(&mut self) {
+    fn execute(&mut self, context: &mut RunContext) {
         self.output = PlaybookCommandOutput::new();
         self.output.set_start_time();
 
@@ -203,11 +182,7 @@ impl PlaybookCommandTrait for PrintCommandTask {
         let data_str;
         let template = serde_yaml::to_string(&vars.resource).unwrap();
 
-        let processed_temp: String;
-        {
-            let facts = FACTS.read().unwrap();
-            processed_temp = config_proc::process_template(&template, &facts.context).unwrap();
-        }
+        let processed_temp: String = context.render(&template).unwrap();
 
         //println!("processed_temp: {:?}", processed_temp);
 
@@ -229,10 +204,9 @@ impl PlaybookCommandTrait for PrintCommandTask {
 
                 let resource_str = resource.as_str().unwrap();
                 if resource_str.contains("{{") && resource_str.contains("}}") {
-                    let obj_name:String = config_proc::extract_object_path_from_handlebars(&resource_str);
+                    let obj_name:String = config_proc::extract_object_path_from_handlebars(resource_str);
                     {
-                        let facts = FACTS.read().unwrap();
-                        let values = facts.context.get(&obj_name).unwrap();
+                        let values = context.facts.context.get(&obj_name).unwrap();
                         //println!("values: {:?}", values);
                         data_str = serde_yaml::to_string(&values).unwrap();
                         self.output.data = serde_yaml::from_str(&data_str).unwrap();
@@ -242,8 +216,7 @@ impl PlaybookCommandTrait for PrintCommandTask {
                     let obj_name:String = config_proc::extract_object_path_from_handlebars(&template);
                     //println!("obj_name: {:?}", obj_name);
                     {
-                        let facts = FACTS.read().unwrap();
-                        let values = facts.context.get(&obj_name).unwrap();
+                        let values = context.facts.context.get(&obj_name).unwrap();
                         //println!("values: {:?}", values);
                         data_str = serde_yaml::to_string(&values).unwrap();
                         self.output.data = serde_yaml::from_str(&data_str).unwrap();
@@ -263,24 +236,13 @@ impl PlaybookCommandTrait for PrintCommandTask {
         }
 
         // Commnad execution zone
-        // print_error, print_warning, print_info, print_success
-        // print_banner_yellow, print_banner_green, print_banner_red
-        println!("name: {}", name);
-        if command == "print" {
-            println!("{}", data_str);
-        } else if command == "debug" {
-            print_warning!("{:?}", data_str);
-        } else if command == "error" {
-            print_error!("{}", data_str);
-        } else if command == "warning" {
-            print_warning!("{}", data_str);
-        } else if command == "info" {
-            print_banner_yellow!("{}", data_str);
-        } else if command == "success" {
-            print_banner_green!("{}", data_str);
-        } else {
-            println!("{}", data_str);
-        }
+        // the sinks show the message: banners on the console, an event in json
+        let info = PlaybookTaskInfo {
+            name,
+            ..self.info(&self.task_type)
+        };
+        let shown = context.facts.secrets.read().unwrap().mask(&data_str);
+        context.observers.task_message(&info, MessageLevel::from_command(&command), &shown);
 
 
         self.output.stdout = data_str;
@@ -302,45 +264,372 @@ impl PlaybookCommandTrait for PrintCommandTask {
         self.output.skipped = 0;
         self.output.changed = 0;
 
-        if register != "" {
+        if !register.is_empty() {
             // add to the central fact store this reference
             {
-                let mut facts = FACTS.write().unwrap();
-                facts.context.insert(register, &self.output.data);
+                let before = match context.facts.context.get(&register) {
+                    Some(value) => serde_yaml::to_string(value).unwrap_or_default(),
+                    None => "".to_string(),
+                };
+                let after = serde_yaml::to_string(&self.output.data).unwrap_or_default();
+                self.output.set_diff(&before, &after);
+                if self.output.diff.is_some() {
+                    self.output.changed = 1;
+                }
+
+                context.facts.context.insert(register, &self.output.data);
             }
         }
 
         self.output.set_end_time();
     }
 
-    fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
-        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***", 
-            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
-            self.output.status,
-            self.output.success,
-            self.output.failed,
-            self.output.skipped,
-            self.output.changed
-        );
-        if verbose.len() >= 1 {
-            print_info!("Task details: {:?}", self);
-            //print_info!("Command: {}", command_str);
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.output.clone()
+    }
+}
+
+
+impl PlaybookCommandTrait for PauseCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
         }
-        if verbose.len() >= 2 {
-            print_banner_yellow!("=== Output Obj ===");
-            print_info!("{:?}", self.output);
+
+        let vars: PauseCommandVars = self.vars.clone();
+        let message = render_text(context, &vars.message.unwrap_or("Paused".to_string()));
+        let on_timeout = vars.on_timeout.unwrap_or("continue".to_string());
+
+        if on_timeout != "continue" && on_timeout != "abort" {
+            self.output.stderr = format!("Invalid on_timeout: {}. Expected: continue or abort", on_timeout);
+            self.output.message = "Failed".to_string();
+            self.output.status = 1;
+            self.output.failed = 1;
+            self.output.set_end_time();
+            return;
         }
-        else {
-            if self.output.stdout != "" {
-                print_banner_green!("=== Output ===");
-                print_success!("{}", self.output.stdout);
+
+        let options = context.options.clone();
+        let decision = if options.non_interactive {
+            let answer = vars.argument.as_ref().and_then(|argument| options.arguments.get(argument));
+            match answer {
+                Some(answer) => answer.to_lowercase(),
+                None => {
+                    self.output.stderr = match &vars.argument {
+                        Some(argument) => format!("No answer for '{}' in non-interactive mode, pass --arguments {}=continue", message, argument),
+                        None => format!("No answer for '{}' in non-interactive mode, set the argument of the pause and pass it with --arguments", message),
+                    };
+                    self.output.message = "Failed".to_string();
+                    self.output.status = 1;
+                    self.output.failed = 1;
+                    self.output.abort = true;
+                    self.output.set_end_time();
+                    return;
+                }
             }
-            if self.output.stderr != "" {
-                print_banner_red!("=== Errors ===");
-                print_error!("{}", self.output.stderr);
+        } else {
+            // stdout belongs to the sinks, the operator is asked on stderr
+            eprintln!("{}", format!("PAUSE: {}", message).yellow());
+            match vars.timeout {
+                Some(seconds) => eprintln!("Press Enter to continue or type 'abort' to stop ({}s, then {}):", seconds, on_timeout),
+                None => eprintln!("Press Enter to continue or type 'abort' to stop:"),
+            }
+            match input::read_line(vars.timeout.map(Duration::from_secs)) {
+                Some(line) if line.trim() == "a" || line.trim() == "abort" => "abort".to_string(),
+                Some(_) => "continue".to_string(),
+                None => on_timeout.clone(),
+            }
+        };
+
+        self.output.stdout = format!("{} -> {}", message, decision);
+        self.output.status = 1;
+        if decision == "abort" {
+            self.output.message = "Aborted".to_string();
+            self.output.failed = 1;
+            self.output.abort = true;
+        } else {
+            self.output.message = "Continued".to_string();
+            self.output.success = 1;
+        }
+
+        self.output.set_end_time();
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.output.clone()
+    }
+}
+
+impl PlaybookCommandTrait for PromptCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
+        }
+
+        let vars: PromptCommandVars = self.vars.clone();
+        let register = self.register.clone().unwrap_or("".to_string());
+        let question = render_text(context, &vars.question);
+        let argument = vars.argument.clone().unwrap_or(register.clone());
+
+        let options = context.options.clone();
+        let answer = if options.non_interactive {
+            options.arguments.get(&argument).cloned().or(vars.default.clone())
+        } else {
+            let prompt = match &vars.default {
+                Some(default) if !vars.secret => format!("{} [{}]: ", question, default),
+                _ => format!("{}: ", question),
+            };
+            let line = if vars.secret {
+                input::read_secret(&prompt)
+            } else {
+                eprintln!("{}", prompt.yellow());
+                input::read_line(None)
+            };
+            match line {
+                Some(line) if !line.is_empty() => Some(line),
+                _ => vars.default.clone(),
+            }
+        };
+
+        let answer = match answer {
+            Some(answer) => answer,
+            None => {
+                self.output.stderr = if options.non_interactive {
+                    format!("No answer for '{}' in non-interactive mode, pass --arguments {}=<value>", question, argument)
+                } else {
+                    format!("No answer for '{}'", question)
+                };
+                self.output.message = "Failed".to_string();
+                self.output.status = 1;
+                self.output.failed = 1;
+                self.output.abort = true;
+                self.output.set_end_time();
+                return;
+            }
+        };
+
+        // the log messages of the run mask the secrets of its facts
+        if vars.secret {
+            context.facts.secrets.write().unwrap().add(&answer);
+        }
+
+        let shown = if vars.secret { "********".to_string() } else { answer.clone() };
+        self.output.stdout = format!("{} {}", question, shown);
+        self.output.data = Some(YamlValue::String(shown));
+        self.output.message = "Success".to_string();
+        self.output.status = 1;
+        self.output.success = 1;
+
+        if !register.is_empty() {
+            context.facts.context.insert(register, &answer);
+        }
+
+        self.output.set_end_time();
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.output.clone()
+    }
+}
+
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct FileCommandVars {
+    pub path: String,
+    /// text written to the file, rendered with the facts; the file is only created when not given
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct TemplateCommandVars {
+    /// tera template rendered with the facts
+    pub src: String,
+    pub dest: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct LineInFileCommandVars {
+    pub path: String,
+    /// line the file must have, or must not have with state absent
+    pub line: Option<String>,
+    /// lines to replace with `line`, or to remove with state absent
+    pub regexp: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
+pub struct SetFactCommandVars {
+    /// facts to set, their values rendered with the facts
+    #[schemars(with = "serde_json::Value")]
+    pub facts: serde_yaml::Mapping,
+}
+
+pub type FileCommandTask = PlaybookCommand<Option<String>, FileCommandVars>;
+pub type TemplateCommandTask = PlaybookCommand<Option<String>, TemplateCommandVars>;
+pub type LineInFileCommandTask = PlaybookCommand<Option<String>, LineInFileCommandVars>;
+pub type SetFactCommandTask = PlaybookCommand<Option<String>, SetFactCommandVars>;
+
+// content of a file, empty when it does not exist
+fn read_text(path: &str) -> Result<String, std::io::Error> {
+    match std::path::Path::new(path).exists() {
+        true => std::fs::read_to_string(path),
+        false => Ok("".to_string()),
+    }
+}
+
+fn write_text(path: &str, text: &str) -> Result<(), std::io::Error> {
+    if let Some(parent) = std::path::Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, text)
+}
+
+fn set_failed(output: &mut PlaybookCommandOutput, message: String) {
+    output.stderr = message;
+    output.message = "Failed".to_string();
+    output.status = 1;
+    output.failed = 1;
+    output.set_end_time();
+}
+
+// a successful output, changed when `before` and `after` differ, with their diff
+fn set_changed(output: &mut PlaybookCommandOutput, before: &str, after: &str) {
+    output.set_diff(before, after);
+    output.message = "Success".to_string();
+    output.status = 1;
+    output.success = 1;
+    output.changed = output.diff.is_some() as i32;
+    output.set_end_time();
+}
+
+impl PlaybookCommandTrait for FileCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
+        }
+
+        let vars: FileCommandVars = self.vars.clone();
+        let path = render_text(context, &vars.path);
+        let state = self.state.clone().unwrap_or("present".to_string());
+        let exists = std::path::Path::new(&path).exists();
+
+        let before = match read_text(&path) {
+            Ok(before) => before,
+            Err(e) => return set_failed(&mut self.output, format!("Reading {}: {}", path, e)),
+        };
+        let written = match (state.as_str(), &vars.content) {
+            ("absent", _) if exists => std::fs::remove_file(&path).map(|_| "".to_string()),
+            ("absent", _) => Ok(before.clone()),
+            (_, Some(content)) => {
+                let after = render_text(context, content);
+                match after == before && exists {
+                    true => Ok(after),
+                    false => write_text(&path, &after).map(|_| after),
+                }
+            },
+            (_, None) if exists => Ok(before.clone()),
+            (_, None) => write_text(&path, "").map(|_| "".to_string()),
+        };
+
+        match written {
+            Ok(after) => {
+                self.output.stdout = format!("{} {}", path, state);
+                set_changed(&mut self.output, &before, &after);
+                // creating or removing an empty file changes no line
+                if self.output.changed == 0 && exists != (state != "absent") {
+                    self.output.changed = 1;
+                }
+            },
+            Err(e) => set_failed(&mut self.output, format!("Writing {}: {}", path, e)),
+        }
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.output.clone()
+    }
+}
+
+impl PlaybookCommandTrait for TemplateCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
+        }
+
+        let vars: TemplateCommandVars = self.vars.clone();
+        let src = render_text(context, &vars.src);
+        let dest = render_text(context, &vars.dest);
+
+        let template = match std::fs::read_to_string(&src) {
+            Ok(template) => template,
+            Err(e) => return set_failed(&mut self.output, format!("Reading template {}: {}", src, e)),
+        };
+        let after = match context.render(&template) {
+            Ok(after) => after,
+            Err(e) => return set_failed(&mut self.output, format!("Rendering template {}: {:?}", src, e)),
+        };
+        let before = match read_text(&dest) {
+            Ok(before) => before,
+            Err(e) => return set_failed(&mut self.output, format!("Reading {}: {}", dest, e)),
+        };
+        if after != before || !std::path::Path::new(&dest).exists() {
+            if let Err(e) = write_text(&dest, &after) {
+                return set_failed(&mut self.output, format!("Writing {}: {}", dest, e));
             }
         }
+
+        self.output.stdout = format!("{} -> {}", src, dest);
+        set_changed(&mut self.output, &before, &after);
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
@@ -348,32 +637,401 @@ impl PlaybookCommandTrait for PrintCommandTask {
     }
 }
 
+/// The lines of `text` with `line` in place of the last line matching `regexp`, or appended when
+/// no line matches and it is missing; with `present` false, without the matching lines.
+pub fn line_in_text(text: &str, line: Option<&str>, regexp: Option<&regex::Regex>, present: bool) -> String {
+    let matches = |candidate: &str| match regexp {
+        Some(regexp) => regexp.is_match(candidate),
+        None => Some(candidate) == line,
+    };
+    let mut lines: Vec<String> = text.lines().map(|l| l.to_string()).collect();
+    if present {
+        let line = line.unwrap_or_default().to_string();
+        match lines.iter().rposition(|l| matches(l)) {
+            Some(index) => lines[index] = line,
+            None if !lines.contains(&line) => lines.push(line),
+            None => {},
+        }
+    } else {
+        lines.retain(|l| !matches(l));
+    }
+    match lines.is_empty() {
+        true => "".to_string(),
+        false => format!("{}\n", lines.join("\n")),
+    }
+}
 
+impl PlaybookCommandTrait for LineInFileCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
+        }
+
+        let vars: LineInFileCommandVars = self.vars.clone();
+        let path = render_text(context, &vars.path);
+        let line = vars.line.as_ref().map(|line| render_text(context, line));
+        let present = self.state.as_deref() != Some("absent");
 
-// register implementation here:
- 
-impl PlaybookCommandTrait for CoreTasks {
-    fn execute(&mut self) {
-        match self {
-            CoreTasks::BashCommandTask(task) => task.execute(),
-            CoreTasks::WinCmdCommandTask(task) => task.execute(),
-            CoreTasks::PrintCommandTask(task) => task.execute(),
+        let regexp = match vars.regexp.as_ref().map(|regexp| regex::Regex::new(regexp)).transpose() {
+            Ok(regexp) => regexp,
+            Err(e) => return set_failed(&mut self.output, format!("Invalid regexp: {}", e)),
+        };
+        if line.is_none() && (present || regexp.is_none()) {
+            return set_failed(&mut self.output, "line is required, or regexp with state absent".to_string());
         }
+        let before = match read_text(&path) {
+            Ok(before) => before,
+            Err(e) => return set_failed(&mut self.output, format!("Reading {}: {}", path, e)),
+        };
+        // a file whose last line has no newline keeps it when nothing changes
+        let mut after = line_in_text(&before, line.as_deref(), regexp.as_ref(), present);
+        if after.trim_end_matches('\n') == before.trim_end_matches('\n') {
+            after = before.clone();
+        }
+        if after != before {
+            if let Err(e) = write_text(&path, &after) {
+                return set_failed(&mut self.output, format!("Writing {}: {}", path, e));
+            }
+        }
+
+        self.output.stdout = path;
+        set_changed(&mut self.output, &before, &after);
     }
 
-    fn display(&self, verbose: Option<String>) {
-        match self {
-            CoreTasks::BashCommandTask(task) => task.display(verbose),
-            CoreTasks::WinCmdCommandTask(task) => task.display(verbose),
-            CoreTasks::PrintCommandTask(task) => task.display(verbose),
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.output.clone()
+    }
+}
+
+impl PlaybookCommandTrait for SetFactCommandTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.output = PlaybookCommandOutput::new();
+        self.output.set_start_time();
+
+        let when = self.when.clone().unwrap_or("true".to_string());
+
+        if when == "false"{
+            self.output.message = "Skipped".to_string();
+            self.output.skipped = 1;
+            self.output.set_end_time();
+            return;
         }
+
+        let template = serde_yaml::to_string(&self.vars.facts).unwrap_or_default();
+        let facts: serde_yaml::Mapping = match context.render(&template).map_err(|e| format!("{:?}", e))
+            .and_then(|rendered| serde_yaml::from_str(&rendered).map_err(|e| e.to_string())) {
+            Ok(facts) => facts,
+            Err(e) => return set_failed(&mut self.output, format!("Rendering facts: {}", e)),
+        };
+
+        // the facts before and after, as yaml, for the diff
+        let mut previous = serde_yaml::Mapping::new();
+        for (key, _) in facts.iter() {
+            let name = key.as_str().unwrap_or_default();
+            let value = context.facts.context.get(name)
+                .and_then(|value| serde_yaml::to_value(value).ok())
+                .unwrap_or(YamlValue::Null);
+            previous.insert(key.clone(), value);
+        }
+        for (key, value) in facts.iter() {
+            context.facts.context.insert(key.as_str().unwrap_or_default(), value);
+        }
+
+        self.output.data = Some(YamlValue::Mapping(facts.clone()));
+        if let Some(register) = self.register.as_ref().filter(|r| !r.is_empty()) {
+            context.register(register, &self.output);
+        }
+        let yaml = |facts: &serde_yaml::Mapping| serde_yaml::to_string(facts).unwrap_or_default().trim_start_matches("---\n").to_string();
+        set_changed(&mut self.output, &yaml(&previous), &yaml(&facts));
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.display_default(verbose, secrets);
     }
 
     fn output(&self) -> PlaybookCommandOutput {
-        match self {
-            CoreTasks::BashCommandTask(task) => task.output(),
-            CoreTasks::WinCmdCommandTask(task) => task.output(),
-            CoreTasks::PrintCommandTask(task) => task.output(),
+        self.output.clone()
+    }
+}
+
+
+// register task execution here:
+
+pub fn register(registry: &mut TaskRegistry) {
+    registry
+        .register(TaskDefinition::new("dx.core.bash", "Runs a command with bash", parse_command::<String, BashCommandVars>)
+            .schema(task_schema::<String, BashCommandVars>)
+            .command(TaskField::required("command", "string", "bash command line"))
+            .var(TaskField::required("resource", "any", "free form data kept with the task")))
+        .register(TaskDefinition::new("dx.core.wincmd", "Runs a command with cmd.exe", parse_command::<String, WinCmdCommandVars>)
+            .schema(task_schema::<String, WinCmdCommandVars>)
+            .command(TaskField::required("command", "string", "cmd.exe command line"))
+            .var(TaskField::required("resource", "any", "free form data kept with the task")))
+        .register(TaskDefinition::new("dx.core.print", "Renders a value with the facts and prints it", parse_command::<Option<String>, PrintCommandVars>)
+            .schema(task_schema::<Option<String>, PrintCommandVars>)
+            .command(TaskField::optional("command", "string", "print (default), debug, error, warning, info or success"))
+            .var(TaskField::required("resource", "any", "value or template to render, registered as the task data"))
+            .states(&["present", "absent"]))
+        .register(TaskDefinition::new("dx.core.pause", "Waits for the operator to continue or abort", parse_command::<Option<String>, PauseCommandVars>)
+            .schema(task_schema::<Option<String>, PauseCommandVars>)
+            .var(TaskField::optional("message", "string", "text shown while paused"))
+            .var(TaskField::optional("timeout", "number", "seconds to wait for an answer before applying on_timeout"))
+            .var(TaskField::optional("on_timeout", "string", "continue (default) or abort"))
+            .var(TaskField::optional("argument", "string", "argument read instead of the terminal in non-interactive mode")))
+        .register(TaskDefinition::new("dx.core.prompt", "Asks the operator for a value and registers it", parse_command::<Option<String>, PromptCommandVars>)
+            .schema(task_schema::<Option<String>, PromptCommandVars>)
+            .var(TaskField::required("question", "string", "text of the question"))
+            .var(TaskField::optional("default", "string", "answer used when none is given"))
+            .var(TaskField::optional("secret", "bool", "hides the answer when true"))
+            .var(TaskField::optional("argument", "string", "argument read instead of the terminal in non-interactive mode, defaults to the register name")))
+        .register(TaskDefinition::new("dx.core.file", "Writes or removes a file", parse_command::<Option<String>, FileCommandVars>)
+            .schema(task_schema::<Option<String>, FileCommandVars>)
+            .var(TaskField::required("path", "string", "file to write or remove"))
+            .var(TaskField::optional("content", "string", "text written to the file, rendered with the facts"))
+            .states(&["present", "absent"]))
+        .register(TaskDefinition::new("dx.core.template", "Renders a template file with the facts into a file", parse_command::<Option<String>, TemplateCommandVars>)
+            .schema(task_schema::<Option<String>, TemplateCommandVars>)
+            .var(TaskField::required("src", "string", "tera template to render"))
+            .var(TaskField::required("dest", "string", "file written with the rendered template")))
+        .register(TaskDefinition::new("dx.core.lineinfile", "Makes sure a line is in a file, or is not", parse_command::<Option<String>, LineInFileCommandVars>)
+            .schema(task_schema::<Option<String>, LineInFileCommandVars>)
+            .var(TaskField::required("path", "string", "file to change, created when missing"))
+            .var(TaskField::optional("line", "string", "line the file must have, rendered with the facts"))
+            .var(TaskField::optional("regexp", "string", "lines to replace with line, or to remove with state absent"))
+            .states(&["present", "absent"]))
+        .register(TaskDefinition::new("dx.core.set_fact", "Sets facts for the next tasks", parse_command::<Option<String>, SetFactCommandVars>)
+            .schema(task_schema::<Option<String>, SetFactCommandVars>)
+            .var(TaskField::required("facts", "object", "facts to set, their values rendered with the facts")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn non_interactive(arguments: &[(&str, &str)]) -> RunContext {
+        let mut context = RunContext::default();
+        context.options.non_interactive = true;
+        context.options.arguments = arguments.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        context
+    }
+
+    fn pause(argument: Option<&str>, on_timeout: Option<&str>) -> PauseCommandTask {
+        PauseCommandTask {
+            vars: PauseCommandVars {
+                message: Some("Check the portal".to_string()),
+                argument: argument.map(|a| a.to_string()),
+                on_timeout: on_timeout.map(|o| o.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
         }
     }
-}
\ No newline at end of file
+
+    fn prompt(default: Option<&str>, secret: bool) -> PromptCommandTask {
+        PromptCommandTask {
+            vars: PromptCommandVars {
+                question: "Approver?".to_string(),
+                default: default.map(|d| d.to_string()),
+                secret,
+                ..Default::default()
+            },
+            register: Some("approver".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_pause_takes_its_decision_from_the_arguments() {
+        let mut task = pause(Some("CONFIRM"), None);
+
+        task.execute(&mut non_interactive(&[("CONFIRM", "continue")]));
+        assert_eq!(task.output.message, "Continued");
+        assert_eq!(task.output.success, 1);
+        assert!(!task.output.abort);
+
+        task.execute(&mut non_interactive(&[("CONFIRM", "ABORT")]));
+        assert_eq!(task.output.message, "Aborted");
+        assert_eq!(task.output.failed, 1);
+        assert!(task.output.abort);
+    }
+
+    #[test]
+    fn a_pause_without_an_answer_fails_and_stops_the_playbook() {
+        let mut task = pause(Some("CONFIRM"), None);
+        task.execute(&mut non_interactive(&[]));
+        assert_eq!(task.output.failed, 1);
+        assert!(task.output.abort);
+        assert!(task.output.stderr.contains("--arguments CONFIRM=continue"));
+
+        let mut task = pause(None, Some("continue"));
+        task.execute(&mut non_interactive(&[]));
+        assert!(task.output.abort);
+    }
+
+    #[test]
+    fn a_pause_rejects_an_unknown_on_timeout() {
+        let mut task = pause(Some("CONFIRM"), Some("retry"));
+        task.execute(&mut non_interactive(&[("CONFIRM", "continue")]));
+        assert_eq!(task.output.failed, 1);
+        assert!(task.output.stderr.contains("Invalid on_timeout: retry"));
+    }
+
+    #[test]
+    fn a_prompt_registers_the_argument_or_its_default() {
+        let mut context = non_interactive(&[("approver", "bob")]);
+        let mut task = prompt(Some("alice"), false);
+        task.execute(&mut context);
+        assert_eq!(task.output.success, 1);
+        assert_eq!(context.render("{{ approver }}").unwrap(), "bob");
+
+        let mut context = non_interactive(&[]);
+        task.execute(&mut context);
+        assert_eq!(context.render("{{ approver }}").unwrap(), "alice");
+    }
+
+    #[test]
+    fn a_prompt_without_an_answer_fails_and_stops_the_playbook() {
+        let mut task = prompt(None, false);
+        task.execute(&mut non_interactive(&[]));
+        assert_eq!(task.output.failed, 1);
+        assert!(task.output.abort);
+        assert!(task.output.stderr.contains("--arguments approver=<value>"));
+    }
+
+    #[test]
+    fn a_secret_answer_is_registered_but_never_shown() {
+        let mut context = non_interactive(&[("approver", "s3cr3t-value")]);
+        let mut task = prompt(None, true);
+        task.execute(&mut context);
+
+        assert_eq!(context.render("{{ approver }}").unwrap(), "s3cr3t-value");
+        assert!(!task.output.stdout.contains("s3cr3t-value"));
+        assert_eq!(task.output.data, Some(YamlValue::String("********".to_string())));
+        assert_eq!(context.facts.secrets.read().unwrap().mask("key=s3cr3t-value"), "key=********");
+    }
+
+    fn folder(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("chgops-tasks-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn context_with(facts: &str) -> RunContext {
+        let mut context = RunContext::default();
+        let facts: serde_yaml::Value = serde_yaml::from_str(facts).unwrap();
+        context.facts.context = tera::Context::from_serialize(&facts).unwrap();
+        context
+    }
+
+    #[test]
+    fn a_file_is_written_removed_and_diffed() {
+        let path = folder("file").join("app/settings.conf").to_string_lossy().to_string();
+        let mut context = context_with("vars: { port: 8080 }");
+        let mut task = FileCommandTask {
+            vars: FileCommandVars { path: path.clone(), content: Some("port={{ vars.port }}\n".to_string()) },
+            ..Default::default()
+        };
+
+        task.execute(&mut context);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "port=8080\n");
+        assert_eq!(task.output.changed, 1);
+        assert!(task.output.diff.as_ref().unwrap().unified.contains("+port=8080"));
+
+        task.execute(&mut context);
+        assert_eq!(task.output.changed, 0);
+        assert!(task.output.diff.is_none());
+
+        task.state = Some("absent".to_string());
+        task.execute(&mut context);
+        assert!(!std::path::Path::new(&path).exists());
+        assert_eq!(task.output.changed, 1);
+        assert!(task.output.diff.as_ref().unwrap().unified.contains("-port=8080"));
+    }
+
+    #[test]
+    fn a_template_is_rendered_with_the_facts_into_its_dest() {
+        let folder = folder("template");
+        let src = folder.join("motd.j2");
+        let dest = folder.join("motd").to_string_lossy().to_string();
+        std::fs::write(&src, "Welcome to {{ vars.env }}\n").unwrap();
+        std::fs::write(&dest, "Welcome to dev\n").unwrap();
+        let mut task = TemplateCommandTask {
+            vars: TemplateCommandVars { src: src.to_string_lossy().to_string(), dest: dest.clone() },
+            ..Default::default()
+        };
+
+        task.execute(&mut context_with("vars: { env: prod }"));
+
+        assert_eq!(std::fs::read_to_string(&dest).unwrap(), "Welcome to prod\n");
+        assert_eq!(task.output.changed, 1);
+        let diff = task.output.diff.as_ref().unwrap();
+        assert_eq!((diff.before.as_str(), diff.after.as_str()), ("Welcome to dev\n", "Welcome to prod\n"));
+    }
+
+    #[test]
+    fn a_line_replaces_the_last_match_or_is_appended_once() {
+        let port = regex::Regex::new("^port=").unwrap();
+        assert_eq!(line_in_text("host=a\nport=1\n", Some("port=2"), Some(&port), true), "host=a\nport=2\n");
+        assert_eq!(line_in_text("host=a\n", Some("port=2"), Some(&port), true), "host=a\nport=2\n");
+        assert_eq!(line_in_text("host=a\nport=2\n", Some("port=2"), None, true), "host=a\nport=2\n");
+        assert_eq!(line_in_text("host=a\nport=1\nport=3\n", None, Some(&port), false), "host=a\n");
+        assert_eq!(line_in_text("", Some("host=a"), None, true), "host=a\n");
+    }
+
+    #[test]
+    fn lineinfile_changes_the_file_only_when_the_line_differs() {
+        let path = folder("lineinfile").join("hosts").to_string_lossy().to_string();
+        std::fs::write(&path, "127.0.0.1 localhost").unwrap();
+        let mut task = LineInFileCommandTask {
+            vars: LineInFileCommandVars { path: path.clone(), line: Some("10.0.0.1 {{ host }}".to_string()), regexp: None },
+            ..Default::default()
+        };
+        let mut context = context_with("host: db");
+
+        task.execute(&mut context);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "127.0.0.1 localhost\n10.0.0.1 db\n");
+        assert_eq!(task.output.changed, 1);
+        assert!(task.output.diff.as_ref().unwrap().unified.contains("+10.0.0.1 db"));
+
+        task.execute(&mut context);
+        assert_eq!(task.output.changed, 0);
+
+        task.vars.regexp = Some("[".to_string());
+        task.execute(&mut context);
+        assert_eq!(task.output.failed, 1);
+    }
+
+    #[test]
+    fn set_fact_sets_rendered_facts_and_diffs_their_values() {
+        let mut context = context_with("vars: { region: westeurope }\nlocation: northeurope");
+        let facts: serde_yaml::Mapping = serde_yaml::from_str("location: \"{{ vars.region }}\"\nowner: ops").unwrap();
+        let mut task = SetFactCommandTask {
+            vars: SetFactCommandVars { facts },
+            ..Default::default()
+        };
+
+        task.execute(&mut context);
+
+        assert_eq!(context.render("{{ location }} {{ owner }}").unwrap(), "westeurope ops");
+        assert_eq!(task.output.changed, 1);
+        let diff = task.output.diff.as_ref().unwrap();
+        assert_eq!(diff.before, "location: northeurope\nowner: ~\n");
+        assert_eq!(diff.after, "location: westeurope\nowner: ops\n");
+
+        task.execute(&mut context);
+        assert_eq!(task.output.changed, 0);
+    }
+}
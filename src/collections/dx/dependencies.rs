@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use semver::{Version, VersionReq};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use crate::collections::dx::{collection, config_proc, files_and_dirs, history, package, registry};
+use crate::{eprint_info, log_debug};
+
+/// Lock file written next to the workspace configuration.
+pub const LOCK_FILE: &str = "chgops.lock";
+
+/// One entry of `dependencies.collections`, e.g. `{ name: dx.azure, version: 1.0.0 }`.
+///
+/// A collection is taken from `path` when given, cloned from `git` at the `version` tag, or
+/// found under `./collections`; when the local copy is missing or has another version it is
+/// downloaded from `registry` (or `dependencies.source`).
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct CollectionDependency {
+    pub name: String,
+    pub version: Option<String>,
+    pub path: Option<String>,
+    pub git: Option<String>,
+    pub registry: Option<String>,
+}
+
+impl CollectionDependency {
+    /// Splits `dx.azure` into `("dx", "azure")`.
+    pub fn namespace_and_name(&self) -> Result<(String, String), Box<dyn Error>> {
+        match self.name.split_once('.') {
+            Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() && !name.contains('.') => {
+                Ok((namespace.to_string(), name.to_string()))
+            },
+            _ => Err(format!("Invalid collection name: {}. Expected format: namespace.collection", self.name).into()),
+        }
+    }
+
+    /// The declared `version` as a requirement, an exact version only matching itself; `None` accepts any version.
+    pub fn requirement(&self) -> Result<Option<VersionReq>, Box<dyn Error>> {
+        let Some(requirement) = self.version.as_deref() else {
+            return Ok(None);
+        };
+        let requirement = match Version::parse(requirement) {
+            Ok(exact) => VersionReq::parse(&format!("={}", exact))?,
+            Err(_) => VersionReq::parse(requirement)
+                .map_err(|e| format!("Invalid version requirement for {}: {}: {}", self.name, requirement, e))?,
+        };
+        Ok(Some(requirement))
+    }
+
+    pub fn matches(&self, version: Option<&str>) -> Result<bool, Box<dyn Error>> {
+        let Some(requirement) = self.requirement()? else {
+            return Ok(true);
+        };
+        let Some(version) = version else {
+            return Ok(false);
+        };
+        let version = Version::parse(version).map_err(|e| format!("Collection {} has an invalid version {}: {}", self.name, version, e))?;
+        Ok(requirement.matches(&version))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Dependencies {
+    pub source: Option<String>,
+    #[serde(default)]
+    pub collections: Vec<CollectionDependency>,
+}
+
+/// A collection as resolved for a workspace.
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
+pub struct LockedCollection {
+    pub name: String,
+    pub version: Option<String>,
+    pub source: String,
+    pub path: String,
+}
+
+/// `chgops.lock`: the resolved collections, their paths relative to the workspace.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct LockFile {
+    pub collections: Vec<LockedCollection>,
+}
+
+impl LockFile {
+    /// Whether every declared collection is locked at a matching version from the declared source, and every locked folder is there.
+    pub fn satisfies(&self, dependencies: &Dependencies) -> bool {
+        let declared = dependencies.collections.iter().all(|dependency| {
+            self.collections.iter().find(|locked| locked.name == dependency.name).is_some_and(|locked| {
+                dependency.matches(locked.version.as_deref()).unwrap_or(false)
+                    && dependency.path.as_ref().is_none_or(|path| locked.source == format!("path:{}", path))
+                    && dependency.git.as_ref().is_none_or(|url| locked.source.starts_with(&format!("git:{}@", url)))
+            })
+        });
+        declared && self.collections.iter().all(|locked| Path::new(&locked.path).is_dir())
+    }
+}
+
+/// Reads `dependencies` from the workspace configuration, `None` when there is no such section.
+pub fn read_dependencies(workspace_path: &str) -> Result<Option<Dependencies>, Box<dyn Error>> {
+    let Some(config_path) = package::workspace_config_path(workspace_path) else {
+        return Ok(None);
+    };
+    let config = config_proc::read_yaml(&config_path)?;
+    match config.get("dependencies") {
+        Some(dependencies) => Ok(Some(serde_yaml::from_value(dependencies.clone())
+            .map_err(|e| format!("Reading dependencies in {}: {}", config_path, e))?)),
+        None => Ok(None),
+    }
+}
+
+/// Version declared by a collection folder: `collection.yaml` `version`, else `common_vars.version` in `vars.yaml`.
+pub fn collection_version(collection_folder: &Path) -> Option<String> {
+    if let Ok(Some(manifest)) = collection::read_manifest(collection_folder) {
+        return Some(manifest.version);
+    }
+    let vars = collection_folder.join("vars.yaml");
+    let yaml = config_proc::read_yaml(vars.to_str()?).ok()?;
+    let version = yaml.get("common_vars")?.get("version")?;
+    match version {
+        serde_yaml::Value::String(v) => Some(v.clone()),
+        other => serde_yaml::to_string(other).ok().map(|v| v.trim_start_matches("---").trim().to_string()),
+    }
+}
+
+fn check_conflicts(dependencies: &[CollectionDependency]) -> Result<(), Box<dyn Error>> {
+    let mut seen: HashMap<&str, &CollectionDependency> = HashMap::new();
+    for dependency in dependencies.iter() {
+        if let Some(previous) = seen.get(dependency.name.as_str()) {
+            if previous.version != dependency.version || previous.path != dependency.path || previous.git != dependency.git {
+                return Err(format!(
+                    "Conflicting declarations for collection {}: {} and {}",
+                    dependency.name,
+                    previous.version.clone().unwrap_or("*".to_string()),
+                    dependency.version.clone().unwrap_or("*".to_string())
+                ).into());
+            }
+        }
+        seen.insert(dependency.name.as_str(), dependency);
+    }
+    Ok(())
+}
+
+fn locked(dependency: &CollectionDependency, folder: &Path, source: String) -> Result<LockedCollection, Box<dyn Error>> {
+    let version = collection_version(folder);
+    if !dependency.matches(version.as_deref())? {
+        return Err(format!(
+            "Collection {} from {} has version {}, required {}",
+            dependency.name,
+            source,
+            version.unwrap_or("unknown".to_string()),
+            dependency.version.clone().unwrap_or("*".to_string())
+        ).into());
+    }
+    Ok(LockedCollection {
+        name: dependency.name.clone(),
+        version,
+        source,
+        path: relative_to_current_dir(folder),
+    })
+}
+
+// keeps messages free of machine specific prefixes
+fn relative_to_current_dir(folder: &Path) -> String {
+    match std::env::current_dir() {
+        Ok(current_dir) => folder.strip_prefix(&current_dir).unwrap_or(folder).to_string_lossy().to_string(),
+        Err(_) => folder.to_string_lossy().to_string(),
+    }
+}
+
+// a folder as the lock file keeps it, valid from any current directory
+fn relative_to_workspace(workspace_path: &str, folder: &Path) -> String {
+    let (Ok(workspace), Ok(folder_path)) = (Path::new(workspace_path).canonicalize(), folder.canonicalize()) else {
+        return folder.to_string_lossy().to_string();
+    };
+    let common = workspace.components().zip(folder_path.components()).take_while(|(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+    for _ in common..workspace.components().count() {
+        relative.push("..");
+    }
+    for component in folder_path.components().skip(common) {
+        relative.push(component);
+    }
+    match relative.as_os_str().is_empty() {
+        true => ".".to_string(),
+        false => relative.to_string_lossy().to_string(),
+    }
+}
+
+// a lock file folder joined to the workspace, folding the `..` it starts with
+fn workspace_folder(workspace_path: &str, relative: &str) -> String {
+    let mut folder = PathBuf::from(workspace_path);
+    for component in Path::new(relative).components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir if matches!(folder.components().next_back(), Some(Component::Normal(_))) => {
+                folder.pop();
+            },
+            other => folder.push(other.as_os_str()),
+        }
+    }
+    folder.to_string_lossy().to_string()
+}
+
+/// The highest tag of the repository matching `requirement`, tags may start with `v`.
+fn resolve_git_tag(dependency: &CollectionDependency, url: &str, requirement: &VersionReq) -> Result<String, Box<dyn Error>> {
+    let output = Command::new("git").arg("ls-remote").arg("--tags").arg("--refs").arg(url).output()?;
+    if !output.status.success() {
+        return Err(format!("Listing tags of {} for {}: {}", url, dependency.name, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once("refs/tags/").map(|(_, tag)| tag.trim().to_string()))
+        .filter_map(|tag| Version::parse(tag.trim_start_matches('v')).ok().map(|version| (version, tag)))
+        .filter(|(version, _)| requirement.matches(version))
+        .max_by(|a, b| a.0.cmp(&b.0))
+        .map(|(_, tag)| tag)
+        .ok_or_else(|| format!("No tag of {} matches {} {}", url, dependency.name, requirement).into())
+}
+
+fn resolve_git(dependency: &CollectionDependency, url: &str, workspace_path: &str) -> Result<LockedCollection, Box<dyn Error>> {
+    let tag = match dependency.requirement()? {
+        Some(requirement) => Some(resolve_git_tag(dependency, url, &requirement)?),
+        None => None,
+    };
+    let reference = tag.clone().unwrap_or("HEAD".to_string());
+    let checkout = PathBuf::from(workspace_path).join(".chgops/collections").join(format!("{}-{}", dependency.name, reference));
+    if !checkout.exists() {
+        eprint_info!("Cloning collection {} from {} at {}", dependency.name, url, reference);
+        let mut command = Command::new("git");
+        command.arg("clone").arg("--depth").arg("1");
+        if let Some(tag) = &tag {
+            command.arg("--branch").arg(tag);
+        }
+        let output = command.arg("--").arg(url).arg(&checkout).output()?;
+        if !output.status.success() {
+            return Err(format!("Cloning {} for {}: {}", url, dependency.name, String::from_utf8_lossy(&output.stderr).trim()).into());
+        }
+    }
+    let commit = history::git_commit(checkout.to_str().unwrap()).unwrap_or_default();
+    locked(dependency, &checkout, format!("git:{}@{}", url, commit))
+}
+
+fn resolve_registry(dependency: &CollectionDependency, location: &str, workspace_path: &str) -> Result<LockedCollection, Box<dyn Error>> {
+    let registry = registry::open_registry(location);
+    let requirement = dependency.version.clone().unwrap_or("latest".to_string());
+    let entry = registry.index(&dependency.name)?.resolve(&requirement)?;
+    let destination = PathBuf::from(workspace_path).join(".chgops/collections").join(format!("{}-{}", dependency.name, entry.version));
+    let folder = destination.join(&dependency.name);
+    if !folder.exists() {
+        eprint_info!("Downloading collection {} {} from {}", dependency.name, entry.version, location);
+        registry.download(&dependency.name, &format!("={}", entry.version), destination.to_str().unwrap())?;
+    }
+    locked(dependency, &folder, format!("registry:{}", location))
+}
+
+/// Reads `chgops.lock` of the workspace, its folders joined to the workspace; `None` without a lock file.
+pub fn read_lock(workspace_path: &str) -> Result<Option<LockFile>, Box<dyn Error>> {
+    let lock_path = format!("{}/{}", workspace_path, LOCK_FILE);
+    let Ok(content) = fs::read_to_string(&lock_path) else {
+        return Ok(None);
+    };
+    let mut lock: LockFile = serde_yaml::from_str(&content).map_err(|e| format!("Reading {}: {}", lock_path, e))?;
+    for collection in lock.collections.iter_mut() {
+        collection.path = workspace_folder(workspace_path, &collection.path);
+    }
+    Ok(Some(lock))
+}
+
+/// `chgops.lock` of the workspace while it satisfies the declarations, `None` when the collections
+/// must be resolved again: there is no lock or a declaration changed.
+pub fn satisfied_lock(workspace_path: &str, dependencies: &Dependencies) -> Result<Option<LockFile>, Box<dyn Error>> {
+    check_conflicts(&dependencies.collections)?;
+    match read_lock(workspace_path)? {
+        Some(lock) if lock.satisfies(dependencies) => {
+            log_debug!("files", "collections of {}/{}", workspace_path, LOCK_FILE);
+            Ok(Some(lock))
+        },
+        Some(_) => {
+            eprint_info!("{}/{} does not match the declared collections, resolving them again", workspace_path, LOCK_FILE);
+            Ok(None)
+        },
+        None => Ok(None),
+    }
+}
+
+/// The collections of a workspace: those of `chgops.lock` while it satisfies the declarations,
+/// resolved again when `update` is set, there is no lock or a declaration changed.
+pub fn resolve_workspace(workspace_path: &str, collections_path: &str, dependencies: &Dependencies, update: bool) -> Result<Vec<LockedCollection>, Box<dyn Error>> {
+    if !update {
+        if let Some(lock) = satisfied_lock(workspace_path, dependencies)? {
+            return Ok(lock.collections);
+        }
+    }
+    resolve(workspace_path, collections_path, dependencies)
+}
+
+/// Resolves every declared collection, and the collections their manifests depend on, to a folder
+/// at the requested version. Dependencies come before the collections that need them.
+pub fn resolve(workspace_path: &str, collections_path: &str, dependencies: &Dependencies) -> Result<Vec<LockedCollection>, Box<dyn Error>> {
+    check_conflicts(&dependencies.collections)?;
+
+    let mut resolved: Vec<LockedCollection> = vec![];
+    for dependency in dependencies.collections.iter() {
+        resolve_with_dependencies(workspace_path, collections_path, dependencies, dependency, &mut vec![], &mut resolved)?;
+    }
+    Ok(resolved)
+}
+
+fn resolve_with_dependencies(workspace_path: &str, collections_path: &str, dependencies: &Dependencies, dependency: &CollectionDependency, required_by: &mut Vec<String>, resolved: &mut Vec<LockedCollection>) -> Result<(), Box<dyn Error>> {
+    if let Some(existing) = resolved.iter().find(|r| r.name == dependency.name) {
+        if !dependency.matches(existing.version.as_deref())? {
+            return Err(format!(
+                "Conflicting versions for collection {}: {} resolved to {}, but {} requires {}",
+                dependency.name,
+                dependency.name,
+                existing.version.clone().unwrap_or("unknown".to_string()),
+                required_by.last().cloned().unwrap_or("the workspace".to_string()),
+                dependency.version.clone().unwrap_or("*".to_string())
+            ).into());
+        }
+        return Ok(());
+    }
+    if required_by.contains(&dependency.name) {
+        return Err(format!("Circular collection dependency: {} -> {}", required_by.join(" -> "), dependency.name).into());
+    }
+
+    let collection = resolve_one(workspace_path, collections_path, dependencies, dependency)?;
+    if let Some(manifest) = collection::read_manifest(Path::new(&collection.path))? {
+        required_by.push(dependency.name.clone());
+        for inner in manifest.dependencies.iter() {
+            resolve_with_dependencies(workspace_path, collections_path, dependencies, inner, required_by, resolved)?;
+        }
+        required_by.pop();
+    }
+    resolved.push(collection);
+    Ok(())
+}
+
+fn resolve_one(workspace_path: &str, collections_path: &str, dependencies: &Dependencies, dependency: &CollectionDependency) -> Result<LockedCollection, Box<dyn Error>> {
+    let (namespace, name) = dependency.namespace_and_name()?;
+
+    if let Some(path) = &dependency.path {
+        let folder = PathBuf::from(workspace_path).join(path);
+        if !folder.is_dir() {
+            return Err(format!("Collection {} not found at path: {:?}", dependency.name, folder).into());
+        }
+        return locked(dependency, &folder, format!("path:{}", path));
+    }
+    if let Some(url) = &dependency.git {
+        return resolve_git(dependency, url, workspace_path);
+    }
+
+    // a packaged workspace carries its collections, otherwise use the shared ones
+    let candidates = [
+        PathBuf::from(workspace_path).join("collections").join(&namespace).join(&name),
+        PathBuf::from(collections_path).join(&namespace).join(&name),
+    ];
+    let mut local_error = None;
+    for folder in candidates.iter().filter(|f| f.is_dir()) {
+        match locked(dependency, folder, format!("path:{}", relative_to_workspace(workspace_path, folder))) {
+            Ok(collection) => return Ok(collection),
+            Err(e) => local_error = Some(e),
+        }
+    }
+
+    match dependency.registry.as_ref().or(dependencies.source.as_ref()) {
+        Some(location) => resolve_registry(dependency, location, workspace_path).map_err(|e| {
+            match &local_error {
+                Some(local) => format!("{}; registry {}: {}", local, location, e).into(),
+                None => format!("Collection {} not found locally; registry {}: {}", dependency.name, location, e).into(),
+            }
+        }),
+        None => Err(local_error.unwrap_or_else(|| {
+            format!("Collection {} not found in {:?}", dependency.name, candidates).into()
+        })),
+    }
+}
+
+/// Vars files of the resolved collections: namespace level files first, then the collection tree.
+pub fn collection_files(collections: &[LockedCollection]) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut files = vec![];
+    for collection in collections.iter() {
+        let folder = Path::new(&collection.path);
+        if let Some(namespace_folder) = folder.parent() {
+            let mut namespace_files = vec![];
+            for entry in fs::read_dir(namespace_folder)? {
+                let path = entry?.path();
+                if path.is_file() && path.extension().map(|e| e == "yaml").unwrap_or(false) {
+                    namespace_files.push(path.to_string_lossy().to_string());
+                }
+            }
+            namespace_files.sort();
+            for file in namespace_files {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+        }
+        for file in files_and_dirs::find_files_by_regex(collection.path.clone(), r".*\.yaml$")? {
+            if collection::is_vars_file(&file) && !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Writes `chgops.lock` into the workspace through a temporary file, returning true when it changed.
+pub fn write_lock(workspace_path: &str, collections: &[LockedCollection]) -> Result<bool, Box<dyn Error>> {
+    let lock_path = format!("{}/{}", workspace_path, LOCK_FILE);
+    let lock = LockFile {
+        collections: collections.iter().map(|collection| LockedCollection {
+            path: relative_to_workspace(workspace_path, Path::new(&collection.path)),
+            ..collection.clone()
+        }).collect(),
+    };
+    let content = format!("# generated by chgops, do not edit\n{}", serde_yaml::to_string(&lock)?);
+    if let Ok(existing) = fs::read_to_string(&lock_path) {
+        if existing == content {
+            return Ok(false);
+        }
+    }
+    // a run stopped halfway leaves the previous lock, never half of one
+    let temp_path = format!("{}.tmp", lock_path);
+    files_and_dirs::write_file(&temp_path, &content)?;
+    fs::rename(&temp_path, &lock_path).inspect_err(|_| { let _ = fs::remove_file(&temp_path); })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chgops-lock-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    fn dependency(name: &str, version: Option<&str>) -> CollectionDependency {
+        CollectionDependency {
+            name: name.to_string(),
+            version: version.map(|v| v.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn an_exact_version_only_matches_itself() {
+        assert!(dependency("dx.azure", Some("1.2.0")).matches(Some("1.2.0")).unwrap());
+        assert!(!dependency("dx.azure", Some("1.2.0")).matches(Some("1.2.1")).unwrap());
+        assert!(dependency("dx.azure", Some("^1.2")).matches(Some("1.9.0")).unwrap());
+        assert!(!dependency("dx.azure", Some("^1.2")).matches(None).unwrap());
+        assert!(dependency("dx.azure", None).matches(None).unwrap());
+        assert!(dependency("dx.azure", Some("newest")).requirement().is_err());
+    }
+
+    #[test]
+    fn two_declarations_of_a_collection_must_agree() {
+        assert!(check_conflicts(&[dependency("dx.azure", Some("1.0.0")), dependency("dx.azure", Some("1.0.0"))]).is_ok());
+        assert!(check_conflicts(&[dependency("dx.azure", Some("1.0.0")), dependency("dx.azure", Some("2.0.0"))]).is_err());
+    }
+
+    #[test]
+    fn lock_paths_are_relative_to_the_workspace() {
+        let root = folder("paths");
+        let workspace = root.join("playbooks/workspace");
+        let collection = root.join("collections/dx/azure");
+        fs::create_dir_all(&workspace).unwrap();
+        fs::create_dir_all(&collection).unwrap();
+        let workspace_path = workspace.to_str().unwrap();
+
+        let relative = relative_to_workspace(workspace_path, &collection);
+        assert_eq!(relative, "../../collections/dx/azure");
+        assert_eq!(Path::new(&workspace_folder(workspace_path, &relative)).canonicalize().unwrap(), collection.canonicalize().unwrap());
+        assert_eq!(relative_to_workspace(workspace_path, &workspace), ".");
+        assert_eq!(workspace_folder("./playbooks/ws", "../../collections/dx/azure"), "./collections/dx/azure");
+    }
+
+    #[test]
+    fn the_lock_is_written_once_and_read_back_while_it_satisfies_the_declarations() {
+        let root = folder("write");
+        let collection = root.join("collections/dx/azure");
+        fs::create_dir_all(&collection).unwrap();
+        let workspace_path = root.to_str().unwrap();
+        let collections = vec![LockedCollection {
+            name: "dx.azure".to_string(),
+            version: Some("1.2.0".to_string()),
+            source: "path:./collections/dx/azure".to_string(),
+            path: collection.to_string_lossy().to_string(),
+        }];
+
+        assert!(write_lock(workspace_path, &collections).unwrap());
+        assert!(!write_lock(workspace_path, &collections).unwrap());
+        assert!(!root.join(format!("{}.tmp", LOCK_FILE)).exists());
+        let content = fs::read_to_string(root.join(LOCK_FILE)).unwrap();
+        assert!(content.contains("path: collections/dx/azure"));
+
+        let lock = read_lock(workspace_path).unwrap().unwrap();
+        assert_eq!(lock.collections[0].path, collection.to_string_lossy());
+
+        let declared = |version: &str, path: Option<&str>| Dependencies {
+            source: None,
+            collections: vec![CollectionDependency {
+                path: path.map(|p| p.to_string()),
+                ..dependency("dx.azure", Some(version))
+            }],
+        };
+        assert!(lock.satisfies(&declared("^1.0", None)));
+        assert!(lock.satisfies(&declared("1.2.0", Some("./collections/dx/azure"))));
+        assert!(!lock.satisfies(&declared("^2.0", None)));
+        assert!(!lock.satisfies(&declared("1.2.0", Some("./other"))));
+        assert!(satisfied_lock(workspace_path, &declared("^1.0", None)).unwrap().is_some());
+        assert!(satisfied_lock(workspace_path, &declared("^2.0", None)).unwrap().is_none());
+
+        fs::remove_dir_all(&collection).unwrap();
+        assert!(!lock.satisfies(&declared("^1.0", None)));
+    }
+}
@@ -0,0 +1,214 @@
+// tasks implemented outside of chgops, by executables shipped in a collection `modules` folder
+
+use serde::Serialize;
+use serde_yaml::Value as YamlValue;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use crate::collections::dx::{PlaybookCommand, PlaybookCommandOutput, PlaybookCommandTrait, PlaybookTaskInfo};
+use crate::collections::dx::context::RunContext;
+use crate::collections::Verbose;
+use crate::collections::dx::vault::Secrets;
+use crate::collections::dx::task_registry::PlaybookTask;
+use crate::log_debug;
+
+/// Folder of a collection holding its module executables.
+pub const MODULES_FOLDER: &str = "modules";
+
+/// Extensions tried after the bare module name.
+const MODULE_EXTENSIONS: [&str; 4] = ["", ".exe", ".cmd", ".bat"];
+
+/// A task whose key is not a built-in task, e.g. `acme.network.dns`.
+#[derive(Debug)]
+pub struct ExternalTask {
+    pub task_type: String,
+    pub task: PlaybookCommand<Option<String>, YamlValue>,
+}
+
+/// [`TaskFactory`](crate::collections::dx::task_registry::TaskFactory) of the tasks run by a module.
+pub fn parse(task_type: &str, body: YamlValue) -> Result<Box<dyn PlaybookTask>, serde_yaml::Error> {
+    let task = serde_yaml::from_value(body)?;
+    Ok(Box::new(ExternalTask { task_type: task_type.to_string(), task }))
+}
+
+/// `<collection>/modules/<module>` of the loaded collection named by a `namespace.collection.module` task type.
+pub fn find_module(task_type: &str, collections: &[(String, String)]) -> Result<PathBuf, Box<dyn Error>> {
+    let (collection_name, module) = task_type.rsplit_once('.')
+        .filter(|(collection_name, module)| collection_name.contains('.') && !module.is_empty())
+        .ok_or_else(|| format!("Unknown task type {}: expected namespace.collection.module", task_type))?;
+    let (_, folder) = collections.iter().find(|(name, _)| name == collection_name)
+        .ok_or_else(|| format!("Unknown task type {}: collection {} is not loaded", task_type, collection_name))?;
+    let modules_folder = Path::new(folder).join(MODULES_FOLDER);
+    let path = MODULE_EXTENSIONS.iter()
+        .map(|extension| modules_folder.join(format!("{}{}", module, extension)))
+        .find(|path| path.is_file())
+        .ok_or_else(|| format!("Unknown task type {}: no module {} in {:?}", task_type, module, modules_folder))?;
+    log_debug!("registry", "task type {} runs module {}", task_type, path.display());
+    Ok(path)
+}
+
+/// Task types of the modules in the loaded collections.
+pub fn loaded_modules(collections: &[(String, String)]) -> Vec<String> {
+    collections.iter()
+        .flat_map(|(name, folder)| {
+            list_modules(Path::new(folder)).into_iter().map(move |module| format!("{}.{}", name, module))
+        })
+        .collect()
+}
+
+/// Message written as JSON to the module stdin.
+#[derive(Debug, Serialize)]
+pub struct ModuleRequest {
+    pub task: String,
+    pub name: Option<String>,
+    pub command: Option<String>,
+    pub state: Option<String>,
+    pub register: Option<String>,
+    pub vars: serde_json::Value,
+    pub context: serde_json::Value,
+    pub non_interactive: bool,
+}
+
+impl ExternalTask {
+    fn request(&self, context: &RunContext) -> Result<ModuleRequest, Box<dyn Error>> {
+        let template = serde_yaml::to_string(&self.task.vars)?;
+        let rendered = context.render(&template)?;
+        let vars: YamlValue = serde_yaml::from_str(&rendered)?;
+        Ok(ModuleRequest {
+            task: self.task_type.clone(),
+            name: self.task.name.clone(),
+            command: self.task.command.clone(),
+            state: self.task.state.clone(),
+            register: self.task.register.clone(),
+            vars: serde_json::to_value(vars)?,
+            context: context.facts.context.clone().into_json(),
+            non_interactive: context.options.non_interactive,
+        })
+    }
+
+    fn run_module(&self, context: &RunContext) -> Result<PlaybookCommandOutput, Box<dyn Error>> {
+        let module = find_module(&self.task_type, &context.collections)?;
+        let request = serde_json::to_string(&self.request(context)?)?;
+
+        let mut child = Command::new(&module)
+            .env("CHGOPS_TASK", &self.task_type)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Starting module {:?}: {}", module, e))?;
+        // written from its own thread, a module answering before it read the whole request would block on a full stdout pipe
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(request.as_bytes()));
+        let result = child.wait_with_output()?;
+        // a module exiting without reading its request breaks the pipe, its exit status tells what happened
+        let _ = writer.join();
+
+        let stdout = String::from_utf8_lossy(&result.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&result.stderr).to_string();
+        let mut output = match serde_json::from_str::<serde_json::Value>(&stdout) {
+            Ok(value) => PlaybookCommandOutput::from_partial(serde_yaml::to_value(value)?)
+                .map_err(|e| format!("Module {:?} returned an invalid output: {}", module, e))?,
+            Err(_) => {
+                // not JSON: keep what the module printed
+                let mut output = PlaybookCommandOutput::new();
+                output.stdout = stdout;
+                output.status = 1;
+                output.success = 1;
+                output
+            },
+        };
+        if output.stderr.is_empty() {
+            output.stderr = stderr;
+        }
+        if !result.status.success() {
+            output.failed = 1;
+            output.success = 0;
+            if output.message.is_empty() {
+                output.message = format!("Module {} exited with {}", self.task_type, result.status);
+            }
+        } else if output.message.is_empty() {
+            output.message = "Success".to_string();
+        }
+        Ok(output)
+    }
+}
+
+impl PlaybookCommandTrait for ExternalTask {
+    fn execute(&mut self, context: &mut RunContext) {
+        let when = self.task.when.clone().unwrap_or("true".to_string());
+        if when == "false" {
+            self.task.output = PlaybookCommandOutput::new();
+            self.task.output.set_start_time();
+            self.task.output.message = "Skipped".to_string();
+            self.task.output.skipped = 1;
+            self.task.output.set_end_time();
+            return;
+        }
+
+        let start_time = chrono::Utc::now();
+        let mut output = self.run_module(context).unwrap_or_else(|err| {
+            let mut output = PlaybookCommandOutput::new();
+            output.message = err.to_string();
+            output.stderr = err.to_string();
+            output.status = 1;
+            output.failed = 1;
+            output
+        });
+        output.start_time = Some(start_time);
+        output.set_end_time();
+
+        if let Some(register) = self.task.register.as_ref().filter(|r| !r.is_empty()) {
+            context.register(register, &output);
+        }
+        self.task.output = output;
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &Secrets) {
+        self.task.display_default(verbose, secrets);
+    }
+
+    fn output(&self) -> PlaybookCommandOutput {
+        self.task.output.clone()
+    }
+}
+
+impl PlaybookTask for ExternalTask {
+    fn info(&self) -> PlaybookTaskInfo {
+        self.task.info(&self.task_type)
+    }
+
+    fn command_text(&self) -> String {
+        self.task.command.clone().unwrap_or_default()
+    }
+
+    fn when(&self) -> Option<String> {
+        self.task.when.clone()
+    }
+
+    fn set_output(&mut self, output: PlaybookCommandOutput) {
+        self.task.output = output;
+    }
+
+    fn to_value(&self) -> Result<YamlValue, serde_yaml::Error> {
+        serde_yaml::to_value(&self.task)
+    }
+}
+
+/// Module executables of a collection folder, by task name.
+pub fn list_modules(collection_folder: &Path) -> Vec<String> {
+    let mut modules = vec![];
+    if let Ok(entries) = std::fs::read_dir(collection_folder.join(MODULES_FOLDER)) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                modules.push(path.file_stem().unwrap().to_string_lossy().to_string());
+            }
+        }
+    }
+    modules.sort();
+    modules.dedup();
+    modules
+}
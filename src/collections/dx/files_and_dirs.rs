@@ -3,6 +3,7 @@ use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use regex::Regex;
+use crate::log_debug;
 
 
 pub fn list_all_files_and_dirs(path: String) -> Result<Vec<String>, Box<dyn Error>> {
@@ -46,8 +47,8 @@ pub fn find_files_by_regex(path: String, re: &str) -> Result<Vec<String>, Box<dy
     Ok(files)
 }
 
-pub fn sort_files_by_path_length(files: &mut Vec<String>) {
-    files.sort_by(|a, b| a.len().cmp(&b.len()));
+pub fn sort_files_by_path_length(files: &mut [String]) {
+    files.sort_by_key(|a| a.len());
 }
 
 
@@ -57,7 +58,7 @@ pub fn read_file(file_path: &str) -> Result<String, Box<dyn std::error::Error>>
     }
     match std::fs::read_to_string(file_path) {
         Ok(s) => {
-            println!("Reading file: {}", file_path);
+            log_debug!("files", "Reading file: {}", file_path);
             Ok(s)
         },
         Err(e) => {
@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use chrono::{NaiveDate, Utc};
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+use crate::collections::dx::files_and_dirs;
+use crate::collections::dx::report::RunReport;
+
+/// A persisted playbook run, stored as `.chgops/runs/<run_id>.json`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub user: String,
+    pub host: String,
+    pub git_commit: Option<String>,
+    pub change_id: Option<String>,
+    pub status: String,
+    pub report: RunReport,
+}
+
+impl RunRecord {
+    pub fn new(report: RunReport) -> RunRecord {
+        let status = if report.summary.failed_counter > 0 { "failed" } else { "success" };
+        let git_commit = git_commit(&report.engine.workspace_path);
+
+        RunRecord {
+            run_id: new_run_id(),
+            user: current_user(),
+            host: current_host(),
+            git_commit,
+            change_id: report.change_id.clone(),
+            status: status.to_string(),
+            report,
+        }
+    }
+}
+
+/// Filters accepted by `chgops history`; empty fields match everything.
+#[derive(Debug, Default)]
+pub struct RunFilter {
+    pub playbook: Option<String>,
+    pub stage: Option<String>,
+    pub status: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+}
+
+impl RunFilter {
+    pub fn matches(&self, record: &RunRecord) -> bool {
+        if let Some(playbook) = &self.playbook {
+            if &record.report.engine.playbook_name != playbook && &record.report.playbook != playbook {
+                return false;
+            }
+        }
+        if let Some(stage) = &self.stage {
+            if record.report.stage.as_ref() != Some(stage) {
+                return false;
+            }
+        }
+        if let Some(status) = &self.status {
+            if &record.status != status {
+                return false;
+            }
+        }
+        let started = record.report.summary.start_time.map(|t| t.date_naive());
+        if let (Some(since), Some(started)) = (self.since, started) {
+            if started < since {
+                return false;
+            }
+        }
+        if let (Some(until), Some(started)) = (self.until, started) {
+            if started > until {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Local run history kept in a directory of JSON records.
+pub struct RunStore {
+    pub path: String,
+}
+
+impl RunStore {
+    pub fn new(path: &str) -> RunStore {
+        RunStore {
+            path: path.to_string(),
+        }
+    }
+
+    pub fn save(&self, record: &RunRecord) -> Result<String, Box<dyn Error>> {
+        validate_run_id(&record.run_id)?;
+        let file_path = format!("{}/{}.json", self.path, record.run_id);
+        files_and_dirs::write_file(&file_path, &serde_json::to_string_pretty(record)?)?;
+        Ok(file_path)
+    }
+
+    pub fn load(&self, run_id: &str) -> Result<RunRecord, Box<dyn Error>> {
+        validate_run_id(run_id)?;
+        let file_path = format!("{}/{}.json", self.path, run_id);
+        if !Path::new(&file_path).exists() {
+            return Err(format!("Run not found: {} in {}", run_id, self.path).into());
+        }
+        let content = std::fs::read_to_string(&file_path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Returns the stored runs matching `filter`, most recent first.
+    pub fn list(&self, filter: &RunFilter) -> Result<Vec<RunRecord>, Box<dyn Error>> {
+        let mut records = vec![];
+        if !Path::new(&self.path).exists() {
+            return Ok(records);
+        }
+        for file in files_and_dirs::find_files_by_regex(self.path.clone(), r".*\.json$")? {
+            let content = std::fs::read_to_string(&file)?;
+            let record: RunRecord = serde_json::from_str(&content)
+                .map_err(|e| format!("Reading run record {}: {}", file, e))?;
+            if filter.matches(&record) {
+                records.push(record);
+            }
+        }
+        records.sort_by_key(|r| std::cmp::Reverse(r.report.summary.start_time));
+        Ok(records)
+    }
+}
+
+pub fn new_run_id() -> String {
+    let now = Utc::now();
+    format!("{}-{:08x}", now.format("%Y%m%d-%H%M%S"), now.timestamp_subsec_nanos() ^ std::process::id())
+}
+
+/// Checks a run id before it becomes a file name in the store: letters, digits, `_` and `-`, as [`new_run_id`] makes them.
+pub fn validate_run_id(run_id: &str) -> Result<(), Box<dyn Error>> {
+    match !run_id.is_empty() && run_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        true => Ok(()),
+        false => Err(format!("Invalid run id: {}", run_id).into()),
+    }
+}
+
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or("unknown".to_string())
+}
+
+pub fn current_host() -> String {
+    if let Ok(host) = std::env::var("HOSTNAME").or_else(|_| std::env::var("COMPUTERNAME")) {
+        return host;
+    }
+    match std::fs::read_to_string("/etc/hostname") {
+        Ok(host) if !host.trim().is_empty() => host.trim().to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Commit of the git repository containing `path`, if any.
+pub fn git_commit(path: &str) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .arg("rev-parse")
+        .arg("HEAD")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn record(run_id: &str, status: &str, stage: &str, day: u32) -> RunRecord {
+        let mut report = RunReport {
+            playbook: "deploy".to_string(),
+            stage: Some(stage.to_string()),
+            ..Default::default()
+        };
+        report.engine.playbook_name = "playbook".to_string();
+        report.summary.start_time = Some(Utc.with_ymd_and_hms(2024, 3, day, 10, 0, 0).unwrap());
+        RunRecord {
+            run_id: run_id.to_string(),
+            status: status.to_string(),
+            report,
+            ..Default::default()
+        }
+    }
+
+    fn store(name: &str) -> RunStore {
+        let path = std::env::temp_dir().join(format!("chgops-history-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        RunStore::new(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn a_saved_run_loads_back_and_lists_most_recent_first() {
+        let store = store("save");
+        store.save(&record("run-1", "success", "dev", 1)).unwrap();
+        store.save(&record("run-2", "failed", "prd", 2)).unwrap();
+
+        assert_eq!(store.load("run-1").unwrap().status, "success");
+        let listed = store.list(&RunFilter::default()).unwrap();
+        assert_eq!(listed.iter().map(|r| r.run_id.as_str()).collect::<Vec<_>>(), vec!["run-2", "run-1"]);
+        assert!(store.load("run-3").unwrap_err().to_string().contains("Run not found"));
+        let _ = std::fs::remove_dir_all(&store.path);
+    }
+
+    #[test]
+    fn run_ids_outside_the_store_are_rejected() {
+        let store = store("ids");
+        for run_id in ["../../x", "a/b", "a\\b", "..", ""] {
+            assert!(store.load(run_id).unwrap_err().to_string().contains("Invalid run id"), "{}", run_id);
+        }
+        assert!(store.save(&record("../x", "success", "dev", 1)).is_err());
+        assert!(validate_run_id(&new_run_id()).is_ok());
+    }
+
+    #[test]
+    fn the_filter_matches_playbook_stage_status_and_dates() {
+        let run = record("run-1", "failed", "dev", 10);
+        let since = |day| NaiveDate::from_ymd_opt(2024, 3, day);
+
+        assert!(RunFilter::default().matches(&run));
+        assert!(RunFilter { playbook: Some("deploy".to_string()), ..Default::default() }.matches(&run));
+        assert!(RunFilter { playbook: Some("playbook".to_string()), ..Default::default() }.matches(&run));
+        assert!(!RunFilter { stage: Some("prd".to_string()), ..Default::default() }.matches(&run));
+        assert!(!RunFilter { status: Some("success".to_string()), ..Default::default() }.matches(&run));
+        assert!(RunFilter { since: since(10), until: since(10), ..Default::default() }.matches(&run));
+        assert!(!RunFilter { since: since(11), ..Default::default() }.matches(&run));
+        assert!(!RunFilter { until: since(9), ..Default::default() }.matches(&run));
+    }
+}
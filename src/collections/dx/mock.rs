@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use regex::Regex;
+use std::error::Error;
+use crate::collections::dx::{files_and_dirs, PlaybookCommandOutput, PlaybookTaskInfo};
+use crate::log_debug;
+
+/// Environment variable pointing `chgops run` at a mocks file when `--mocks` is not given.
+pub const MOCKS_ENV: &str = "CHGOPS_MOCKS";
+
+/// Tasks that never leave the engine; strict mode lets them run without a mock.
+pub const LOCAL_TASK_TYPES: [&str; 4] = ["dx.core.print", "dx.core.pause", "dx.core.prompt", "dx.core.set_fact"];
+
+/// Canned output returned instead of executing a task.
+///
+/// A mock matches when every selector it sets matches: the task `name`, the task `type`
+/// and a regular expression over the task `command`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct TaskMock {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub task_type: Option<String>,
+    pub command: Option<String>,
+    /// fields of the task output, e.g. `{ stdout: "...", changed: 1, data: {...} }`
+    #[serde(default)]
+    pub output: serde_yaml::Value,
+    /// how many tasks the mock answers, unlimited when unset
+    pub times: Option<usize>,
+}
+
+impl TaskMock {
+    pub fn matches(&self, info: &PlaybookTaskInfo, command: &str) -> Result<bool, Box<dyn Error>> {
+        if self.name.is_none() && self.task_type.is_none() && self.command.is_none() {
+            return Ok(false);
+        }
+        let command_matches = match &self.command {
+            Some(pattern) => Regex::new(pattern)
+                .map_err(|e| format!("Invalid command pattern in mock: {}: {}", pattern, e))?
+                .is_match(command),
+            None => true,
+        };
+        Ok(command_matches
+            && self.name.as_ref().map(|n| *n == info.name).unwrap_or(true)
+            && self.task_type.as_ref().map(|t| *t == info.task_type).unwrap_or(true))
+    }
+
+    /// The mocked output: a successful run unless the mock says otherwise.
+    pub fn to_output(&self) -> Result<PlaybookCommandOutput, Box<dyn Error>> {
+        let mut output = PlaybookCommandOutput::from_partial(self.output.clone())?;
+        if output.message.is_empty() {
+            output.message = "Mocked".to_string();
+        }
+        output.mocked = true;
+        output.set_start_time();
+        output.set_end_time();
+        Ok(output)
+    }
+
+    fn describe(&self) -> String {
+        [
+            self.name.as_ref().map(|n| format!("name={}", n)),
+            self.task_type.as_ref().map(|t| format!("type={}", t)),
+            self.command.as_ref().map(|c| format!("command=/{}/", c)),
+        ].into_iter().flatten().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// A task answered by a mock.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct MockCall {
+    pub mock: usize,
+    pub task_name: String,
+    pub task_type: String,
+    pub command: String,
+}
+
+/// What to do with a task about to execute.
+pub enum MockDecision {
+    Run,
+    Mocked(Box<PlaybookCommandOutput>),
+    /// strict mode and no mock: the task fails with this message
+    Blocked(String),
+}
+
+/// File format: a list of mocks, or `{ strict: true, mocks: [...] }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum MockConfig {
+    List(Vec<TaskMock>),
+    Registry {
+        #[serde(default)]
+        strict: bool,
+        #[serde(default)]
+        mocks: Vec<TaskMock>,
+    },
+}
+
+/// The mocks consulted before every task executes, in declaration order.
+#[derive(Debug, Default, Clone)]
+pub struct MockRegistry {
+    pub mocks: Vec<TaskMock>,
+    /// fail tasks reaching outside the engine that no mock answers
+    pub strict: bool,
+    pub calls: Vec<MockCall>,
+}
+
+impl MockRegistry {
+    pub fn new() -> MockRegistry {
+        MockRegistry::default()
+    }
+
+    pub fn from_yaml(content: &str) -> Result<MockRegistry, Box<dyn Error>> {
+        let (strict, mocks) = match serde_yaml::from_str::<Option<MockConfig>>(content)? {
+            Some(MockConfig::List(mocks)) => (false, mocks),
+            Some(MockConfig::Registry { strict, mocks }) => (strict, mocks),
+            None => (false, vec![]),
+        };
+        for mock in mocks.iter() {
+            if let Some(pattern) = &mock.command {
+                Regex::new(pattern).map_err(|e| format!("Invalid command pattern in mock: {}: {}", pattern, e))?;
+            }
+        }
+        Ok(MockRegistry {
+            mocks,
+            strict,
+            calls: vec![],
+        })
+    }
+
+    pub fn load(path: &str) -> Result<MockRegistry, Box<dyn Error>> {
+        let content = files_and_dirs::read_file(path)?;
+        MockRegistry::from_yaml(&content).map_err(|e| format!("Reading mocks in {}: {}", path, e).into())
+    }
+
+    pub fn add(&mut self, mock: TaskMock) -> &mut MockRegistry {
+        self.mocks.push(mock);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mocks.is_empty() && !self.strict
+    }
+
+    fn used(&self, index: usize) -> usize {
+        self.calls.iter().filter(|c| c.mock == index).count()
+    }
+
+    /// Picks the first mock for the task that has answers left and records the call.
+    pub fn decide(&mut self, info: &PlaybookTaskInfo, command: &str) -> Result<MockDecision, Box<dyn Error>> {
+        for index in 0..self.mocks.len() {
+            let mock = &self.mocks[index];
+            if mock.times.map(|t| self.used(index) >= t).unwrap_or(false) || !mock.matches(info, command)? {
+                continue;
+            }
+            let output = mock.to_output()?;
+            log_debug!("mock", "task {} answered by mock {}", info.name, mock.describe());
+            self.calls.push(MockCall {
+                mock: index,
+                task_name: info.name.clone(),
+                task_type: info.task_type.clone(),
+                command: command.to_string(),
+            });
+            return Ok(MockDecision::Mocked(Box::new(output)));
+        }
+
+        if self.strict && !LOCAL_TASK_TYPES.contains(&info.task_type.as_str()) {
+            return Ok(MockDecision::Blocked(format!("No mock for task {} ({}) in strict mode", info.name, info.task_type)));
+        }
+        Ok(MockDecision::Run)
+    }
+
+    /// Mocks that never answered a task, described by their selectors.
+    pub fn unused(&self) -> Vec<String> {
+        self.mocks.iter().enumerate()
+            .filter(|(index, _)| self.used(*index) == 0)
+            .map(|(_, mock)| mock.describe())
+            .collect()
+    }
+}
+
+/// Loads the mocks from `path`, or from `CHGOPS_MOCKS` when `path` is empty; no mocks when neither is set.
+pub fn load(path: &str) -> Result<MockRegistry, Box<dyn Error>> {
+    let path = match path {
+        "" => match std::env::var(MOCKS_ENV) {
+            Ok(path) if !path.is_empty() => path,
+            _ => return Ok(MockRegistry::new()),
+        },
+        path => path.to_string(),
+    };
+    MockRegistry::load(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::dx::context::RunContext;
+    use crate::collections::dx::core::tasks::BashCommandTask;
+    use crate::collections::dx::{PlaybookCommandTrait, PlaybookTasks};
+
+    fn info(name: &str, task_type: &str) -> PlaybookTaskInfo {
+        PlaybookTaskInfo {
+            name: name.to_string(),
+            task_type: task_type.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn a_mock_matches_on_every_selector_it_sets() {
+        let mock = TaskMock {
+            task_type: Some("dx.core.bash".to_string()),
+            command: Some("^az group (create|show)".to_string()),
+            ..Default::default()
+        };
+
+        assert!(mock.matches(&info("any", "dx.core.bash"), "az group show -n rg1").unwrap());
+        assert!(!mock.matches(&info("any", "dx.core.bash"), "echo az group show").unwrap());
+        assert!(!mock.matches(&info("any", "dx.core.wincmd"), "az group show -n rg1").unwrap());
+    }
+
+    #[test]
+    fn a_mock_without_selectors_matches_nothing() {
+        assert!(!TaskMock::default().matches(&info("any", "dx.core.bash"), "echo").unwrap());
+    }
+
+    #[test]
+    fn an_invalid_command_pattern_is_an_error() {
+        let mock = TaskMock {
+            command: Some("(".to_string()),
+            ..Default::default()
+        };
+
+        assert!(mock.matches(&info("any", "dx.core.bash"), "echo").is_err());
+    }
+
+    fn bash(name: &str, when: &str) -> PlaybookTasks {
+        PlaybookTasks(Box::new(BashCommandTask {
+            command: format!("az group show -n {}", name),
+            name: Some(name.to_string()),
+            when: Some(when.to_string()),
+            task_type: "dx.core.bash".to_string(),
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn tasks_skipped_by_when_are_neither_mocked_nor_blocked() {
+        let mut context = RunContext::default();
+        context.mocks.strict = true;
+        context.mocks.add(TaskMock {
+            task_type: Some("dx.core.bash".to_string()),
+            times: Some(1),
+            ..Default::default()
+        });
+
+        let mut skipped = bash("skipped", "false");
+        skipped.execute_or_mock(&mut context);
+        assert_eq!(skipped.output().skipped, 1);
+        assert_eq!(skipped.output().failed, 0);
+        assert!(context.mocks.calls.is_empty());
+
+        let mut mocked = bash("mocked", "true");
+        mocked.execute_or_mock(&mut context);
+        assert!(mocked.output().mocked);
+        assert_eq!(context.mocks.calls.len(), 1);
+
+        let mut blocked = bash("blocked", "true");
+        blocked.execute_or_mock(&mut context);
+        assert_eq!(blocked.output().failed, 1);
+        assert!(blocked.output().message.contains("strict mode"));
+    }
+}
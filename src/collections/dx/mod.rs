@@ -3,20 +3,39 @@ pub mod config_proc;
 pub mod yaml_handler;
 pub mod core;
 pub mod azure;
+pub mod report;
+pub mod history;
+pub mod change;
+pub mod package;
+pub mod registry;
+pub mod dependencies;
+pub mod collection;
+pub mod testing;
+pub mod mock;
+pub mod external;
+pub mod task_registry;
+pub mod validate;
+pub mod schema;
+pub mod provenance;
+pub mod context;
+pub mod observer;
+pub mod sinks;
+pub mod vault;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use chrono::{DateTime, Utc};
 use chrono_humanize::HumanTime;
-use std::sync::Mutex;
-use lazy_static::lazy_static;
 use std::env;
 use std::collections::HashMap;
 use yaml_rust2::{YamlLoader, Yaml};
 use std::fs::File;
 use std::io::prelude::*;
 use tera::Context;
-use std::sync::RwLock;
+use context::RunContext;
+use observer::RunObserver;
+use crate::collections::{log, Verbose};
 
-use crate::{print_banner_yellow, print_error, print_banner_green};
+use crate::{eprint_info, log_debug, print_banner_yellow, print_error, print_banner_green, print_banner_red, print_banner_blue, print_info, print_success, print_warning};
 // use crate::{print_banner_yellow, print_error, print_banner_green, print_warning};
 
 pub fn open_yaml(filename: &str) -> Vec<Yaml> {
@@ -62,19 +81,31 @@ pub fn dump_yaml(doc: &Yaml, indent: usize) {
     }
 }
 
-lazy_static! {
-    pub static ref WORKSPACE: Mutex<ChgOpsWorkspace> = Mutex::new(ChgOpsWorkspace::new());
+/// Engine options tasks can read while they execute, see [`context::RunContext`].
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    pub non_interactive: bool,
+    pub arguments: HashMap<String, String>,
 }
 
-lazy_static! {
-    pub static ref FACTS: RwLock<Facts> = RwLock::new(Facts::new());
+impl RunOptions {
+    /// True when `--non-interactive` was given, stdin is not a terminal, or a CI system is detected.
+    pub fn detect_non_interactive(requested: bool) -> bool {
+        use std::io::IsTerminal;
+        requested
+            || !std::io::stdin().is_terminal()
+            || env::var("CI").is_ok()
+            || env::var("TF_BUILD").is_ok()
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct Facts {
     pub yaml: serde_yaml::Value,
     pub str: String,
     pub context: Context,
+    /// values of the vault files, of the variables declared `secret` and of the secret answers of the run
+    pub secrets: vault::SharedSecrets,
 }
 
 impl Facts {
@@ -83,11 +114,12 @@ impl Facts {
             yaml: serde_yaml::Value::Null,
             str: "".to_string(),
             context: Context::new(),
+            secrets: vault::SharedSecrets::default(),
         }
     }
 
     pub fn from_yaml2(&mut self, yaml: &yaml_rust2::Yaml) {
-        self.str = yaml_handler::yaml_to_string(&yaml).unwrap();
+        self.str = yaml_handler::yaml_to_string(yaml).unwrap();
         self.yaml = serde_yaml::from_str(&self.str).unwrap();
         let json: serde_json::Value = yaml_handler::yaml_to_json(&self.str).unwrap();
         self.context = Context::from_serialize(json).unwrap();
@@ -103,10 +135,35 @@ pub struct ChgOpsWorkspace {
 
     // engine_parameters
     pub playbook_name: String,
-    pub verbose: String,
+    pub verbose: Verbose,
     pub arguments: String,
+    pub diff: bool,
+    pub non_interactive: bool,
+    pub report: String,
+    pub change_id: String,
+    pub mocks: String,
+    /// folder receiving the intermediate files of the merge pipeline, none when empty
+    pub dump_intermediates: String,
+    /// `--output-format`: "console" (default), "json" or "dots"
+    pub output_format: String,
+    /// record the run in `.chgops/runs` of the current directory, off for `run --no-history` such as test runs
+    /// and for library runs unless `Workspace::with_history` turns it on
+    pub history: bool,
+    /// write the collections to `chgops.lock` when they are resolved again, set by `run` and `deps lock`
+    pub write_lock: bool,
+    /// resolve the declared collections again instead of reading `chgops.lock`, set by `deps lock`
+    pub update_lock: bool,
+    /// decrypts the encrypted vars files
+    pub vault: vault::Vault,
+    /// collections of the workspace as (`namespace.name`, folder), set by `collection_files`;
+    /// their modules are task types
+    pub collections: Vec<(String, String)>,
+    /// task types the playbooks of the workspace are parsed and validated with
+    pub tasks: task_registry::TaskRegistry,
 
     pub playbook: Playbook,
+    /// the rendered vars and `settings`, set by `load_workspace`
+    pub facts: Facts,
     pub configurations: Vec<Yaml>,
     pub variables: Vec<Yaml>,
     pub summary: PlaybookSummary,
@@ -123,9 +180,23 @@ impl ChgOpsWorkspace {
             current_dir: current_dir.clone(),
             workspace_path: current_dir.clone(),
             playbook_name: "".to_string(),
-            verbose: "".to_string(),
+            verbose: Verbose::Empty,
             arguments: "".to_string(),
-
+            diff: false,
+            non_interactive: false,
+            report: "".to_string(),
+            change_id: "".to_string(),
+            mocks: "".to_string(),
+            dump_intermediates: "".to_string(),
+            output_format: "".to_string(),
+            history: true,
+            write_lock: false,
+            update_lock: false,
+            vault: vault::Vault::new(""),
+            collections: vec![],
+            tasks: task_registry::TaskRegistry::with_builtins(),
+
+            facts: Facts::new(),
             playbook: Playbook::new("",
                 Settings::default(),
                 vec![]),
@@ -137,13 +208,15 @@ impl ChgOpsWorkspace {
     }
 
     pub fn workspace_path(&mut self) -> String {
-        let workspace_path = if self.workspace_path.is_empty() {
+        if self.workspace_path.is_empty() {
             self.current_dir.to_string()
         } else {
             self.workspace_path.to_string()
-        };
+        }
+    }
 
-        workspace_path
+    pub fn runs_path(&self) -> String {
+        format!("{}/.chgops/runs", &self.current_dir)
     }
 
     pub fn collection_path(&mut self) -> String {
@@ -158,126 +231,328 @@ impl ChgOpsWorkspace {
         format!("{}/config.yaml", &self.workspace_path())
     }
 
+    /// Parses the `--arguments` string, e.g. "STAGE=dev ARG1=1,ARG2=3", into a key/value map.
+    pub fn arguments_map(&self) -> HashMap<String, String> {
+        self.arguments
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect()
+    }
 
-    pub fn load_workspace(&mut self) {
-        let pattern1 = r".*\.yaml$";
+    /// The `--dump-intermediates` folder, if any.
+    pub fn dump_dir(&self) -> Option<&str> {
+        Some(self.dump_intermediates.as_str()).filter(|dir| !dir.is_empty())
+    }
 
-        let list_of_files_in_collection = files_and_dirs::find_files_by_regex(self.collection_path(), pattern1).unwrap_or_else(|err| {
-            print_error!("Error finding files in collection: {}", err);
-            panic!()
-        });
+    pub fn stage(&self) -> Option<String> {
+        self.arguments_map().get("STAGE").cloned()
+    }
 
-        if list_of_files_in_collection.len() == 0 {
-            print_error!("ERROR: No files found in collection");
-            panic!();
+    /// The change this run belongs to: `--change-id` if given, otherwise `settings.change.id`.
+    pub fn effective_change_id(&self) -> Option<String> {
+        if !self.change_id.is_empty() {
+            return Some(self.change_id.clone());
         }
+        self.playbook.settings.change.as_ref().map(|change| change.id.clone())
+    }
+
+    pub fn validate_change(&self) -> Result<(), String> {
+        let classification = match self.stage() {
+            Some(stage) => change::stage_classification(&self.facts.context.clone().into_json(), &stage),
+            None => None,
+        };
 
-        let pattern = r".*/vars/.*\.yaml$";
+        change::validate(self.playbook.settings.change.as_ref(), classification.as_deref(), &self.change_id, Utc::now())
+    }
 
-        let list_of_files_in_workspace = files_and_dirs::find_files_by_regex(self.workspace_path(), pattern).unwrap_or_else(|err| {
-            print_error!("Error finding files in workspace: {}", err);
-            panic!()
-        });
 
-        if list_of_files_in_workspace.len() == 0 {
-            print_error!("No 'vars' files found in workspace");
-            panic!();
+    /// Vars files of the collections declared in `dependencies.collections`, or of every collection when none are declared.
+    pub fn collection_files(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let workspace_path = self.workspace_path();
+        let collection_path = self.collection_path();
+
+        match dependencies::read_dependencies(&workspace_path)? {
+            Some(declared) if !declared.collections.is_empty() => {
+                // the lock is only written when the collections were resolved again
+                let locked = match self.update_lock {
+                    true => None,
+                    false => dependencies::satisfied_lock(&workspace_path, &declared)?,
+                };
+                let resolved = match locked {
+                    Some(lock) => lock.collections,
+                    None => {
+                        let resolved = dependencies::resolve(&workspace_path, &collection_path, &declared)?;
+                        if self.write_lock && dependencies::write_lock(&workspace_path, &resolved)? {
+                            eprint_info!("Lock file updated: {}/{}", workspace_path, dependencies::LOCK_FILE);
+                        }
+                        resolved
+                    },
+                };
+                for collection in resolved.iter() {
+                    eprint_info!("Collection {} {} from {}", collection.name, collection.version.clone().unwrap_or("*".to_string()), collection.source);
+                }
+                self.collections = resolved.iter().map(|c| (c.name.clone(), c.path.clone())).collect();
+                dependencies::collection_files(&resolved)
+            },
+            _ => {
+                self.collections = collection::discover(std::path::Path::new(&collection_path))?
+                    .into_iter()
+                    .map(|(name, folder)| (name, folder.to_string_lossy().to_string()))
+                    .collect();
+                Ok(files_and_dirs::find_files_by_regex(collection_path, r".*\.yaml$")?
+                    .into_iter()
+                    .filter(|file| collection::is_vars_file(file))
+                    .collect())
+            },
         }
+    }
 
-        //println!("list_of_files_in_workspace: {:#?}", list_of_files_in_workspace);
+    /// Playbook files of the workspace: the named one, or every yaml file at its root except tests.
+    pub fn playbook_files(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if !self.playbook_name.is_empty() {
+            return Ok(vec![self.playbook_full_path()]);
+        }
+        let mut playbooks = vec![];
+        for entry in std::fs::read_dir(self.workspace_path())? {
+            let path = entry?.path();
+            let file = path.to_string_lossy().to_string();
+            if path.is_file() && file.ends_with(".yaml") && !file.ends_with(".test.yaml") {
+                playbooks.push(file);
+            }
+        }
+        playbooks.sort();
+        Ok(playbooks)
+    }
 
-        let proc = config_proc::process_configuration_files(
-            list_of_files_in_collection, 
-            list_of_files_in_workspace);
-        match proc {
-            Ok(data) => {
-                println!("Facts are set to be used");
-                // we can now process the playbook
-                {
-                    let mut facts = FACTS.write().unwrap();
-                    facts.from_yaml2(&data);
-                }
+    /// Vars files of the workspace, merged after the collection ones.
+    ///
+    /// A folder directly under `vars/` holds the files of one stage, e.g. `vars/dev/`: only the folder
+    /// of the `STAGE` argument is used, every folder when no stage is given.
+    pub fn workspace_vars_files(&mut self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let vars_folder = std::path::Path::new(&self.workspace_path()).join("vars");
+        let stage = self.stage();
+        Ok(files_and_dirs::find_files_by_regex(self.workspace_path(), r".*/vars/.*\.yaml$")?
+            .into_iter()
+            .filter(|file| {
+                let Some(stage) = stage.as_deref() else { return true };
+                let Ok(relative) = std::path::Path::new(file).strip_prefix(&vars_folder) else { return true };
+                let components = relative.components().collect::<Vec<_>>();
+                components.len() < 2 || components[0].as_os_str() == stage
+            })
+            .collect())
+    }
 
-                match config_proc::process_playbook(&self.playbook_full_path(), data) {
-                    Ok(playbook_str) => {
-                        self.playbook = match serde_yaml::from_str(&playbook_str) {
-                            Ok(playbook) => {
-                                //println!("playbook: {:#?}", playbook);
-                                playbook
-                            },
-                            Err(err) => {
-                                // Handle deserialization errors from serde_yaml
-                                if let Some(location) = err.location() {
-                                    let snippet = yaml_handler::get_error_snippet(&playbook_str, location.line(), location.column());
-                                    print_error!("processing playbook: {}\nSnippet:\n{}", err, snippet);
-                                } else {
-                                    print_error!("processing playbook: {}", err);
-                                }
-                                panic!();
-                            }
-                        };
-
-                        {
-                            let mut facts = FACTS.write().unwrap();
-                            facts.context.insert("settings", &self.playbook.settings);
-                            //println!("facts.context: {:#?}", facts.context);
-                        } 
+    /// The `CHGOPS_LOG` filter of the workspace, showing the template renders and timings at `-vvv`.
+    pub fn log_filter(&self) -> log::LogFilter {
+        let mut filter = log::LogFilter::from_env();
+        if self.verbose >= Verbose::VVV {
+            filter.enable("template", log::Level::Trace);
+            filter.enable("timing", log::Level::Trace);
+        }
+        filter
+    }
 
-                    },
-                    Err(err) => {
-                        print_error!("ERROR: processing playbook: {}", err);
-                    }
-                }
+    /// Checks the playbooks and the vars files of the workspace and its collections against their schemas.
+    pub fn validate(&mut self) -> Result<Vec<validate::ValidationIssue>, Box<dyn std::error::Error>> {
+        let scope = log::LogScope { filter: self.log_filter(), ..Default::default() };
+        log::scoped(scope, || {
+            let collection_files = self.collection_files()?;
+            let workspace_files = self.workspace_vars_files()?;
+            let playbooks = self.playbook_files()?;
+            Ok(validate_sources(&collection_files, &workspace_files, &playbooks, &self.vault, &self.tasks, &self.collections))
+        })
+    }
 
-            },
-            Err(err) => {
-                print_error!("ERROR: processing configuration files: {}", err);
+    /// Resolves the collections, validates the sources, renders the vars and parses the playbook.
+    ///
+    /// The secrets found on the way are masked in the log messages of the load.
+    pub fn load(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.facts.secrets = vault::SharedSecrets::default();
+        let scope = log::LogScope { filter: self.log_filter(), secrets: self.facts.secrets.clone() };
+        log::scoped(scope, || self.load_sources())
+    }
+
+    fn load_sources(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let start = std::time::Instant::now();
+        let list_of_files_in_collection = self.collection_files()
+            .map_err(|err| format!("Error finding files in collection: {}", err))?;
+        if list_of_files_in_collection.is_empty() {
+            return Err("No files found in collection".into());
+        }
+
+        let list_of_files_in_workspace = self.workspace_vars_files()
+            .map_err(|err| format!("Error finding files in workspace: {}", err))?;
+        if list_of_files_in_workspace.is_empty() {
+            return Err("No 'vars' files found in workspace".into());
+        }
+
+        let issues = validate_sources(&list_of_files_in_collection, &list_of_files_in_workspace, &[self.playbook_full_path()], &self.vault, &self.tasks, &self.collections);
+        if !issues.is_empty() {
+            return Err(Box::new(validate::ValidationFailed(issues)));
+        }
+
+        let data = config_proc::process_configuration_files(
+            list_of_files_in_collection, 
+            list_of_files_in_workspace,
+            self.dump_dir(),
+            &self.vault)
+            .map_err(|err| format!("processing configuration files: {}", err))?;
+        log_debug!("timing", "vars merged and rendered in {:?}", start.elapsed());
+        // we can now process the playbook
+        self.facts.from_yaml2(&data);
+        let secrets = self.secrets();
+        self.facts.secrets.write().unwrap().extend(&secrets);
+
+        let playbook_str = config_proc::process_playbook(&self.playbook_full_path(), data, self.dump_dir(), &self.facts.secrets.read().unwrap())
+            .map_err(|err| format!("processing playbook: {}", err))?;
+        self.playbook = Playbook::parse(&playbook_str, &self.tasks, &self.collections)
+            .map_err(|err| format!("processing playbook: {}", err))?;
+        self.facts.secrets.write().unwrap().extend(&self.playbook.secrets());
+
+        self.facts.context.insert("settings", &self.playbook.settings);
+        log_debug!("timing", "playbook {} loaded in {:?}", self.playbook.name, start.elapsed());
+        Ok(())
+    }
+
+    /// [`load`](Self::load) for the command line: reports the errors, false when the workspace did not load.
+    pub fn load_workspace(&mut self) -> bool {
+        if let Err(err) = self.load() {
+            match err.downcast_ref::<validate::ValidationFailed>() {
+                Some(failed) => {
+                    for issue in failed.0.iter() {
+                        print_error!("{}", issue);
+                    }
+                    print_error!("{} validation errors, see `chgops validate`", failed.0.len());
+                },
+                None => print_error!("{}", err),
             }
+            return false;
+        }
+        true
+    }
+
+    /// The context a run of the loaded playbook starts with: the facts, the options and the `--mocks`,
+    /// without observers.
+    pub fn run_context(&self) -> Result<RunContext, Box<dyn std::error::Error>> {
+        let mut context = RunContext::new(self.facts.clone());
+        // the secrets the run learns stay with the run
+        context.facts.secrets = self.facts.secrets.read().unwrap().clone().shared();
+        context.settings = self.playbook.settings.clone();
+        context.verbose = self.verbose;
+        context.diff = self.diff;
+        context.options = RunOptions {
+            non_interactive: RunOptions::detect_non_interactive(self.non_interactive),
+            arguments: self.arguments_map(),
         };
+        context.mocks = mock::load(&self.mocks)?;
+        context.collections = self.collections.clone();
+        context.log_filter = self.log_filter();
+        Ok(context)
+    }
 
+    /// Runs the loaded playbook from the command line, shown by the sink of the `--output-format`.
+    pub fn run_playbook(&mut self) -> Result<report::RunReport, Box<dyn std::error::Error>> {
+        let mut context = self.run_context()
+            .and_then(|mut context| {
+                context.observers.add(sinks::for_format(&self.output_format, self.verbose, self.diff, context.facts.secrets.clone())?);
+                Ok(context)
+            })
+            .inspect_err(|err| print_error!("Refusing to run playbook: {}", err))?;
+        self.run_playbook_with(&mut context)
     }
 
-    pub fn run_playbook(&mut self) {
+    /// [`execute`](Self::execute) for the command line: reports why a run did not start.
+    pub fn run_playbook_with(&mut self, context: &mut RunContext) -> Result<report::RunReport, Box<dyn std::error::Error>> {
+        self.execute(context).inspect_err(|err| print_error!("Refusing to run playbook: {}", err))
+    }
 
-        
+    /// Runs the loaded playbook in `context`, which holds the facts and mock calls afterwards.
+    ///
+    /// Fails only when the run cannot start; failed tasks are reported in the returned report.
+    /// The log messages of the run follow the `log_filter` of the context and mask its secrets.
+    pub fn execute(&mut self, context: &mut RunContext) -> Result<report::RunReport, Box<dyn std::error::Error>> {
+        let scope = log::LogScope { filter: context.log_filter.clone(), secrets: context.facts.secrets.clone() };
+        log::scoped(scope, || self.execute_tasks(context))
+    }
+
+    fn execute_tasks(&mut self, context: &mut RunContext) -> Result<report::RunReport, Box<dyn std::error::Error>> {
+        self.validate_change()?;
+
+        if !context.mocks.mocks.is_empty() {
+            print_warning!("{} task mocks loaded, mocked tasks will not execute", context.mocks.mocks.len());
+        }
 
+        self.summary = PlaybookSummary::new();
         self.summary.set_start_time();
-        self.start_banner();
+        let engine = self.engine_parameters(context);
+        context.observers.playbook_start(&engine, &self.playbook);
 
-        self.playbook.run_tasks(Some(self.verbose.clone()));
+        self.playbook.run_tasks(context);
         
-        // generate summary
+        // generate summary, tasks after an abort never started
         for task in self.playbook.tasks.iter() {
             let output = task.output();
-            self.summary.increment_as_task(output);
+            if output.start_time.is_some() {
+                self.summary.increment_as_task(output);
+            }
         }
         
-        self.end_banner();
-    }
-
-    pub fn start_banner(&mut self) {
-        print_banner_yellow!("ChgOps - Change management and operations tool");
+        self.summary.set_end_time();
+        context.observers.playbook_end(&self.summary);
 
-        println!("Engine Parameters ###########################");
+        for unused in context.mocks.unused() {
+            print_warning!("Mock never used: {}", unused);
+        }
 
-        println!("\tPlaybook Name: {}", &self.playbook_name);
-        println!("\tWorkspace Path: {}", self.workspace_path());
-        println!("\tVerbose: {}", &self.verbose);
-        println!("\tArguments: {}", &self.arguments);
-        println!("\tFiles information:");
-        println!("\t\tCurrent Dir: {}", &self.current_dir);
-        println!("\t\tPlaybook Full Path: {}", self.playbook_full_path());
-        println!("\t\tConfigurations Full Path: {}", self.config_full_path());
-        println!("#############################################");
+        // the report and the history keep no secret values
+        let report = report::RunReport::from_workspace(self, engine).masked(&context.facts.secrets.read().unwrap());
+        if !self.report.is_empty() {
+            if let Err(err) = report.write(&self.report) {
+                print_error!("writing run report: {}", err);
+            }
+        }
 
-        self.playbook.display(Some(self.verbose.clone()));
+        if self.history {
+            let record = history::RunRecord::new(report.clone());
+            match history::RunStore::new(&self.runs_path()).save(&record) {
+                Ok(_) => eprint_info!("Run recorded: {}", record.run_id),
+                Err(err) => print_error!("recording run history: {}", err),
+            }
+        }
+        Ok(report)
     }
 
-    pub fn end_banner(&mut self) {
-        self.summary.set_end_time();
+    /// Values never shown: those of the decrypted vars files, of the variables the collections declare `secret`
+    /// and of the vars named like a secret, e.g. `db.password`.
+    pub fn secrets(&self) -> vault::Secrets {
+        let mut secrets = self.vault.secrets(&self.facts.yaml);
+        secrets.add_named(&self.facts.yaml);
+        for (name, (_, variable)) in validate::declared_variables(&self.collections).unwrap_or_default() {
+            if let Some(value) = self.facts.yaml.get(&name).filter(|_| variable.secret) {
+                secrets.add_yaml(value);
+            }
+        }
+        if !secrets.is_empty() {
+            log_debug!("config", "{} secret values masked", secrets.len());
+        }
+        secrets
+    }
 
-        self.summary.display();
-        print_banner_yellow!("ChgOps - End of execution");
+    /// Parameters of the run in `context`, as the start event and the report give them.
+    pub fn engine_parameters(&mut self, context: &RunContext) -> report::EngineParameters {
+        report::EngineParameters {
+            playbook_name: self.playbook_name.clone(),
+            workspace_path: self.workspace_path(),
+            current_dir: self.current_dir.clone(),
+            verbose: self.verbose.to_string(),
+            arguments: self.arguments.clone(),
+            diff: self.diff,
+            change_id: self.change_id.clone(),
+            non_interactive: context.options.non_interactive,
+            playbook_full_path: self.playbook_full_path(),
+            config_full_path: self.config_full_path(),
+        }
     }
 
     pub fn display(&mut self) {
@@ -291,7 +566,20 @@ impl ChgOpsWorkspace {
 
 
 
-#[derive(Debug, Deserialize, Default, Serialize)]
+/// Validation of the source files, in the order a run reads them: vars, then playbooks.
+fn validate_sources(collection_files: &[String], workspace_files: &[String], playbooks: &[String], vault: &vault::Vault, registry: &task_registry::TaskRegistry, collections: &[(String, String)]) -> Vec<validate::ValidationIssue> {
+    let vars_files = collection_files.iter().chain(workspace_files.iter()).cloned().collect::<Vec<_>>();
+    let mut issues = match validate::declared_variables(collections) {
+        Ok(declared) => validate::validate_vars(&vars_files, &declared, vault),
+        Err(err) => vec![validate::ValidationIssue { file: collection::MANIFEST_FILE.to_string(), line: 1, column: 1, message: err.to_string() }],
+    };
+    for playbook in playbooks.iter() {
+        issues.extend(validate::validate_playbook(playbook, registry, collections));
+    }
+    issues
+}
+
+#[derive(Debug, Deserialize, Default, Serialize, Clone)]
 pub struct PlaybookSummary {
     pub tasks_counter: i32,
     pub success_counter: i32,
@@ -407,49 +695,93 @@ impl WorkspaceVariables {
     }
 }
 
-#[derive(Debug, Deserialize, Default, Serialize)]
+#[derive(Debug, Deserialize, Default, Serialize, Clone, JsonSchema)]
 pub struct Settings {
     pub name: String,
     pub vars: Option<HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<change::ChangeTicket>,
 }
 
-#[derive(Debug, Deserialize, Default, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct Playbook {
     pub name: String,
     pub settings: Settings,
-
     pub tasks: Vec<PlaybookTasks>,
 
 }
 
+// a playbook as written, before its tasks are parsed into their types
+#[derive(Deserialize)]
+struct PlaybookSource {
+    name: String,
+    settings: Settings,
+    tasks: Vec<serde_yaml::Value>,
+}
+
 impl Playbook {
+    /// Parses a rendered playbook; its task types are those of `registry` and the modules of `collections`.
+    pub fn parse(text: &str, registry: &task_registry::TaskRegistry, collections: &[(String, String)]) -> Result<Playbook, String> {
+        let source: PlaybookSource = serde_yaml::from_str(text).map_err(|err| match err.location() {
+            Some(location) => {
+                let snippet = yaml_handler::get_error_snippet(text, location.line(), location.column());
+                format!("{}\nSnippet:\n{}", err, snippet)
+            },
+            None => err.to_string(),
+        })?;
+        Ok(Playbook {
+            name: source.name,
+            settings: source.settings,
+            tasks: registry.parse_tasks(source.tasks, collections)?,
+        })
+    }
+
+    /// The values the tasks declare secret, e.g. the login secret of `dx.azure.login`.
+    pub fn secrets(&self) -> vault::Secrets {
+        let mut secrets = vault::Secrets::default();
+        for value in self.tasks.iter().flat_map(|task| task.secrets()) {
+            secrets.add(&value);
+        }
+        secrets
+    }
+
     pub fn new(name: &str, settings: Settings, tasks: Vec<PlaybookTasks>) -> Playbook {
         Playbook {
             name: name.to_string(),
-            settings: settings,
-            tasks: tasks
+            settings,
+            tasks
         }
     }
 
-    pub fn display(&self, verbose: Option<String>) {
-        let verbose = verbose.unwrap_or("".to_string());
+    pub fn display(&self, verbose: Verbose, secrets: &vault::Secrets) {
         println!("Playbook: {} #####################################", self.name);
-        if verbose.len() >= 1  {
-            println!("\tSettings: {:?}", self.settings);
+        if verbose >= Verbose::V {
+            println!("\tSettings: {}", secrets.mask(&format!("{:?}", self.settings)));
         }
-        if verbose.len() >= 2 {
-            println!("\tTasks: {:?}", self.tasks);
+        if verbose >= Verbose::VV {
+            let tasks = self.tasks.iter().map(|task| match task.info() {
+                info if info.no_log => format!("{} {:?}: {}", info.task_type, info.name, NO_LOG_TEXT),
+                _ => format!("{:?}", task),
+            }).collect::<Vec<_>>();
+            println!("\tTasks: [{}]", secrets.mask(&tasks.join(", ")));
         }
         println!("\tTasks count: {:?}", self.tasks.len());
         println!("#############################################");
     }
 
-    pub fn run_tasks(&mut self, verbose: Option<String>) {
+    pub fn run_tasks(&mut self, context: &mut RunContext) {
 
         for task in self.tasks.iter_mut() {
-            task.execute();
-            // TODO: display task output when needed
-            task.display(verbose.clone());
+            context.observers.task_start(task.0.as_ref());
+            task.execute_or_mock(context);
+            let output = task.output();
+            match output.skipped > 0 {
+                true => context.observers.task_skipped(task.0.as_ref()),
+                false => context.observers.task_end(task.0.as_ref()),
+            }
+            if output.abort {
+                break;
+            }
         }
 
     }
@@ -471,11 +803,54 @@ pub struct PlaybookCommandOutput {
 
     pub data: Option<serde_yaml::Value>,
 
+    #[serde(default)]
+    pub diff: Option<PlaybookCommandDiff>,
+
+    /// Set by a task to stop the playbook after it.
+    #[serde(default)]
+    pub abort: bool,
+
+    /// The output came from a mock, the task did not execute.
+    #[serde(default)]
+    pub mocked: bool,
+
     pub start_time: Option<DateTime<Utc>>,
     pub end_time: Option<DateTime<Utc>>,
 }
 
 impl PlaybookCommandOutput {
+    /// The output with the secrets masked in what the task wrote; counters and times are kept as they are.
+    pub fn masked(&self, secrets: &vault::Secrets) -> PlaybookCommandOutput {
+        let mut data = self.data.clone();
+        if let Some(data) = data.as_mut() {
+            secrets.mask_yaml(data);
+        }
+        PlaybookCommandOutput {
+            stdout: secrets.mask(&self.stdout),
+            stderr: secrets.mask(&self.stderr),
+            message: secrets.mask(&self.message),
+            data,
+            diff: self.diff.as_ref().map(|diff| PlaybookCommandDiff {
+                before: secrets.mask(&diff.before),
+                after: secrets.mask(&diff.after),
+                unified: secrets.mask(&diff.unified),
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// The output of a `no_log` task as it is shown and stored: the counters and times, without what the task wrote.
+    pub fn censored(&self) -> PlaybookCommandOutput {
+        let hidden = |text: &str| if text.is_empty() { String::new() } else { NO_LOG_TEXT.to_string() };
+        PlaybookCommandOutput {
+            stdout: hidden(&self.stdout),
+            stderr: hidden(&self.stderr),
+            data: None,
+            diff: None,
+            ..self.clone()
+        }
+    }
+
     pub fn new() -> PlaybookCommandOutput {
         PlaybookCommandOutput {
             stdout: "".to_string(),
@@ -487,11 +862,33 @@ impl PlaybookCommandOutput {
             skipped: 0,
             changed: 0,
             data: None,
+            diff: None,
+            abort: false,
+            mocked: false,
             start_time: None,
             end_time: None,
         }
     }
 
+    /// Output from a partial document such as `{ stdout: "...", changed: 1 }`: a successful run unless it says otherwise.
+    pub fn from_partial(fields: serde_yaml::Value) -> Result<PlaybookCommandOutput, Box<dyn std::error::Error>> {
+        let mut output = PlaybookCommandOutput::new();
+        output.status = 1;
+        output.success = 1;
+
+        let mut value = serde_yaml::to_value(&output)?;
+        if let (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(fields)) = (&mut value, &fields) {
+            for (key, field) in fields.iter() {
+                base.insert(key.clone(), field.clone());
+            }
+        }
+        let mut output: PlaybookCommandOutput = serde_yaml::from_value(value)?;
+        if output.failed > 0 {
+            output.success = 0;
+        }
+        Ok(output)
+    }
+
     pub fn set_start_time(&mut self) {
         self.start_time = Some(Utc::now());
     }
@@ -500,7 +897,8 @@ impl PlaybookCommandOutput {
         self.end_time = Some(Utc::now());
     }
 
-    pub fn display(&self) {
+    /// Prints the output, with its diff when it has changes or `diff` asks for it.
+    pub fn display(&self, diff: bool) {
         println!("####### Playbook Command Output ##########");
         println!("\tstdout: {:?}", self.stdout);
         println!("\tstderr: {:?}", self.stderr);
@@ -510,6 +908,9 @@ impl PlaybookCommandOutput {
         println!("\tfailed: {:?}", self.failed);
         println!("\tskipped: {:?}", self.skipped);
         println!("\tchanged: {:?}", self.changed);
+        if diff || self.diff.as_ref().is_some_and(|diff| diff.has_changes()) {
+            self.display_diff();
+        }
 
         let start_time_formatted = self.start_time.unwrap().format("%Y-%m-%d %H:%M:%S").to_string();
         let end_time_formatted = self.end_time.unwrap().format("%Y-%m-%d %H:%M:%S").to_string();
@@ -520,6 +921,44 @@ impl PlaybookCommandOutput {
         println!("#############################################");        
     }
 
+    pub fn set_diff(&mut self, before: &str, after: &str) {
+        let diff = PlaybookCommandDiff::new(before, after);
+        if diff.has_changes() {
+            self.diff = Some(diff);
+        }
+    }
+
+    /// Prints the task banner and its whole output.
+    pub fn display_task(&self, name: &str) {
+        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***",
+            name,
+            self.status,
+            self.success,
+            self.failed,
+            self.skipped,
+            self.changed
+        );
+        self.display_streams(None, &vault::Secrets::default());
+    }
+
+    /// Prints stdout and stderr with the secrets masked, each cut to its last `max_lines` lines when given.
+    pub fn display_streams(&self, max_lines: Option<usize>, secrets: &vault::Secrets) {
+        if !self.stdout.is_empty() {
+            print_banner_green!("=== Output ===");
+            print_success!("{}", secrets.mask(&tail_lines(&self.stdout, max_lines)));
+        }
+        if !self.stderr.is_empty() {
+            print_banner_red!("=== Errors ===");
+            print_error!("{}", secrets.mask(&tail_lines(&self.stderr, max_lines)));
+        }
+    }
+
+    pub fn display_diff(&self) {
+        if let Some(diff) = &self.diff {
+            diff.display();
+        }
+    }
+
     pub fn duration(&self) -> String {
         let duration1 = self.end_time.unwrap().signed_duration_since(self.start_time.unwrap());
         // set duration to a human readable format
@@ -529,15 +968,89 @@ impl PlaybookCommandOutput {
     }
 }
 
+/// Lines of task output shown below `-vv`.
+pub const OUTPUT_TAIL_LINES: usize = 20;
+
+/// What is shown instead of the command, vars and output of a `no_log` task.
+pub const NO_LOG_TEXT: &str = "(output hidden, no_log is set)";
+
+fn tail_lines(text: &str, max_lines: Option<usize>) -> String {
+    let lines = text.lines().collect::<Vec<_>>();
+    match max_lines {
+        Some(max) if lines.len() > max => format!("... {} lines hidden, -vv shows them\n{}", lines.len() - max, lines[lines.len() - max..].join("\n")),
+        _ => text.to_string(),
+    }
+}
+
+/// Before and after state of a resource touched by a task, rendered for `run --diff`.
+#[derive(Debug, Deserialize, Default, Serialize, Clone)]
+pub struct PlaybookCommandDiff {
+    pub before: String,
+    pub after: String,
+    pub unified: String,
+}
+
+impl PlaybookCommandDiff {
+    pub fn new(before: &str, after: &str) -> PlaybookCommandDiff {
+        let unified = similar::TextDiff::from_lines(before, after)
+            .unified_diff()
+            .context_radius(3)
+            .header("before", "after")
+            .to_string();
+
+        PlaybookCommandDiff {
+            before: before.to_string(),
+            after: after.to_string(),
+            unified,
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.before != self.after
+    }
+
+    pub fn display(&self) {
+        print_banner_yellow!("=== Diff ===");
+        for line in self.unified.lines() {
+            if line.starts_with("+++") || line.starts_with("---") {
+                println!("{}", line);
+            } else if line.starts_with('+') {
+                print_banner_green!("{}", line);
+            } else if line.starts_with('-') {
+                print_banner_red!("{}", line);
+            } else if line.starts_with("@@") {
+                print_banner_blue!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }
+}
+
 pub trait PlaybookCommandTrait {
-    fn execute(&mut self);
-    fn display(&self, verbose: Option<String>);
+    fn execute(&mut self, context: &mut RunContext);
+    /// Prints the task and its output, with the secrets masked.
+    fn display(&self, verbose: Verbose, secrets: &vault::Secrets);
     fn output(&self) -> PlaybookCommandOutput;
+    /// Values of the task never shown, besides the workspace secrets.
+    fn secrets(&self) -> Vec<String> {
+        vec![]
+    }
 }
 
 
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+/// Identifies a task in summaries and reports.
+#[derive(Debug, Deserialize, Default, Serialize, Clone)]
+pub struct PlaybookTaskInfo {
+    pub name: String,
+    pub task_type: String,
+    pub register: Option<String>,
+    #[serde(default)]
+    pub no_log: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, JsonSchema)]
 pub struct PlaybookCommand<COMMAND, VARS> {
     pub command: COMMAND,
     pub name: Option<String>,
@@ -545,42 +1058,157 @@ pub struct PlaybookCommand<COMMAND, VARS> {
     pub register: Option<String>,
     pub state: Option<String>,
     pub when: Option<String>,
+    /// hides the command, vars and output of the task in the console, events, reports and history
+    #[serde(default)]
+    pub no_log: bool,
 
     #[serde(skip_deserializing)]
+    #[schemars(skip)]
     pub output: PlaybookCommandOutput,
+
+    /// registered name, e.g. `dx.core.bash`, set by the task registry
+    #[serde(skip)]
+    pub task_type: String,
 }
 
 
-// adding more commands to the overall playbook processing is here
-// just add the new command module to the PlaybookTasks enum
+impl<COMMAND, VARS> PlaybookCommand<COMMAND, VARS> {
+    pub fn info(&self, task_type: &str) -> PlaybookTaskInfo {
+        PlaybookTaskInfo {
+            name: self.name.clone().unwrap_or("Unnamed".to_string()),
+            task_type: task_type.to_string(),
+            register: self.register.clone(),
+            no_log: self.no_log,
+        }
+    }
+}
 
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(untagged)]
-pub enum PlaybookTasks {
-    CoreTasks(crate::collections::dx::core::tasks::CoreTasks),
-    AzureTasks(crate::collections::dx::azure::tasks::AzureTasks),
+impl<COMMAND: std::fmt::Debug, VARS: std::fmt::Debug> PlaybookCommand<COMMAND, VARS> {
+    /// Standard task banner, by level: the command and vars at `-v`, the whole output object
+    /// at `-vv`, the duration at `-vvv`; below `-vv` only the last lines of the outputs.
+    pub fn display_default(&self, verbose: Verbose, secrets: &vault::Secrets) {
+        print_banner_blue!("TASK: *** {} *** [St.:{}/Succ.:{}/Fail:{}/Skip:{}/Chg:{}] ***",
+            self.name.as_ref().unwrap_or(&"Unnamed".to_string()),
+            self.output.status,
+            self.output.success,
+            self.output.failed,
+            self.output.skipped,
+            self.output.changed
+        );
+        if self.no_log {
+            print_info!("{}", NO_LOG_TEXT);
+            return;
+        }
+        if verbose >= Verbose::V {
+            print_info!("Command: {}", secrets.mask(&format!("{:?}", self.command)));
+            print_info!("Vars: {}", secrets.mask(&format!("{:?}", self.vars)));
+            if let Some(when) = &self.when {
+                print_info!("When: {}", secrets.mask(when));
+            }
+        }
+        if verbose >= Verbose::VV {
+            print_banner_yellow!("=== Output Obj ===");
+            print_info!("{}", secrets.mask(&format!("{:?}", self.output)));
+        }
+        else {
+            self.output.display_streams(Some(OUTPUT_TAIL_LINES), secrets);
+        }
+        if verbose >= Verbose::VVV {
+            if let (Some(start), Some(end)) = (self.output.start_time, self.output.end_time) {
+                print_info!("Duration: {} ms", end.signed_duration_since(start).num_milliseconds());
+            }
+        }
+    }
 }
 
 
-impl PlaybookCommandTrait for PlaybookTasks {
-    fn execute(&mut self) {
-        match self {
-            PlaybookTasks::CoreTasks(task) => task.execute(),
-            PlaybookTasks::AzureTasks(task) => task.execute(),
-        }
+// adding more commands to the overall playbook processing is here
+// register the new task types in task_registry::TaskRegistry::with_builtins
+
+/// A playbook task of any registered type.
+#[derive(Debug)]
+pub struct PlaybookTasks(pub Box<dyn task_registry::PlaybookTask>);
+
+impl Serialize for PlaybookTasks {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error, SerializeMap};
+        let body = self.0.to_value().map_err(S::Error::custom)?;
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.0.info().task_type, &body)?;
+        map.end()
+    }
+}
+
+impl PlaybookTasks {
+    pub fn info(&self) -> PlaybookTaskInfo {
+        self.0.info()
     }
 
-    fn display(&self, verbose: Option<String>) {
-        match self {
-            PlaybookTasks::CoreTasks(task) => task.display(verbose),
-            PlaybookTasks::AzureTasks(task) => task.display(verbose),
+    pub fn set_output(&mut self, output: PlaybookCommandOutput) {
+        self.0.set_output(output)
+    }
+
+    pub fn logged_output(&self) -> PlaybookCommandOutput {
+        self.0.logged_output()
+    }
+
+    pub fn command_text(&self) -> String {
+        self.0.command_text()
+    }
+
+    /// True when the `when` of the task rendered to false.
+    pub fn is_skipped(&self) -> bool {
+        self.0.when().as_deref() == Some("false")
+    }
+
+    /// Runs the task, unless the active mocks answer it or block it.
+    ///
+    /// A task skipped by its `when` skips itself: no mock is consulted and strict mode does not block it.
+    pub fn execute_or_mock(&mut self, context: &mut RunContext) {
+        if self.is_skipped() {
+            return self.execute(context);
         }
+        let info = self.info();
+        let decision = match context.mocks.decide(&info, &self.command_text()) {
+            Ok(decision) => decision,
+            Err(err) => mock::MockDecision::Blocked(format!("Invalid mock for task {}: {}", info.name, err)),
+        };
+        match decision {
+            mock::MockDecision::Run => self.execute(context),
+            mock::MockDecision::Mocked(output) => {
+                if let Some(register) = info.register.as_ref().filter(|r| !r.is_empty()) {
+                    context.register(register, &output);
+                }
+                self.set_output(*output);
+            },
+            mock::MockDecision::Blocked(message) => {
+                let mut output = PlaybookCommandOutput::new();
+                output.set_start_time();
+                output.stderr = message.clone();
+                output.message = message;
+                output.status = 1;
+                output.failed = 1;
+                output.set_end_time();
+                self.set_output(output);
+            },
+        }
+    }
+}
+
+impl PlaybookCommandTrait for PlaybookTasks {
+    fn execute(&mut self, context: &mut RunContext) {
+        self.0.execute(context)
+    }
+
+    fn display(&self, verbose: Verbose, secrets: &vault::Secrets) {
+        self.0.display(verbose, secrets)
     }
 
     fn output(&self) -> PlaybookCommandOutput {
-        match self {
-            PlaybookTasks::CoreTasks(task) => task.output(),
-            PlaybookTasks::AzureTasks(task) => task.output(),
-        }
+        self.0.output()
     }
-}
\ No newline at end of file
+
+    fn secrets(&self) -> Vec<String> {
+        self.0.secrets()
+    }
+}
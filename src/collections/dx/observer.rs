@@ -0,0 +1,108 @@
+// events of a playbook run, for callers that follow it while it happens
+
+use serde::Serialize;
+use crate::collections::dx::report::EngineParameters;
+use crate::collections::dx::task_registry::PlaybookTask;
+use crate::collections::dx::{Playbook, PlaybookSummary, PlaybookTaskInfo};
+
+/// Stream a chunk of task output was written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// How a message a task shows to the operator reads, the `command` of `dx.core.print`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageLevel {
+    Print,
+    Debug,
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl MessageLevel {
+    /// The level of a print command, `print` for the unknown ones.
+    pub fn from_command(command: &str) -> MessageLevel {
+        match command {
+            "debug" => MessageLevel::Debug,
+            "info" => MessageLevel::Info,
+            "success" => MessageLevel::Success,
+            "warning" => MessageLevel::Warning,
+            "error" => MessageLevel::Error,
+            _ => MessageLevel::Print,
+        }
+    }
+}
+
+/// Receives the events of a run; every event does nothing unless implemented.
+///
+/// Each task that starts ends with either `task_skipped` or `task_end`; tasks after an abort never start.
+pub trait RunObserver {
+    fn playbook_start(&mut self, _engine: &EngineParameters, _playbook: &Playbook) {}
+    fn task_start(&mut self, _task: &dyn PlaybookTask) {}
+    /// A chunk of output while the task runs, sent by the tasks that stream it (`dx.core.bash`, `dx.core.wincmd`).
+    fn task_output(&mut self, _task: &PlaybookTaskInfo, _stream: OutputStream, _chunk: &str) {}
+    /// A message for the operator, sent by `dx.core.print`.
+    fn task_message(&mut self, _task: &PlaybookTaskInfo, _level: MessageLevel, _message: &str) {}
+    fn task_skipped(&mut self, _task: &dyn PlaybookTask) {}
+    fn task_end(&mut self, _task: &dyn PlaybookTask) {}
+    fn playbook_end(&mut self, _summary: &PlaybookSummary) {}
+}
+
+/// Observer that ignores every event.
+#[derive(Debug, Default)]
+pub struct NoObserver;
+
+impl RunObserver for NoObserver {}
+
+/// The observers of a run, each receiving every event in the order they were added.
+#[derive(Default)]
+pub struct Observers(Vec<Box<dyn RunObserver>>);
+
+impl std::fmt::Debug for Observers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Observers {
+    pub fn add(&mut self, observer: Box<dyn RunObserver>) -> &mut Observers {
+        self.0.push(observer);
+        self
+    }
+}
+
+impl RunObserver for Observers {
+    fn playbook_start(&mut self, engine: &EngineParameters, playbook: &Playbook) {
+        self.0.iter_mut().for_each(|o| o.playbook_start(engine, playbook));
+    }
+
+    fn task_start(&mut self, task: &dyn PlaybookTask) {
+        self.0.iter_mut().for_each(|o| o.task_start(task));
+    }
+
+    fn task_output(&mut self, task: &PlaybookTaskInfo, stream: OutputStream, chunk: &str) {
+        self.0.iter_mut().for_each(|o| o.task_output(task, stream, chunk));
+    }
+
+    fn task_message(&mut self, task: &PlaybookTaskInfo, level: MessageLevel, message: &str) {
+        self.0.iter_mut().for_each(|o| o.task_message(task, level, message));
+    }
+
+    fn task_skipped(&mut self, task: &dyn PlaybookTask) {
+        self.0.iter_mut().for_each(|o| o.task_skipped(task));
+    }
+
+    fn task_end(&mut self, task: &dyn PlaybookTask) {
+        self.0.iter_mut().for_each(|o| o.task_end(task));
+    }
+
+    fn playbook_end(&mut self, summary: &PlaybookSummary) {
+        self.0.iter_mut().for_each(|o| o.playbook_end(summary));
+    }
+}
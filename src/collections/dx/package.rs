@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use crate::collections::dx::{dependencies, files_and_dirs};
+
+/// Name of the manifest stored at the root of every package.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PackageFile {
+    pub path: String,
+    pub sha256: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq)]
+pub struct PackageCollection {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+/// Describes a deployable unit produced by `chgops build`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PackageManifest {
+    pub name: String,
+    pub version: String,
+    pub change_id: String,
+    pub build_time: Option<DateTime<Utc>>,
+    pub chgops_version: String,
+    pub collections: Vec<PackageCollection>,
+    pub files: Vec<PackageFile>,
+}
+
+impl PackageManifest {
+    /// Package file name, e.g. `az_deixei-1_1_0-1234.zip`.
+    pub fn file_name(&self) -> String {
+        format!("{}-{}-{}.zip", self.name, self.version.replace('.', "_"), self.change_id)
+    }
+
+    pub fn display(&self) {
+        println!("Package: {} #####################################", self.file_name());
+        println!("\tName: {}", self.name);
+        println!("\tVersion: {}", self.version);
+        println!("\tChange Id: {}", self.change_id);
+        println!("\tBuild Time: {}", self.build_time.map(|t| t.to_rfc3339()).unwrap_or_default());
+        for collection in self.collections.iter() {
+            println!("\tCollection: {} {}", collection.name, collection.version.clone().unwrap_or("*".to_string()));
+        }
+        println!("\tFiles: {}", self.files.len());
+        println!("#############################################");
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Location of the workspace configuration: `config.yaml` or `vars/config.yaml`.
+pub fn workspace_config_path(workspace_path: &str) -> Option<String> {
+    [format!("{}/config.yaml", workspace_path), format!("{}/vars/config.yaml", workspace_path)]
+        .into_iter()
+        .find(|path| Path::new(path).exists())
+}
+
+fn relative_files(root: &Path) -> Result<Vec<(String, PathBuf)>, Box<dyn Error>> {
+    let mut files = vec![];
+    if !root.exists() {
+        return Ok(files);
+    }
+    for file in files_and_dirs::list_all_files_and_dirs(root.to_str().unwrap().to_string())? {
+        let path = PathBuf::from(&file);
+        let relative = path.strip_prefix(root)?.to_path_buf();
+        // skip local state such as .chgops and .git
+        if relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.')) {
+            continue;
+        }
+        let relative = relative.components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/");
+        files.push((relative, path));
+    }
+    Ok(files)
+}
+
+/// Packs a workspace and the collections it resolves to into `<output_dir>/<name>-<version>-<change_id>.zip`.
+///
+/// The workspace files sit at the root of the archive and collections under `collections/<namespace>/<name>`,
+/// so an unpacked package is a self-contained workspace.
+pub fn build_package(workspace_path: &str, collections_path: &str, name: &str, version: &str, change_id: &str, output_dir: &str) -> Result<(String, PackageManifest), Box<dyn Error>> {
+    let workspace = Path::new(workspace_path);
+    if !workspace.is_dir() {
+        return Err(format!("Workspace not found: {}", workspace_path).into());
+    }
+
+    let mut entries: Vec<(String, PathBuf)> = relative_files(workspace)?
+        .into_iter()
+        .filter(|(relative, _)| !relative.starts_with("collections/") && relative != MANIFEST_FILE)
+        .collect();
+
+    let collections_root = Path::new(collections_path);
+    let declared = dependencies::read_dependencies(workspace_path)?.unwrap_or_default();
+    let mut collections = vec![];
+    if declared.collections.is_empty() {
+        for (relative, path) in relative_files(collections_root)? {
+            entries.push((format!("collections/{}", relative), path));
+        }
+    } else {
+        for collection in dependencies::resolve_workspace(workspace_path, collections_path, &declared, false)? {
+            let (namespace, collection_name) = collection.name.split_once('.')
+                .ok_or_else(|| format!("Invalid collection name: {}. Expected format: namespace.collection", collection.name))?;
+            let collection_folder = PathBuf::from(&collection.path);
+            // namespace level files (vars.yaml, README.md) belong to every collection of the namespace
+            if let Some(namespace_folder) = collection_folder.parent() {
+                for entry in fs::read_dir(namespace_folder)? {
+                    let path = entry?.path();
+                    if path.is_file() {
+                        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+                        entries.push((format!("collections/{}/{}", namespace, file_name), path));
+                    }
+                }
+            }
+            for (relative, path) in relative_files(&collection_folder)? {
+                entries.push((format!("collections/{}/{}/{}", namespace, collection_name, relative), path));
+            }
+            collections.push(PackageCollection {
+                name: collection.name.clone(),
+                version: collection.version.clone(),
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let mut manifest = PackageManifest {
+        name: name.to_string(),
+        version: version.to_string(),
+        change_id: change_id.to_string(),
+        build_time: Some(Utc::now()),
+        chgops_version: env!("CARGO_PKG_VERSION").to_string(),
+        collections,
+        files: vec![],
+    };
+
+    let mut contents = vec![];
+    for (relative, path) in entries {
+        let bytes = fs::read(&path)?;
+        manifest.files.push(PackageFile {
+            path: relative.clone(),
+            sha256: sha256_hex(&bytes),
+            size: bytes.len() as u64,
+        });
+        contents.push((relative, bytes));
+    }
+
+    fs::create_dir_all(output_dir)?;
+    let package_path = format!("{}/{}", output_dir, manifest.file_name());
+    let mut zip = zip::ZipWriter::new(File::create(&package_path)?);
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    for (relative, bytes) in contents {
+        zip.start_file(relative, options)?;
+        zip.write_all(&bytes)?;
+    }
+    zip.finish()?;
+
+    Ok((package_path, manifest))
+}
+
+pub fn read_manifest(package_path: &str) -> Result<PackageManifest, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(File::open(package_path)?)?;
+    let mut file = archive.by_name(MANIFEST_FILE)
+        .map_err(|e| format!("Package {} has no {}: {}", package_path, MANIFEST_FILE, e))?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Extracts a package into `destination`, checking every file against the manifest hashes.
+pub fn unpack_package(package_path: &str, destination: &str) -> Result<PackageManifest, Box<dyn Error>> {
+    let manifest = read_manifest(package_path)?;
+    let mut archive = zip::ZipArchive::new(File::open(package_path)?)?;
+
+    // verify everything before writing anything
+    let mut contents = vec![];
+    for file in manifest.files.iter() {
+        let mut entry = archive.by_name(&file.path)
+            .map_err(|e| format!("Package {} is missing {}: {}", package_path, file.path, e))?;
+        let mut bytes = vec![];
+        entry.read_to_end(&mut bytes)?;
+        let hash = sha256_hex(&bytes);
+        if hash != file.sha256 {
+            return Err(format!("Hash mismatch for {} in {}: expected {}, found {}", file.path, package_path, file.sha256, hash).into());
+        }
+        let target = Path::new(destination).join(&file.path);
+        if !target.starts_with(destination) || file.path.split('/').any(|part| part == "..") {
+            return Err(format!("Refusing to extract {} outside of {}", file.path, destination).into());
+        }
+        contents.push((target, bytes));
+    }
+
+    for (target, bytes) in contents {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, bytes)?;
+    }
+    fs::write(Path::new(destination).join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+
+    Ok(manifest)
+}
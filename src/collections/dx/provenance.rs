@@ -0,0 +1,215 @@
+// where every value of the merged facts comes from: the vars files, in the order they are merged
+
+use serde::Serialize;
+use serde_yaml::Value as YamlValue;
+use std::collections::BTreeMap;
+use std::error::Error;
+use tera::Context;
+use crate::collections::dx::validate::SourceMap;
+use crate::collections::dx::config_proc;
+use crate::collections::dx::vault::Vault;
+
+/// A vars file setting a value.
+#[derive(Debug, Clone, Serialize)]
+pub struct Origin {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// the value as written, before templates are rendered
+    pub value: YamlValue,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Every layer that set each path of the merged facts, e.g. `tags.Owner` or `locations[1]`, in merge order.
+///
+/// Mappings merge key by key; any other value replaces what earlier layers set below its path.
+#[derive(Debug, Default, Clone)]
+pub struct Provenance {
+    layers: BTreeMap<String, Vec<Origin>>,
+}
+
+fn child_path(parent: &str, key: &str) -> String {
+    match parent.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", parent, key),
+    }
+}
+
+fn is_below(path: &str, parent: &str) -> bool {
+    path.len() > parent.len()
+        && path.starts_with(parent)
+        && matches!(path.as_bytes()[parent.len()], b'.' | b'[')
+}
+
+impl Provenance {
+    pub fn new() -> Provenance {
+        Provenance::default()
+    }
+
+    /// Records the files in merge order; files that are not valid YAML are skipped, the merge reports them.
+    pub fn from_files(files: &[String], vault: &Vault) -> Result<Provenance, Box<dyn Error>> {
+        let mut provenance = Provenance::new();
+        for file in files.iter() {
+            let content = vault.read(file)?;
+            if let Ok((value, map)) = SourceMap::parse(file, &content) {
+                provenance.add_layer(&value, &map);
+            }
+        }
+        Ok(provenance)
+    }
+
+    /// Records a vars file merged over the previous ones.
+    pub fn add_layer(&mut self, value: &YamlValue, map: &SourceMap) {
+        if let YamlValue::Mapping(mapping) = value {
+            for (key, child) in mapping.iter() {
+                if let Some(key) = key.as_str() {
+                    self.record(key, child, map);
+                }
+            }
+        }
+    }
+
+    fn record(&mut self, path: &str, value: &YamlValue, map: &SourceMap) {
+        if !value.is_mapping() {
+            self.layers.retain(|p, _| !is_below(p, path));
+        }
+        let (line, column) = map.position(path);
+        self.layers.entry(path.to_string()).or_default().push(Origin {
+            file: map.file.clone(),
+            line,
+            column,
+            value: value.clone(),
+        });
+
+        match value {
+            YamlValue::Mapping(mapping) => {
+                for (key, child) in mapping.iter() {
+                    if let Some(key) = key.as_str() {
+                        self.record(&child_path(path, key), child, map);
+                    }
+                }
+            },
+            YamlValue::Sequence(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    self.record(&format!("{}[{}]", path, index), item, map);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Every layer that set `path`, the last one wins.
+    pub fn layers(&self, path: &str) -> &[Origin] {
+        self.layers.get(path).map(|l| l.as_slice()).unwrap_or(&[])
+    }
+
+    /// The layer the value of `path` comes from, or of its closest parent set by the files.
+    pub fn origin(&self, path: &str) -> Option<&Origin> {
+        let mut path = path.to_string();
+        loop {
+            if let Some(origin) = self.layers.get(&path).and_then(|l| l.last()) {
+                return Some(origin);
+            }
+            let index = path.rfind(['.', '['])?;
+            path.truncate(index);
+        }
+    }
+
+    /// Paths starting with `prefix`, e.g. the keys of `tags`, sorted.
+    pub fn paths(&self, prefix: &str) -> Vec<String> {
+        self.layers.keys()
+            .filter(|p| prefix.is_empty() || p.as_str() == prefix || is_below(p, prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Renders the template strings of the merged facts one by one, returning the first that fails,
+    /// located in the file that set it.
+    pub fn failing_template(&self, context: &Context) -> Option<(String, Origin, String)> {
+        let mut templates = self.layers.iter()
+            .filter_map(|(path, layers)| layers.last().map(|origin| (path, origin)))
+            .filter(|(_, origin)| origin.value.as_str().map(|s| s.contains("{{") || s.contains("{%")).unwrap_or(false))
+            .collect::<Vec<_>>();
+        templates.sort_by(|a, b| (&a.1.file, a.1.line).cmp(&(&b.1.file, b.1.line)));
+
+        templates.into_iter().find_map(|(path, origin)| {
+            let template = origin.value.as_str().unwrap();
+            config_proc::render_template(template, context)
+                .err()
+                .map(|e| (path.clone(), origin.clone(), config_proc::error_chain(&e)))
+        })
+    }
+}
+
+/// The value at a dotted path such as `tags.Owner` or `locations[1]`.
+pub fn lookup<'a>(value: &'a YamlValue, path: &str) -> Option<&'a YamlValue> {
+    let mut current = value;
+    for segment in path.split('.') {
+        let (key, indexes) = match segment.find('[') {
+            Some(i) => (&segment[..i], &segment[i..]),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        for index in indexes.split(['[', ']']).filter(|i| !i.is_empty()) {
+            current = current.get(index.parse::<usize>().ok()?)?;
+        }
+    }
+    Some(current)
+}
+
+/// The scalar values below `value` by dotted path; empty mappings and lists count as values.
+pub fn leaves(value: &YamlValue, path: &str) -> BTreeMap<String, YamlValue> {
+    let mut leaves = BTreeMap::new();
+    match value {
+        YamlValue::Mapping(mapping) if !mapping.is_empty() => {
+            for (key, child) in mapping.iter() {
+                if let Some(key) = key.as_str() {
+                    leaves.extend(self::leaves(child, &child_path(path, key)));
+                }
+            }
+        },
+        YamlValue::Sequence(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                leaves.extend(self::leaves(item, &format!("{}[{}]", path, index)));
+            }
+        },
+        other => {
+            leaves.insert(path.to_string(), other.clone());
+        },
+    }
+    leaves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> YamlValue {
+        serde_yaml::from_str("tags:\n  Owner: demo@deixei.com\nlocations: [westeurope, northeurope]\nempty: {}\n").unwrap()
+    }
+
+    #[test]
+    fn lookup_follows_keys_and_indexes() {
+        let facts = facts();
+
+        assert_eq!(lookup(&facts, "tags.Owner"), Some(&YamlValue::String("demo@deixei.com".to_string())));
+        assert_eq!(lookup(&facts, "locations[1]"), Some(&YamlValue::String("northeurope".to_string())));
+        assert_eq!(lookup(&facts, "locations[2]"), None);
+        assert_eq!(lookup(&facts, "tags.Missing"), None);
+    }
+
+    #[test]
+    fn leaves_lists_every_scalar_by_path() {
+        let leaves = leaves(&facts(), "");
+
+        assert_eq!(leaves.keys().cloned().collect::<Vec<_>>(), vec!["empty", "locations[0]", "locations[1]", "tags.Owner"]);
+        assert_eq!(leaves["locations[0]"], YamlValue::String("westeurope".to_string()));
+    }
+}
@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use semver::{Version, VersionReq};
+use std::error::Error;
+use std::fs;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use crate::collections::dx::collection;
+use crate::collections::dx::package::{self, PackageManifest};
+
+/// Default registry location when neither `--registry` nor `CHGOPS_REGISTRY` is set.
+pub const DEFAULT_REGISTRY: &str = "./.chgops/registry";
+
+/// Checks a package name before it becomes a registry path: dot separated parts named like collections, e.g. `dx.azure`.
+pub fn validate_package_name(name: &str) -> Result<(), Box<dyn Error>> {
+    match name.split('.').all(collection::is_valid_name) {
+        true => Ok(()),
+        false => Err(format!("Invalid package name: {}. Expected letters, digits, '_' and '-' in dot separated parts, e.g. namespace.collection", name).into()),
+    }
+}
+
+/// Checks that a change id can be part of a package file name.
+pub fn validate_change_id(change_id: &str) -> Result<(), Box<dyn Error>> {
+    match collection::is_valid_name(change_id) {
+        true => Ok(()),
+        false => Err(format!("Invalid change id: {}. Expected letters, digits, '_' and '-', e.g. CHG-1234", change_id).into()),
+    }
+}
+
+/// Checks that a package file name stays in its version folder.
+fn validate_file_name(file: &str) -> Result<(), Box<dyn Error>> {
+    if file.is_empty() || file.contains(['/', '\\']) || file.contains("..") {
+        return Err(format!("Invalid package file name: {}", file).into());
+    }
+    Ok(())
+}
+
+/// A published version of a package, as listed in `<name>/index.json`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PackageVersion {
+    pub version: String,
+    pub file: String,
+    pub sha256: String,
+    pub change_id: String,
+    pub build_time: Option<DateTime<Utc>>,
+    pub published: Option<DateTime<Utc>>,
+    pub feed: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct PackageIndex {
+    pub name: String,
+    pub versions: Vec<PackageVersion>,
+}
+
+impl PackageIndex {
+    /// Picks the highest version matching `requirement`: "latest", an exact version or a semver range like "^1.2".
+    pub fn resolve(&self, requirement: &str) -> Result<PackageVersion, Box<dyn Error>> {
+        let requirement = requirement.trim();
+        let matcher = if requirement == "latest" || requirement == "*" {
+            VersionReq::STAR
+        } else if let Ok(exact) = Version::parse(requirement) {
+            VersionReq::parse(&format!("={}", exact))?
+        } else {
+            VersionReq::parse(requirement).map_err(|e| format!("Invalid version requirement: {}: {}", requirement, e))?
+        };
+
+        self.versions
+            .iter()
+            .filter_map(|entry| Version::parse(&entry.version).ok().map(|v| (v, entry)))
+            .filter(|(version, _)| matcher.matches(version))
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, entry)| entry.clone())
+            .ok_or_else(|| {
+                let known = self.versions.iter().map(|v| v.version.clone()).collect::<Vec<_>>().join(", ");
+                format!("No version of {} matches {} (published: [{}])", self.name, requirement, known).into()
+            })
+    }
+}
+
+/// Storage for packages. Layout, shared by every backend:
+///
+/// ```text
+/// <name>/index.json
+/// <name>/<version>/<name>-<version>-<change_id>.zip
+/// ```
+pub trait PackageRegistry {
+    fn location(&self) -> String;
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+    fn put(&self, path: &str, bytes: &[u8]) -> Result<(), Box<dyn Error>>;
+
+    fn index(&self, name: &str) -> Result<PackageIndex, Box<dyn Error>> {
+        match self.get(&format!("{}/index.json", name))? {
+            Some(bytes) => Ok(serde_json::from_slice(&bytes)
+                .map_err(|e| format!("Reading index of {} in {}: {}", name, self.location(), e))?),
+            None => Ok(PackageIndex {
+                name: name.to_string(),
+                versions: vec![],
+            }),
+        }
+    }
+
+    fn publish(&self, package_path: &str, feed: Option<String>) -> Result<PackageVersion, Box<dyn Error>> {
+        let manifest: PackageManifest = package::read_manifest(package_path)?;
+        validate_package_name(&manifest.name)?;
+        validate_file_name(&manifest.file_name())?;
+        Version::parse(&manifest.version)
+            .map_err(|e| format!("Package {} has an invalid version {}: {}", package_path, manifest.version, e))?;
+
+        let mut index = self.index(&manifest.name)?;
+        if index.versions.iter().any(|v| v.version == manifest.version) {
+            return Err(format!("{} {} is already published in {}", manifest.name, manifest.version, self.location()).into());
+        }
+
+        let bytes = fs::read(package_path)?;
+        let entry = PackageVersion {
+            version: manifest.version.clone(),
+            file: manifest.file_name(),
+            sha256: package::sha256_hex(&bytes),
+            change_id: manifest.change_id.clone(),
+            build_time: manifest.build_time,
+            published: Some(Utc::now()),
+            feed,
+        };
+
+        self.put(&format!("{}/{}/{}", manifest.name, entry.version, entry.file), &bytes)?;
+        index.versions.push(entry.clone());
+        self.put(&format!("{}/index.json", manifest.name), serde_json::to_string_pretty(&index)?.as_bytes())?;
+
+        Ok(entry)
+    }
+
+    /// Downloads a package, verifies its hash and unpacks it into `<destination>/<name>`.
+    fn download(&self, name: &str, requirement: &str, destination: &str) -> Result<(PackageVersion, PackageManifest), Box<dyn Error>> {
+        validate_package_name(name)?;
+        let entry = self.index(name)?.resolve(requirement)?;
+        validate_file_name(&entry.file)?;
+        let bytes = self.get(&format!("{}/{}/{}", name, entry.version, entry.file))?
+            .ok_or_else(|| format!("Package file {} is listed but missing in {}", entry.file, self.location()))?;
+
+        let hash = package::sha256_hex(&bytes);
+        if hash != entry.sha256 {
+            return Err(format!("Hash mismatch for {}: expected {}, found {}", entry.file, entry.sha256, hash).into());
+        }
+
+        let cache_dir = std::env::temp_dir().join("chgops");
+        fs::create_dir_all(&cache_dir)?;
+        let package_path = cache_dir.join(&entry.file);
+        fs::write(&package_path, &bytes)?;
+
+        let target = format!("{}/{}", destination, name);
+        let manifest = package::unpack_package(package_path.to_str().unwrap(), &target)?;
+        let _ = fs::remove_file(&package_path);
+
+        Ok((entry, manifest))
+    }
+}
+
+/// A directory acting as a feed, e.g. a local folder or a network share.
+pub struct FileRegistry {
+    pub root: PathBuf,
+}
+
+impl FileRegistry {
+    pub fn new(root: &str) -> FileRegistry {
+        FileRegistry {
+            root: PathBuf::from(root),
+        }
+    }
+
+    /// The file of a registry path, which must stay under the root.
+    fn file(&self, path: &str) -> Result<PathBuf, Box<dyn Error>> {
+        if path.contains('\\') || Path::new(path).components().any(|c| !matches!(c, Component::Normal(_))) {
+            return Err(format!("Invalid registry path: {}", path).into());
+        }
+        Ok(self.root.join(path))
+    }
+}
+
+impl PackageRegistry for FileRegistry {
+    fn location(&self) -> String {
+        self.root.to_string_lossy().to_string()
+    }
+
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let file = self.file(path)?;
+        if !file.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(file)?))
+    }
+
+    fn put(&self, path: &str, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        let file = self.file(path)?;
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, bytes)?;
+        Ok(())
+    }
+}
+
+/// A feed served over HTTP with the same layout: GET reads files, PUT uploads them.
+pub struct HttpRegistry {
+    pub base_url: String,
+}
+
+impl HttpRegistry {
+    pub fn new(base_url: &str) -> HttpRegistry {
+        HttpRegistry {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+}
+
+impl PackageRegistry for HttpRegistry {
+    fn location(&self) -> String {
+        self.base_url.clone()
+    }
+
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        match ureq::get(&self.url(path)).call() {
+            Ok(response) => {
+                let mut bytes = vec![];
+                response.into_reader().read_to_end(&mut bytes)?;
+                Ok(Some(bytes))
+            },
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(e) => Err(format!("GET {}: {}", self.url(path), e).into()),
+        }
+    }
+
+    fn put(&self, path: &str, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        ureq::put(&self.url(path))
+            .set("Content-Type", "application/octet-stream")
+            .send_bytes(bytes)
+            .map_err(|e| format!("PUT {}: {}", self.url(path), e))?;
+        Ok(())
+    }
+}
+
+/// Opens the registry at `location`: an http(s) URL, a `file://` URL or a directory.
+pub fn open_registry(location: &str) -> Box<dyn PackageRegistry> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        Box::new(HttpRegistry::new(location))
+    } else {
+        let path = location.strip_prefix("file://").unwrap_or(location);
+        Box::new(FileRegistry::new(path))
+    }
+}
+
+/// Registry location from `--registry`, then `CHGOPS_REGISTRY`, then [`DEFAULT_REGISTRY`].
+pub fn registry_location(argument: Option<&str>) -> String {
+    match argument {
+        Some(location) if !location.is_empty() => location.to_string(),
+        _ => std::env::var("CHGOPS_REGISTRY").unwrap_or(DEFAULT_REGISTRY.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(versions: &[&str]) -> PackageIndex {
+        PackageIndex {
+            name: "az_deixei".to_string(),
+            versions: versions.iter().map(|version| PackageVersion {
+                version: version.to_string(),
+                file: format!("az_deixei-{}-1234.zip", version.replace('.', "_")),
+                ..Default::default()
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn resolve_picks_the_highest_matching_version() {
+        let index = index(&["1.0.0", "1.2.0", "1.10.0", "2.0.0", "not-a-version"]);
+
+        assert_eq!(index.resolve("latest").unwrap().version, "2.0.0");
+        assert_eq!(index.resolve("*").unwrap().version, "2.0.0");
+        assert_eq!(index.resolve("^1.2").unwrap().version, "1.10.0");
+        assert_eq!(index.resolve("~1.2").unwrap().version, "1.2.0");
+        assert_eq!(index.resolve(">=1.0, <1.5").unwrap().version, "1.2.0");
+    }
+
+    #[test]
+    fn resolve_takes_an_exact_version_as_is() {
+        let index = index(&["1.0.0", "1.2.0", "1.2.1"]);
+
+        assert_eq!(index.resolve("1.2.0").unwrap().version, "1.2.0");
+        assert_eq!(index.resolve(" 1.0.0 ").unwrap().version, "1.0.0");
+    }
+
+    #[test]
+    fn resolve_fails_without_a_match_or_with_an_invalid_requirement() {
+        let index = index(&["1.0.0", "1.2.0"]);
+
+        let err = index.resolve("^3").unwrap_err().to_string();
+        assert!(err.contains("No version of az_deixei matches ^3"));
+        assert!(err.contains("1.0.0, 1.2.0"));
+        assert!(index.resolve("newest").unwrap_err().to_string().contains("Invalid version requirement"));
+        assert!(PackageIndex::default().resolve("latest").is_err());
+    }
+
+    #[test]
+    fn package_names_stay_inside_the_registry() {
+        assert!(validate_package_name("dx.azure").is_ok());
+        assert!(validate_package_name("az_deixei").is_ok());
+        assert!(validate_package_name("my-app").is_ok());
+        for name in ["", "..", "../x", "a..b", "a/b", "a\\b", "dx.", ".dx", "a b"] {
+            assert!(validate_package_name(name).is_err(), "{} should be rejected", name);
+        }
+    }
+
+    #[test]
+    fn change_ids_are_plain_names() {
+        assert!(validate_change_id("1234").is_ok());
+        assert!(validate_change_id("CHG-1234").is_ok());
+        for change_id in ["", "..", "../x", "a/b", "CHG 1", "1.2"] {
+            assert!(validate_change_id(change_id).is_err(), "{} should be rejected", change_id);
+        }
+    }
+
+    #[test]
+    fn file_registry_rejects_paths_leaving_its_root() {
+        let registry = FileRegistry::new("/tmp/chgops-registry-test");
+
+        assert!(registry.get("../index.json").is_err());
+        assert!(registry.get("/etc/passwd").is_err());
+        assert!(registry.put("a/../../b", b"").is_err());
+        assert!(registry.get("missing/index.json").unwrap().is_none());
+    }
+}
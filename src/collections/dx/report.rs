@@ -0,0 +1,188 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use crate::collections::dx::change::ChangeTicket;
+use crate::collections::dx::vault::Secrets;
+use crate::collections::dx::{files_and_dirs, ChgOpsWorkspace, Settings, PlaybookCommandOutput, PlaybookSummary, PlaybookTaskInfo};
+use crate::eprint_info;
+
+/// Parameters the engine was started with, as shown in the start banner.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct EngineParameters {
+    pub playbook_name: String,
+    pub workspace_path: String,
+    pub current_dir: String,
+    pub verbose: String,
+    pub arguments: String,
+    pub diff: bool,
+    #[serde(default)]
+    pub change_id: String,
+    #[serde(default)]
+    pub non_interactive: bool,
+    #[serde(default)]
+    pub playbook_full_path: String,
+    #[serde(default)]
+    pub config_full_path: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct TaskReport {
+    pub index: usize,
+    #[serde(flatten)]
+    pub info: PlaybookTaskInfo,
+    pub error: Option<String>,
+    pub output: PlaybookCommandOutput,
+}
+
+impl TaskReport {
+    /// Duration of the task in seconds, zero when the task never ran.
+    pub fn seconds(&self) -> f64 {
+        match (self.output.start_time, self.output.end_time) {
+            (Some(start), Some(end)) => end.signed_duration_since(start).num_milliseconds() as f64 / 1000.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Full record of a playbook run, written by `chgops run --report`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct RunReport {
+    pub engine: EngineParameters,
+    pub stage: Option<String>,
+    pub change_id: Option<String>,
+    pub change: Option<ChangeTicket>,
+    pub playbook: String,
+    #[serde(default)]
+    pub settings: Option<Settings>,
+    pub summary: PlaybookSummary,
+    pub tasks: Vec<TaskReport>,
+}
+
+impl RunReport {
+    pub fn from_workspace(workspace: &mut ChgOpsWorkspace, engine: EngineParameters) -> RunReport {
+        let tasks = workspace.playbook.tasks.iter().enumerate().map(|(index, task)| {
+            let output = task.logged_output();
+            let error = if output.failed > 0 {
+                if output.stderr.is_empty() { Some(output.message.clone()) } else { Some(output.stderr.clone()) }
+            } else {
+                None
+            };
+            TaskReport {
+                index,
+                info: task.info(),
+                error,
+                output,
+            }
+        }).collect();
+
+        RunReport {
+            engine,
+            stage: workspace.stage(),
+            change_id: workspace.effective_change_id(),
+            change: workspace.playbook.settings.change.clone(),
+            playbook: workspace.playbook.name.clone(),
+            settings: Some(workspace.playbook.settings.clone()),
+            summary: workspace.summary.clone(),
+            tasks,
+        }
+    }
+
+    /// The report with the secrets masked in the free text: task outputs and errors, arguments and settings vars.
+    pub fn masked(mut self, secrets: &Secrets) -> RunReport {
+        if secrets.is_empty() {
+            return self;
+        }
+        self.engine.arguments = secrets.mask(&self.engine.arguments);
+        if let Some(vars) = self.settings.as_mut().and_then(|settings| settings.vars.as_mut()) {
+            vars.values_mut().for_each(|value| *value = secrets.mask(value));
+        }
+        for task in self.tasks.iter_mut() {
+            task.output = task.output.masked(secrets);
+            task.error = task.error.as_ref().map(|error| secrets.mask(error));
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Renders the run as a JUnit document, one testcase per task.
+    pub fn to_junit(&self) -> String {
+        let total_seconds: f64 = self.tasks.iter().map(|t| t.seconds()).sum();
+        let timestamp = self.summary.start_time.map(|t| t.to_rfc3339()).unwrap_or_default();
+
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"chgops\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\">\n",
+            self.tasks.len(), self.summary.failed_counter, self.summary.skipped_counter, total_seconds
+        ));
+        xml.push_str(&format!(
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">\n",
+            xml_escape(&self.playbook), self.tasks.len(), self.summary.failed_counter, self.summary.skipped_counter, total_seconds, timestamp
+        ));
+        if self.stage.is_some() || self.change_id.is_some() {
+            xml.push_str("    <properties>\n");
+            if let Some(stage) = &self.stage {
+                xml.push_str(&format!("      <property name=\"stage\" value=\"{}\"/>\n", xml_escape(stage)));
+            }
+            if let Some(change_id) = &self.change_id {
+                xml.push_str(&format!("      <property name=\"change_id\" value=\"{}\"/>\n", xml_escape(change_id)));
+            }
+            xml.push_str("    </properties>\n");
+        }
+
+        for task in self.tasks.iter() {
+            xml.push_str(&format!(
+                "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+                xml_escape(&task.info.name), xml_escape(&task.info.task_type), task.seconds()
+            ));
+            if let Some(error) = &task.error {
+                xml.push_str(&format!(
+                    "      <failure message=\"{}\">{}</failure>\n",
+                    xml_escape(&task.output.message), xml_escape(error)
+                ));
+            } else if task.output.skipped > 0 {
+                xml.push_str("      <skipped/>\n");
+            }
+            if !task.output.stdout.is_empty() {
+                xml.push_str(&format!("      <system-out>{}</system-out>\n", xml_escape(&task.output.stdout)));
+            }
+            if !task.output.stderr.is_empty() {
+                xml.push_str(&format!("      <system-err>{}</system-err>\n", xml_escape(&task.output.stderr)));
+            }
+            xml.push_str("    </testcase>\n");
+        }
+
+        xml.push_str("  </testsuite>\n");
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+
+    /// Writes the report to every target of a `--report` spec such as "json=run.json,junit=run.xml".
+    pub fn write(&self, spec: &str) -> Result<(), Box<dyn Error>> {
+        for target in spec.split(',').map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            let (format, path) = target.split_once('=').ok_or_else(|| {
+                format!("Invalid report target: {}. Expected format: json=path or junit=path", target)
+            })?;
+
+            let content = match format {
+                "json" => self.to_json()?,
+                "junit" => self.to_junit(),
+                _ => return Err(format!("Unknown report format: {}. Supported formats: json, junit", format).into()),
+            };
+            files_and_dirs::write_file(path, &content)?;
+            eprint_info!("Run report written: {} ({})", path, format);
+        }
+        Ok(())
+    }
+}
+
+pub fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,157 @@
+// JSON Schema of playbooks, for editors to validate and complete them
+
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value as JsonValue};
+use std::error::Error;
+use crate::collections::dx::task_registry::{TaskDefinition, TaskRegistry};
+use crate::collections::dx::{external, files_and_dirs, PlaybookCommand, Settings};
+
+/// Builds the JSON Schema of a task body.
+pub type TaskSchema = fn(&mut SchemaGenerator) -> Schema;
+
+/// Schema of a `PlaybookCommand<COMMAND, VARS>` body.
+pub fn task_schema<COMMAND: JsonSchema, VARS: JsonSchema>(gen: &mut SchemaGenerator) -> Schema {
+    gen.subschema_for::<PlaybookCommand<COMMAND, VARS>>()
+}
+
+fn generator() -> SchemaGenerator {
+    let mut settings = SchemaSettings::draft07();
+    settings.inline_subschemas = true;
+    settings.into_generator()
+}
+
+// `true`, the schema of any value, becomes an object to hold the description
+fn describe(schema: &mut JsonValue, description: &str) {
+    if schema.is_boolean() {
+        *schema = json!({});
+    }
+    schema["description"] = json!(description);
+}
+
+/// Schema of `{ <task type>: <body> }`, with what the registry knows beyond the Rust types:
+/// descriptions, accepted `state` values and the closed list of `vars` fields.
+fn task_definition_schema(gen: &mut SchemaGenerator, definition: &TaskDefinition) -> Result<JsonValue, Box<dyn Error>> {
+    let schema = definition.schema.unwrap_or(task_schema::<Option<String>, JsonValue>);
+    let mut body = serde_json::to_value(schema(gen))?;
+
+    if let Some(properties) = body.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        if let (Some(field), Some(command)) = (&definition.command, properties.get_mut("command")) {
+            describe(command, &field.description);
+        }
+        if let Some(vars) = properties.get_mut("vars") {
+            for field in definition.vars.iter() {
+                if let Some(property) = vars.get_mut("properties").and_then(|p| p.get_mut(&field.name)) {
+                    describe(property, &field.description);
+                }
+            }
+            if !definition.vars.is_empty() {
+                vars["additionalProperties"] = json!(false);
+            }
+        }
+        if !definition.states.is_empty() {
+            properties.insert("state".to_string(), json!({ "type": "string", "enum": definition.states }));
+        }
+    }
+    body["additionalProperties"] = json!(false);
+
+    Ok(json!({
+        "description": definition.description,
+        "type": "object",
+        "properties": { definition.name.clone(): body },
+        "required": [definition.name],
+        "additionalProperties": false,
+    }))
+}
+
+/// Schema of a playbook using the registered tasks and the `modules` task types.
+pub fn playbook_schema(registry: &TaskRegistry, modules: &[String]) -> Result<JsonValue, Box<dyn Error>> {
+    let mut gen = generator();
+    let mut definitions = Map::new();
+
+    for definition in registry.definitions() {
+        definitions.insert(definition.name.clone(), task_definition_schema(&mut gen, definition)?);
+    }
+    for module in modules.iter() {
+        let definition = TaskDefinition::new(module, "Runs a collection module", external::parse);
+        definitions.insert(module.clone(), task_definition_schema(&mut gen, &definition)?);
+    }
+
+    let tasks = definitions.keys()
+        .map(|name| json!({ "$ref": format!("#/definitions/{}", name) }))
+        .collect::<Vec<_>>();
+
+    Ok(json!({
+        "$schema": gen.settings().meta_schema,
+        "title": "chgops playbook",
+        "type": "object",
+        "properties": {
+            "name": { "type": "string", "description": "Name of the playbook" },
+            "settings": serde_json::to_value(gen.subschema_for::<Settings>())?,
+            "tasks": { "type": "array", "items": { "anyOf": tasks } },
+        },
+        "required": ["name", "settings", "tasks"],
+        "definitions": definitions,
+    }))
+}
+
+/// Writes the playbook schema as pretty JSON.
+pub fn export(registry: &TaskRegistry, modules: &[String], output: &str) -> Result<(), Box<dyn Error>> {
+    let schema = playbook_schema(registry, modules)?;
+    files_and_dirs::write_file(output, &format!("{}\n", serde_json::to_string_pretty(&schema)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::dx::collection;
+    use std::path::Path;
+
+    fn schema() -> JsonValue {
+        playbook_schema(&TaskRegistry::with_builtins(), &["acme.tools.deploy".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn every_task_type_and_module_is_a_choice_for_the_tasks() {
+        let schema = schema();
+        let choices: Vec<&str> = schema["properties"]["tasks"]["items"]["anyOf"].as_array().unwrap().iter()
+            .map(|choice| choice["$ref"].as_str().unwrap())
+            .collect();
+
+        assert!(choices.contains(&"#/definitions/dx.core.print"));
+        assert!(choices.contains(&"#/definitions/dx.azure.cli"));
+        assert!(choices.contains(&"#/definitions/acme.tools.deploy"));
+        assert_eq!(choices.len(), TaskRegistry::with_builtins().definitions().count() + 1);
+        assert_eq!(schema["required"], json!(["name", "settings", "tasks"]));
+    }
+
+    #[test]
+    fn a_task_schema_closes_its_fields_and_describes_its_vars_and_states() {
+        let schema = schema();
+        let print = &schema["definitions"]["dx.core.print"];
+        let body = &print["properties"]["dx.core.print"];
+
+        assert_eq!(print["required"], json!(["dx.core.print"]));
+        assert_eq!(body["additionalProperties"], json!(false));
+        assert_eq!(body["properties"]["state"]["enum"], json!(["present", "absent"]));
+        assert_eq!(body["properties"]["vars"]["additionalProperties"], json!(false));
+        assert_eq!(body["properties"]["vars"]["properties"]["resource"]["description"], json!("value or template to render, registered as the task data"));
+        assert!(body["properties"]["command"]["description"].as_str().unwrap().starts_with("print (default)"));
+
+        // a module takes any vars
+        let module = &schema["definitions"]["acme.tools.deploy"]["properties"]["acme.tools.deploy"];
+        assert!(module["properties"]["vars"].get("additionalProperties").is_none());
+    }
+
+    #[test]
+    fn the_exported_schema_is_up_to_date() {
+        let collections: Vec<(String, String)> = collection::discover(Path::new("./collections")).unwrap().into_iter()
+            .map(|(name, folder)| (name, folder.to_string_lossy().to_string()))
+            .collect();
+        let schema = playbook_schema(&TaskRegistry::with_builtins(), &external::loaded_modules(&collections)).unwrap();
+        let exported: JsonValue = serde_json::from_str(&std::fs::read_to_string("./schemas/playbook.schema.json").unwrap()).unwrap();
+
+        assert!(schema == exported, "run `cargo run -- schema export` and commit ./schemas/playbook.schema.json");
+    }
+}
@@ -0,0 +1,236 @@
+// where the events of a run are written, chosen with `run --output-format`
+
+use serde_json::json;
+use std::error::Error;
+use std::io::Write;
+use crate::collections::dx::observer::{MessageLevel, OutputStream, RunObserver};
+use crate::collections::dx::report::EngineParameters;
+use crate::collections::dx::task_registry::PlaybookTask;
+use crate::collections::dx::vault::SharedSecrets;
+use crate::collections::dx::{Playbook, PlaybookSummary, PlaybookTaskInfo};
+use crate::collections::Verbose;
+use crate::{print_banner_green, print_banner_yellow, print_error, print_warning};
+
+pub const OUTPUT_FORMATS: [&str; 3] = ["console", "json", "dots"];
+
+/// The sink of an output format: "console" (or empty), "json" or "dots", masking `secrets` as the run adds to them.
+pub fn for_format(format: &str, verbose: Verbose, diff: bool, secrets: SharedSecrets) -> Result<Box<dyn RunObserver>, Box<dyn Error>> {
+    match format {
+        "" | "console" => Ok(Box::new(ConsoleSink::new(verbose, diff, secrets))),
+        "json" => Ok(Box::new(JsonLinesSink::new(std::io::stdout(), secrets))),
+        "dots" => Ok(Box::new(DotsSink::new(secrets))),
+        other => Err(format!("Unknown output format {}, expected one of: {}", other, OUTPUT_FORMATS.join(", ")).into()),
+    }
+}
+
+/// Banners for people watching the run: the engine parameters, every task with its output, the summary.
+///
+/// Quiet shows only the failed tasks and the summary.
+#[derive(Debug, Default)]
+pub struct ConsoleSink {
+    verbose: Verbose,
+    diff: bool,
+    secrets: SharedSecrets,
+}
+
+impl ConsoleSink {
+    pub fn new(verbose: Verbose, diff: bool, secrets: SharedSecrets) -> ConsoleSink {
+        ConsoleSink {
+            verbose,
+            diff,
+            secrets,
+        }
+    }
+}
+
+impl RunObserver for ConsoleSink {
+    fn playbook_start(&mut self, engine: &EngineParameters, playbook: &Playbook) {
+        if self.verbose == Verbose::Quiet {
+            return;
+        }
+        let secrets = self.secrets.read().unwrap();
+        print_banner_yellow!("ChgOps - Change management and operations tool");
+
+        println!("Engine Parameters ###########################");
+
+        println!("\tPlaybook Name: {}", engine.playbook_name);
+        println!("\tWorkspace Path: {}", engine.workspace_path);
+        println!("\tVerbose: {}", engine.verbose);
+        println!("\tArguments: {}", secrets.mask(&engine.arguments));
+        println!("\tChange Id: {}", engine.change_id);
+        println!("\tDiff: {}", engine.diff);
+        println!("\tNon Interactive: {}", engine.non_interactive);
+        println!("\tFiles information:");
+        println!("\t\tCurrent Dir: {}", engine.current_dir);
+        println!("\t\tPlaybook Full Path: {}", engine.playbook_full_path);
+        println!("\t\tConfigurations Full Path: {}", engine.config_full_path);
+        println!("#############################################");
+
+        if let Some(change) = &playbook.settings.change {
+            change.display();
+        }
+        playbook.display(self.verbose, &secrets);
+    }
+
+    fn task_message(&mut self, task: &PlaybookTaskInfo, level: MessageLevel, message: &str) {
+        if self.verbose == Verbose::Quiet && level != MessageLevel::Error {
+            return;
+        }
+        let message = self.secrets.read().unwrap().mask(message);
+        println!("name: {}", task.name);
+        match level {
+            MessageLevel::Print => println!("{}", message),
+            MessageLevel::Debug => print_warning!("{:?}", message),
+            MessageLevel::Info => print_banner_yellow!("{}", message),
+            MessageLevel::Success => print_banner_green!("{}", message),
+            MessageLevel::Warning => print_warning!("{}", message),
+            MessageLevel::Error => print_error!("{}", message),
+        }
+    }
+
+    fn task_skipped(&mut self, task: &dyn PlaybookTask) {
+        if self.verbose > Verbose::Quiet {
+            task.display(self.verbose, &self.secrets.read().unwrap());
+        }
+    }
+
+    fn task_end(&mut self, task: &dyn PlaybookTask) {
+        let output = task.logged_output();
+        let secrets = self.secrets.read().unwrap();
+        match self.verbose {
+            Verbose::Quiet if output.failed == 0 => {},
+            Verbose::Quiet => task.display(Verbose::Empty, &secrets),
+            verbose => task.display(verbose, &secrets),
+        }
+        if self.diff {
+            output.masked(&secrets).display_diff();
+        }
+        if output.abort {
+            print_error!("Playbook aborted by task: {}", task.info().name);
+        }
+    }
+
+    fn playbook_end(&mut self, summary: &PlaybookSummary) {
+        summary.display();
+        print_banner_yellow!("ChgOps - End of execution");
+    }
+}
+
+/// One JSON object per event and line, for CI logs and dashboards.
+///
+/// Every line has the `event` name and its `time`; task events carry the `task` info and,
+/// once it ran, its `output`. Secret values are masked in every string, `no_log` tasks send no output.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    secrets: SharedSecrets,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W, secrets: SharedSecrets) -> JsonLinesSink<W> {
+        JsonLinesSink { writer, secrets }
+    }
+
+    fn write(&mut self, event: &str, mut fields: serde_json::Value) {
+        self.secrets.read().unwrap().mask_json(&mut fields);
+        fields["event"] = json!(event);
+        fields["time"] = json!(chrono::Utc::now());
+        let written = writeln!(self.writer, "{}", fields).and_then(|_| self.writer.flush());
+        if let Err(err) = written {
+            print_error!("writing {} event: {}", event, err);
+        }
+    }
+}
+
+impl<W: Write> RunObserver for JsonLinesSink<W> {
+    fn playbook_start(&mut self, engine: &EngineParameters, playbook: &Playbook) {
+        self.write("playbook_start", json!({
+            "playbook": playbook.name,
+            "engine": engine,
+            "change": playbook.settings.change,
+            "tasks": playbook.tasks.len(),
+        }));
+    }
+
+    fn task_start(&mut self, task: &dyn PlaybookTask) {
+        self.write("task_start", json!({ "task": task.info() }));
+    }
+
+    fn task_output(&mut self, task: &PlaybookTaskInfo, stream: OutputStream, chunk: &str) {
+        if task.no_log {
+            return;
+        }
+        self.write("task_output", json!({ "task": task, "stream": stream, "chunk": chunk }));
+    }
+
+    fn task_message(&mut self, task: &PlaybookTaskInfo, level: MessageLevel, message: &str) {
+        if task.no_log {
+            return;
+        }
+        self.write("task_message", json!({ "task": task, "level": level, "message": message }));
+    }
+
+    fn task_skipped(&mut self, task: &dyn PlaybookTask) {
+        self.write("task_skipped", json!({ "task": task.info(), "output": task.logged_output() }));
+    }
+
+    fn task_end(&mut self, task: &dyn PlaybookTask) {
+        self.write("task_end", json!({ "task": task.info(), "output": task.logged_output() }));
+    }
+
+    fn playbook_end(&mut self, summary: &PlaybookSummary) {
+        self.write("playbook_end", json!({ "summary": summary }));
+    }
+}
+
+/// A character per task, `.` succeeded, `C` changed, `S` skipped, `F` failed, then the failures and the summary.
+#[derive(Debug, Default)]
+pub struct DotsSink {
+    failures: Vec<(String, String)>,
+    secrets: SharedSecrets,
+}
+
+impl DotsSink {
+    pub fn new(secrets: SharedSecrets) -> DotsSink {
+        DotsSink { failures: vec![], secrets }
+    }
+
+    fn dot(&self, mark: char) {
+        print!("{}", mark);
+        let _ = std::io::stdout().flush();
+    }
+}
+
+impl RunObserver for DotsSink {
+    fn task_skipped(&mut self, _task: &dyn PlaybookTask) {
+        self.dot('S');
+    }
+
+    fn task_end(&mut self, task: &dyn PlaybookTask) {
+        let output = task.logged_output();
+        if output.failed > 0 {
+            let message = if output.stderr.is_empty() { output.message } else { output.stderr };
+            let message = self.secrets.read().unwrap().mask(&message);
+            self.failures.push((task.info().name, message));
+            self.dot('F');
+        } else if output.changed > 0 {
+            self.dot('C');
+        } else {
+            self.dot('.');
+        }
+    }
+
+    fn playbook_end(&mut self, summary: &PlaybookSummary) {
+        println!();
+        for (name, message) in self.failures.iter() {
+            print_error!("{}: {}", name, message.trim_end());
+        }
+        println!("{} tasks, {} succeeded, {} failed, {} skipped, {} changed in {}",
+            summary.tasks_counter,
+            summary.success_counter,
+            summary.failed_counter,
+            summary.skipped_counter,
+            summary.changed_counter,
+            summary.duration()
+        );
+    }
+}
@@ -0,0 +1,354 @@
+// task types known to the engine and how playbook tasks are parsed into them
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::Value as YamlValue;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use crate::collections::dx::schema::TaskSchema;
+use crate::collections::dx::{external, PlaybookCommand, PlaybookCommandOutput, PlaybookCommandTrait, PlaybookTaskInfo, PlaybookTasks};
+
+/// A parsed playbook task, whatever its type.
+pub trait PlaybookTask: PlaybookCommandTrait + Debug + Send {
+    fn info(&self) -> PlaybookTaskInfo;
+    /// Text the mocks match `command` against.
+    fn command_text(&self) -> String;
+    /// The rendered `when` of the task.
+    fn when(&self) -> Option<String>;
+    fn set_output(&mut self, output: PlaybookCommandOutput);
+    /// The output as shown and stored, censored when the task is `no_log`.
+    fn logged_output(&self) -> PlaybookCommandOutput {
+        match self.info().no_log {
+            true => self.output().censored(),
+            false => self.output(),
+        }
+    }
+    /// The task body, without its type key.
+    fn to_value(&self) -> Result<YamlValue, serde_yaml::Error>;
+}
+
+/// How a task `command` field reads as text.
+pub trait TaskCommand {
+    fn text(&self) -> String;
+}
+
+impl TaskCommand for String {
+    fn text(&self) -> String {
+        self.clone()
+    }
+}
+
+impl TaskCommand for Option<String> {
+    fn text(&self) -> String {
+        self.clone().unwrap_or_default()
+    }
+}
+
+impl<COMMAND, VARS> PlaybookTask for PlaybookCommand<COMMAND, VARS>
+where
+    PlaybookCommand<COMMAND, VARS>: PlaybookCommandTrait,
+    COMMAND: TaskCommand + Serialize + Debug + Send,
+    VARS: Serialize + Debug + Send,
+{
+    fn info(&self) -> PlaybookTaskInfo {
+        PlaybookCommand::info(self, &self.task_type)
+    }
+
+    fn command_text(&self) -> String {
+        self.command.text()
+    }
+
+    fn when(&self) -> Option<String> {
+        self.when.clone()
+    }
+
+    fn set_output(&mut self, output: PlaybookCommandOutput) {
+        self.output = output;
+    }
+
+    fn to_value(&self) -> Result<YamlValue, serde_yaml::Error> {
+        serde_yaml::to_value(self)
+    }
+}
+
+/// Builds a task of a registered type from its body.
+pub type TaskFactory = fn(&str, YamlValue) -> Result<Box<dyn PlaybookTask>, serde_yaml::Error>;
+
+/// Factory of the built-in tasks: the body deserializes into a `PlaybookCommand<COMMAND, VARS>`.
+pub fn parse_command<COMMAND, VARS>(task_type: &str, body: YamlValue) -> Result<Box<dyn PlaybookTask>, serde_yaml::Error>
+where
+    PlaybookCommand<COMMAND, VARS>: PlaybookTask + DeserializeOwned + 'static,
+{
+    let mut task: PlaybookCommand<COMMAND, VARS> = serde_yaml::from_value(body)?;
+    task.task_type = task_type.to_string();
+    Ok(Box::new(task))
+}
+
+/// A `command` or `vars` field of a task type.
+#[derive(Debug, Clone)]
+pub struct TaskField {
+    pub name: String,
+    /// one of `collection::VARIABLE_TYPES`
+    pub field_type: String,
+    pub required: bool,
+    pub description: String,
+}
+
+impl TaskField {
+    pub fn required(name: &str, field_type: &str, description: &str) -> TaskField {
+        TaskField {
+            name: name.to_string(),
+            field_type: field_type.to_string(),
+            required: true,
+            description: description.to_string(),
+        }
+    }
+
+    pub fn optional(name: &str, field_type: &str, description: &str) -> TaskField {
+        TaskField {
+            required: false,
+            ..TaskField::required(name, field_type, description)
+        }
+    }
+
+    fn display(&self, prefix: &str) {
+        let required = if self.required { " (required)" } else { "" };
+        println!("\t{}{}: {}{} {}", prefix, self.name, self.field_type, required, self.description);
+    }
+}
+
+/// A task type: its schema, documentation and factory.
+#[derive(Debug, Clone)]
+pub struct TaskDefinition {
+    pub name: String,
+    pub description: String,
+    /// the `command` field, `None` when the task takes no command
+    pub command: Option<TaskField>,
+    /// fields of `vars`, any field is accepted when empty
+    pub vars: Vec<TaskField>,
+    /// accepted `state` values, `state` is not used when empty
+    pub states: Vec<String>,
+    pub factory: TaskFactory,
+    /// JSON Schema of the task body, any body is accepted when `None`
+    pub schema: Option<TaskSchema>,
+}
+
+impl TaskDefinition {
+    pub fn new(name: &str, description: &str, factory: TaskFactory) -> TaskDefinition {
+        TaskDefinition {
+            name: name.to_string(),
+            description: description.to_string(),
+            command: None,
+            vars: vec![],
+            states: vec![],
+            factory,
+            schema: None,
+        }
+    }
+
+    pub fn command(mut self, field: TaskField) -> TaskDefinition {
+        self.command = Some(field);
+        self
+    }
+
+    pub fn var(mut self, field: TaskField) -> TaskDefinition {
+        self.vars.push(field);
+        self
+    }
+
+    pub fn schema(mut self, schema: TaskSchema) -> TaskDefinition {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn states(mut self, states: &[&str]) -> TaskDefinition {
+        self.states = states.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn display(&self) {
+        println!("Task: {} #####################################", self.name);
+        println!("\tDescription: {}", self.description);
+        match &self.command {
+            Some(command) => command.display(""),
+            None => println!("\tcommand: not used"),
+        }
+        for field in self.vars.iter() {
+            field.display("vars.");
+        }
+        match self.states.is_empty() {
+            true => println!("\tstate: not used"),
+            false => println!("\tstate: {}", self.states.join(", ")),
+        }
+        println!("\tCommon fields: name, register, when");
+        println!("#############################################");
+    }
+}
+
+/// Every registered task type, by name. Each workspace parses and validates its playbooks
+/// with its own registry, so task types added to one workspace are unknown to the others.
+///
+/// The modules of a workspace's collections are task types too: the lookups taking `collections`,
+/// the loaded collections as (`namespace.name`, folder), find them there.
+#[derive(Debug, Default, Clone)]
+pub struct TaskRegistry {
+    tasks: BTreeMap<String, TaskDefinition>,
+}
+
+impl TaskRegistry {
+    pub fn new() -> TaskRegistry {
+        TaskRegistry::default()
+    }
+
+    /// The registry with the dx.core and dx.azure tasks.
+    pub fn with_builtins() -> TaskRegistry {
+        let mut registry = TaskRegistry::new();
+        crate::collections::dx::core::tasks::register(&mut registry);
+        crate::collections::dx::azure::tasks::register(&mut registry);
+        registry
+    }
+
+    pub fn register(&mut self, definition: TaskDefinition) -> &mut TaskRegistry {
+        self.tasks.insert(definition.name.clone(), definition);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TaskDefinition> {
+        self.tasks.get(name)
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = &TaskDefinition> {
+        self.tasks.values()
+    }
+
+    /// Parses the task at `index` (0 based) of a playbook, e.g. `{ dx.core.bash: { command: ls, ... } }`.
+    ///
+    /// Keys that are not registered run a module of `collections` when one exists, see [`external`].
+    pub fn parse(&self, index: usize, task: YamlValue, collections: &[(String, String)]) -> Result<PlaybookTasks, String> {
+        let position = format!("task #{}", index + 1);
+        let mapping = match task {
+            YamlValue::Mapping(mapping) => mapping,
+            other => return Err(format!("{}: expected a mapping with a task type key, found {}", position, yaml_kind(&other))),
+        };
+        if mapping.len() != 1 {
+            let keys = mapping.iter().map(|(key, _)| key_text(key)).collect::<Vec<_>>().join(", ");
+            return Err(format!("{}: expected exactly one task type key, found: {}", position, keys));
+        }
+        let (key, body) = mapping.into_iter().next().unwrap();
+        let task_type = key_text(&key);
+        let task_name = body.get("name").and_then(|n| n.as_str()).map(|n| format!(" '{}'", n)).unwrap_or_default();
+
+        let Some(factory) = self.factory(&task_type, collections) else {
+            return Err(format!("{}{}: {}", position, task_name, self.unknown_task(&task_type, collections)));
+        };
+        factory(&task_type, body)
+            .map(PlaybookTasks)
+            .map_err(|e| format!("{} ({}{}): {}", position, task_type, task_name, e))
+    }
+
+    /// Parses the tasks of a playbook, in order.
+    pub fn parse_tasks(&self, tasks: Vec<YamlValue>, collections: &[(String, String)]) -> Result<Vec<PlaybookTasks>, String> {
+        tasks.into_iter()
+            .enumerate()
+            .map(|(index, task)| self.parse(index, task, collections))
+            .collect()
+    }
+
+    /// Factory of a registered task type, or of the module of one of `collections`.
+    pub fn factory(&self, task_type: &str, collections: &[(String, String)]) -> Option<TaskFactory> {
+        match self.get(task_type) {
+            Some(definition) => Some(definition.factory),
+            None if external::find_module(task_type, collections).is_ok() => Some(external::parse),
+            None => None,
+        }
+    }
+
+    /// Error message for a task type with no factory, with the close matches.
+    pub fn unknown_task(&self, task_type: &str, collections: &[(String, String)]) -> String {
+        let mut message = format!("unknown task type {}", task_type);
+        let suggestions = self.suggestions(task_type, collections);
+        match suggestions.is_empty() {
+            true => message.push('.'),
+            false => message.push_str(&format!(". Did you mean {}?", suggestions.join(" or "))),
+        }
+        message.push_str(" Run `chgops tasks list` for the available tasks");
+        message
+    }
+
+    /// Registered tasks and modules of `collections` whose name is close to `task_type`, closest first.
+    pub fn suggestions(&self, task_type: &str, collections: &[(String, String)]) -> Vec<String> {
+        let limit = (task_type.len() / 4).max(2);
+        let mut candidates = self.tasks.keys().cloned().collect::<Vec<_>>();
+        candidates.extend(external::loaded_modules(collections));
+
+        let mut close = candidates.into_iter()
+            .map(|candidate| (edit_distance(task_type, &candidate), candidate))
+            .filter(|(distance, _)| *distance <= limit)
+            .collect::<Vec<_>>();
+        close.sort();
+        close.into_iter().take(3).map(|(_, candidate)| candidate).collect()
+    }
+}
+
+pub fn key_text(key: &YamlValue) -> String {
+    match key {
+        YamlValue::String(text) => text.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim_start_matches("---\n").trim().to_string(),
+    }
+}
+
+pub fn yaml_kind(value: &YamlValue) -> &'static str {
+    match value {
+        YamlValue::Null => "nothing",
+        YamlValue::Bool(_) => "a bool",
+        YamlValue::Number(_) => "a number",
+        YamlValue::String(_) => "a string",
+        YamlValue::Sequence(_) => "a list",
+        YamlValue::Mapping(_) => "a mapping",
+    }
+}
+
+/// Levenshtein distance between two task names.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_insertions_deletions_and_substitutions() {
+        assert_eq!(edit_distance("dx.core.bash", "dx.core.bash"), 0);
+        assert_eq!(edit_distance("dx.core.bsh", "dx.core.bash"), 1);
+        assert_eq!(edit_distance("dx.core.bashh", "dx.core.bash"), 1);
+        assert_eq!(edit_distance("dx.core.bosh", "dx.core.bash"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn a_task_type_is_only_known_to_the_registry_it_was_added_to() {
+        use crate::collections::dx::core::tasks::PrintCommandVars;
+
+        let builtins = TaskRegistry::with_builtins();
+        let mut extended = builtins.clone();
+        extended.register(TaskDefinition::new("acme.notify", "Prints a notification", parse_command::<Option<String>, PrintCommandVars>));
+        let tasks = || vec![serde_yaml::from_str::<YamlValue>("acme.notify: { name: hello, vars: { resource: hi } }").unwrap()];
+
+        let parsed = extended.parse_tasks(tasks(), &[]).unwrap();
+        assert_eq!(parsed[0].info().task_type, "acme.notify");
+        assert!(builtins.parse_tasks(tasks(), &[]).unwrap_err().contains("unknown task type acme.notify"));
+        assert!(builtins.get("dx.core.bash").is_some());
+    }
+}
@@ -0,0 +1,726 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+use crate::api::Workspace;
+use crate::collections::dx::{config_proc, files_and_dirs, PlaybookTaskInfo};
+use crate::collections::dx::context::RunContext;
+use crate::collections::dx::dependencies::CollectionDependency;
+use crate::collections::dx::mock::{MockRegistry, TaskMock};
+use crate::collections::dx::observer::{MessageLevel, OutputStream, RunObserver};
+use crate::collections::dx::report::{xml_escape, RunReport};
+use crate::collections::dx::task_registry::PlaybookTask;
+use crate::collections::dx::vault::Secrets;
+use crate::{print_error, print_success};
+
+/// A fake external binary placed in front of `PATH` while a test runs.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct BinaryStub {
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    #[serde(default)]
+    pub exit_code: i32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct ExpectedSummary {
+    pub executed: Option<i32>,
+    pub success: Option<i32>,
+    pub failed: Option<i32>,
+    pub skipped: Option<i32>,
+    pub changed: Option<i32>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct ExpectedTask {
+    pub name: String,
+    pub stdout: Option<String>,
+    pub stdout_contains: Option<String>,
+    pub failed: Option<bool>,
+    pub skipped: Option<bool>,
+    pub changed: Option<bool>,
+    pub mocked: Option<bool>,
+}
+
+/// A tera condition evaluated after the run, e.g. `that: "tags.Owner == 'rust@deixei.com'"`.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Assertion {
+    pub that: String,
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct Expectations {
+    pub summary: Option<ExpectedSummary>,
+    #[serde(default)]
+    pub tasks: Vec<ExpectedTask>,
+    /// values of facts after the run, by dotted path, e.g. `settings.vars.when_me: marcio`
+    #[serde(default)]
+    pub facts: BTreeMap<String, serde_yaml::Value>,
+    /// values registered by tasks, by register name
+    #[serde(default)]
+    pub registered: BTreeMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+}
+
+/// Content of a test file: a playbook, what it runs against and what it must produce.
+///
+/// `playbook` is either the name of a playbook next to the test file or the playbook itself.
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct TestCase {
+    pub description: Option<String>,
+    pub arguments: Option<String>,
+    pub stage: Option<String>,
+    /// environment variables set for the run
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub stubs: BTreeMap<String, BinaryStub>,
+    #[serde(default)]
+    pub mocks: Vec<TaskMock>,
+    /// fail the tasks reaching outside the engine that no mock answers
+    #[serde(default)]
+    pub strict: bool,
+    pub vars: Option<serde_yaml::Value>,
+    pub playbook: serde_yaml::Value,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+impl TestCase {
+    /// `--arguments` for the run: the declared arguments with `STAGE` taken from `stage`.
+    pub fn run_arguments(&self) -> String {
+        let mut arguments: Vec<String> = self.arguments.clone().unwrap_or("STAGE=dev".to_string())
+            .split(',')
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect();
+        if let Some(stage) = &self.stage {
+            arguments.retain(|a| !a.starts_with("STAGE="));
+            arguments.push(format!("STAGE={}", stage));
+        }
+        arguments.join(",")
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub description: Option<String>,
+    pub failures: Vec<String>,
+    pub seconds: f64,
+    pub output: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+pub fn read_test_case(path: &Path) -> Result<TestCase, Box<dyn Error>> {
+    let yaml = config_proc::read_yaml(path.to_str().unwrap())?;
+    Ok(serde_yaml::from_value(yaml).map_err(|e| format!("Reading test {:?}: {}", path, e))?)
+}
+
+/// Test files of a workspace tree: `*.test.yaml` next to the playbooks, or `scope` itself when it is a file.
+pub fn find_workspace_tests(scope: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    if scope.is_file() {
+        return Ok(vec![scope.to_path_buf()]);
+    }
+    if !scope.is_dir() {
+        return Err(format!("Test scope not found: {:?}", scope).into());
+    }
+    let mut tests: Vec<PathBuf> = files_and_dirs::find_files_by_regex(scope.to_string_lossy().to_string(), r".*\.test\.yaml$")?
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|path| !path.components().any(|c| c.as_os_str().to_string_lossy().starts_with(".chgops")))
+        .collect();
+    tests.sort();
+    Ok(tests)
+}
+
+/// Name of a test: the file name without `.test.yaml` / `.yaml`.
+pub fn test_name(path: &Path) -> String {
+    let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+    file_name.trim_end_matches(".yaml").trim_end_matches(".test").to_string()
+}
+
+/// The `*.yaml` files of a `tests` folder, sorted by name.
+pub fn find_tests(tests_folder: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut tests = vec![];
+    if !tests_folder.is_dir() {
+        return Ok(tests);
+    }
+    for entry in fs::read_dir(tests_folder)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().map(|e| e == "yaml").unwrap_or(false) {
+            tests.push(path);
+        }
+    }
+    tests.sort();
+    Ok(tests)
+}
+
+fn write_stubs(bin_folder: &Path, stubs: &BTreeMap<String, BinaryStub>) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(bin_folder)?;
+    for (name, stub) in stubs.iter() {
+        let stdout_file = bin_folder.join(format!("{}.stdout", name));
+        let stderr_file = bin_folder.join(format!("{}.stderr", name));
+        fs::write(&stdout_file, &stub.stdout)?;
+        fs::write(&stderr_file, &stub.stderr)?;
+        let script = bin_folder.join(name);
+        fs::write(&script, format!(
+            "#!/bin/sh\ncat '{}'\ncat '{}' >&2\nexit {}\n",
+            stdout_file.to_string_lossy(), stderr_file.to_string_lossy(), stub.exit_code
+        ))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_workspace(workspace: &Path, sandbox: &Path) -> Result<(), Box<dyn Error>> {
+    for file in files_and_dirs::list_all_files_and_dirs(workspace.to_string_lossy().to_string())? {
+        let path = PathBuf::from(&file);
+        let relative = path.strip_prefix(workspace)?;
+        // skip local state and the test files themselves
+        if relative.components().any(|c| c.as_os_str().to_string_lossy().starts_with('.'))
+            || file.ends_with(".test.yaml") || relative.starts_with("tests") {
+            continue;
+        }
+        let target = sandbox.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&path, target)?;
+    }
+    Ok(())
+}
+
+/// Builds a throwaway workspace for the test: a copy of `workspace`, or a configuration that
+/// depends only on `collections`, plus the test playbook, vars and stubs.
+fn prepare_workspace(sandbox: &Path, case: &TestCase, workspace: Option<&Path>, collections: &[CollectionDependency]) -> Result<String, Box<dyn Error>> {
+    match workspace {
+        Some(workspace) => copy_workspace(workspace, sandbox)?,
+        None => {
+            let config = serde_yaml::to_string(&serde_yaml::Mapping::from_iter([(
+                serde_yaml::Value::String("dependencies".to_string()),
+                serde_yaml::to_value(BTreeMap::from([("collections", collections)]))?,
+            )]))?;
+            files_and_dirs::write_file(&sandbox.join("vars/config.yaml").to_string_lossy(), &format!("#!chgops.config\n{}", config))?;
+        },
+    }
+
+    if case.vars.is_some() || workspace.is_none() {
+        let vars = match &case.vars {
+            Some(vars) => serde_yaml::to_string(vars)?,
+            None => "test: true\n".to_string(),
+        };
+        files_and_dirs::write_file(&sandbox.join("vars/zz_test.yaml").to_string_lossy(), &format!("#!chgops.vars\n{}", vars))?;
+    }
+
+    let playbook_name = match &case.playbook {
+        serde_yaml::Value::String(name) => name.trim_end_matches(".yaml").to_string(),
+        serde_yaml::Value::Mapping(_) => {
+            files_and_dirs::write_file(&sandbox.join("test_playbook.yaml").to_string_lossy(), &format!("#!chgops\n{}", serde_yaml::to_string(&case.playbook)?))?;
+            "test_playbook".to_string()
+        },
+        _ => return Err("playbook must be a playbook name or a playbook".into()),
+    };
+    if !sandbox.join(format!("{}.yaml", playbook_name)).exists() {
+        return Err(format!("Playbook not found: {}.yaml", playbook_name).into());
+    }
+
+    write_stubs(&sandbox.join("bin"), &case.stubs)?;
+    Ok(playbook_name)
+}
+
+// the environment is the process's, so the tests setting it run one at a time
+static ENVIRONMENT: Mutex<()> = Mutex::new(());
+
+/// The `env` of a test and its stubs in front of `PATH`, set until dropped.
+struct TestEnvironment {
+    previous: Vec<(String, Option<String>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestEnvironment {
+    fn set(case: &TestCase, bin_folder: &Path) -> TestEnvironment {
+        let lock = ENVIRONMENT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let path = format!("{}:{}", bin_folder.to_string_lossy(), std::env::var("PATH").unwrap_or_default());
+        let mut previous = vec![];
+        for (name, value) in case.env.iter().map(|(n, v)| (n.clone(), v.clone())).chain([("PATH".to_string(), path)]) {
+            previous.push((name.clone(), std::env::var(&name).ok()));
+            std::env::set_var(name, value);
+        }
+        TestEnvironment { previous, _lock: lock }
+    }
+}
+
+impl Drop for TestEnvironment {
+    fn drop(&mut self) {
+        for (name, value) in self.previous.iter().rev() {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+    }
+}
+
+/// Keeps what the tasks of a test run write, shown when the test fails.
+struct Transcript(Arc<Mutex<String>>);
+
+impl RunObserver for Transcript {
+    fn task_start(&mut self, task: &dyn PlaybookTask) {
+        self.0.lock().unwrap().push_str(&format!("TASK [{}]\n", task.info().name));
+    }
+
+    fn task_output(&mut self, _task: &PlaybookTaskInfo, _stream: OutputStream, chunk: &str) {
+        self.0.lock().unwrap().push_str(chunk);
+    }
+
+    fn task_message(&mut self, _task: &PlaybookTaskInfo, _level: MessageLevel, message: &str) {
+        self.0.lock().unwrap().push_str(&format!("{}\n", message));
+    }
+}
+
+/// Runs a test playbook in process, with the mocks of the test, and checks the expectations
+/// against the report and the facts of the run.
+///
+/// The run uses a copy of `workspace` when given, otherwise a workspace loading only `collections`.
+pub fn run_test(name: &str, case: &TestCase, workspace: Option<&Path>, collections: &[CollectionDependency]) -> TestResult {
+    let started = Instant::now();
+    let mut result = TestResult {
+        name: name.to_string(),
+        description: case.description.clone(),
+        ..Default::default()
+    };
+
+    let sandbox = std::env::temp_dir().join(format!("chgops-test-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&sandbox);
+    match prepare_workspace(&sandbox, case, workspace, collections) {
+        Ok(playbook_name) => {
+            let transcript = Arc::new(Mutex::new(String::new()));
+            let environment = TestEnvironment::set(case, &sandbox.join("bin"));
+            match run_in(&sandbox, &playbook_name, case, Transcript(transcript.clone())) {
+                Ok((report, context)) => result.failures.extend(check(&case.expect, &report, &context.facts.context)),
+                Err(e) => result.failures.push(format!("the run did not start: {}", e)),
+            }
+            drop(environment);
+            result.output = transcript.lock().unwrap().clone();
+        },
+        Err(e) => result.failures.push(format!("preparing the test workspace: {}", e)),
+    }
+
+    let _ = fs::remove_dir_all(&sandbox);
+    result.seconds = started.elapsed().as_secs_f64();
+    result
+}
+
+// loads and runs the playbook of the sandbox, returning the report and the context the run ended with
+fn run_in(sandbox: &Path, playbook_name: &str, case: &TestCase, transcript: Transcript) -> Result<(RunReport, RunContext), Box<dyn Error>> {
+    let arguments = case.run_arguments();
+    let arguments: Vec<(&str, &str)> = arguments.split(',').filter_map(|pair| pair.split_once('=')).collect();
+    let stage = arguments.iter().find(|(key, _)| *key == "STAGE").map(|(_, stage)| *stage).unwrap_or("dev");
+    let mocks = MockRegistry {
+        mocks: case.mocks.clone(),
+        strict: case.strict,
+        ..Default::default()
+    };
+    let mut workspace = Workspace::open(sandbox)?
+        .with_playbook(playbook_name)
+        .with_stage(stage)
+        .with_args(arguments.iter().copied())
+        .with_mocks(mocks);
+    let mut context = workspace.run_context()?;
+    context.observers.add(Box::new(transcript));
+    let report = workspace.execute(&mut context)?;
+    Ok((report, context))
+}
+
+fn compare(failures: &mut Vec<String>, what: &str, expected: Option<i32>, actual: i32) {
+    if let Some(expected) = expected {
+        if expected != actual {
+            failures.push(format!("{}: expected {}, found {}", what, expected, actual));
+        }
+    }
+}
+
+/// Every expectation the report and `facts`, the facts the run ended with, do not meet.
+pub fn check(expect: &Expectations, report: &RunReport, facts: &tera::Context) -> Vec<String> {
+    let mut failures = vec![];
+
+    if let Some(summary) = &expect.summary {
+        compare(&mut failures, "summary.executed", summary.executed, report.summary.tasks_counter);
+        compare(&mut failures, "summary.success", summary.success, report.summary.success_counter);
+        compare(&mut failures, "summary.failed", summary.failed, report.summary.failed_counter);
+        compare(&mut failures, "summary.skipped", summary.skipped, report.summary.skipped_counter);
+        compare(&mut failures, "summary.changed", summary.changed, report.summary.changed_counter);
+    }
+
+    for expected in expect.tasks.iter() {
+        let Some(task) = report.tasks.iter().find(|t| t.info.name == expected.name) else {
+            failures.push(format!("task {} not found in the run", expected.name));
+            continue;
+        };
+        let output = &task.output;
+        if let Some(stdout) = &expected.stdout {
+            if output.stdout.trim_end() != stdout.trim_end() {
+                failures.push(format!("task {}: stdout expected {:?}, found {:?}", expected.name, stdout, output.stdout));
+            }
+        }
+        if let Some(fragment) = &expected.stdout_contains {
+            if !output.stdout.contains(fragment.as_str()) {
+                failures.push(format!("task {}: stdout does not contain {:?}", expected.name, fragment));
+            }
+        }
+        for (what, expected_flag, actual) in [
+            ("failed", expected.failed, output.failed > 0),
+            ("skipped", expected.skipped, output.skipped > 0),
+            ("changed", expected.changed, output.changed > 0),
+            ("mocked", expected.mocked, output.mocked),
+        ] {
+            if let Some(expected_flag) = expected_flag {
+                if expected_flag != actual {
+                    failures.push(format!("task {}: {} expected {}, found {}", expected.name, what, expected_flag, actual));
+                }
+            }
+        }
+    }
+
+    for (register, expected) in expect.registered.iter() {
+        let registered = report.tasks.iter()
+            .any(|t| t.info.register.as_deref() == Some(register.as_str()))
+            .then(|| facts.get(register))
+            .flatten();
+        let expected_json = serde_json::to_value(expected).unwrap_or_default();
+        match registered {
+            Some(value) if *value == expected_json => {},
+            Some(value) => failures.push(format!("registered {}: expected {}, found {}", register, expected_json, value)),
+            None => failures.push(format!("nothing was registered as {}", register)),
+        }
+    }
+
+    if !expect.assertions.is_empty() || !expect.facts.is_empty() {
+        match assertion_context(report, facts) {
+            Ok(context) => {
+                let facts = context.clone().into_json();
+                for (path, expected) in expect.facts.iter() {
+                    let found = path.split('.').try_fold(&facts, |value, key| value.get(key));
+                    let expected_json = serde_json::to_value(expected).unwrap_or_default();
+                    match found {
+                        Some(value) if *value == expected_json => {},
+                        Some(value) => failures.push(format!("fact {}: expected {}, found {}", path, expected_json, value)),
+                        None => failures.push(format!("fact {} is not defined", path)),
+                    }
+                }
+                for assertion in expect.assertions.iter() {
+                    let template = format!("{{% if {} %}}true{{% else %}}false{{% endif %}}", assertion.that);
+                    match config_proc::process_template(&template, &context, &Secrets::default()) {
+                        Ok(value) if value == "true" => {},
+                        Ok(_) => failures.push(assertion.msg.clone().unwrap_or(format!("assertion failed: {}", assertion.that))),
+                        Err(e) => failures.push(format!("assertion {}: {}", assertion.that, e)),
+                    }
+                }
+            },
+            Err(e) => failures.push(format!("reading the facts for assertions: {}", e)),
+        }
+    }
+
+    failures
+}
+
+/// Facts the assertions see: the facts of the run, with the vars, `settings` and registered values,
+/// plus `summary` and the `tasks.<name>` outputs.
+fn assertion_context(report: &RunReport, facts: &tera::Context) -> Result<tera::Context, Box<dyn Error>> {
+    let mut context = facts.clone();
+    context.insert("summary", &report.summary);
+    let tasks: BTreeMap<String, _> = report.tasks.iter().map(|t| (t.info.name.clone(), &t.output)).collect();
+    context.insert("tasks", &tasks);
+    Ok(context)
+}
+
+/// Renders test results as a JUnit document, one testcase per test.
+pub fn to_junit(suite: &str, results: &[TestResult]) -> String {
+    let failures = results.iter().filter(|r| !r.passed()).count();
+    let total_seconds: f64 = results.iter().map(|r| r.seconds).sum();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"chgops test\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        results.len(), failures, total_seconds
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+        xml_escape(suite), results.len(), failures, total_seconds
+    ));
+    for result in results.iter() {
+        xml.push_str(&format!(
+            "    <testcase name=\"{}\" classname=\"{}\" time=\"{:.3}\">\n",
+            xml_escape(&result.name), xml_escape(suite), result.seconds
+        ));
+        if !result.passed() {
+            xml.push_str(&format!(
+                "      <failure message=\"{}\">{}</failure>\n",
+                xml_escape(&result.failures[0]), xml_escape(&result.failures.join("\n"))
+            ));
+            xml.push_str(&format!("      <system-out>{}</system-out>\n", xml_escape(&result.output)));
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n");
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Prints the pass/fail line of every test and the totals, returning the number of failed tests.
+pub fn display_results(scope: &str, results: &[TestResult]) -> usize {
+    println!("Test results: {} #####################################", scope);
+    for result in results.iter() {
+        if result.passed() {
+            print_success!("PASS {} ({:.2}s) {}", result.name, result.seconds, result.description.clone().unwrap_or_default());
+        } else {
+            print_error!("FAIL {} ({:.2}s) {}", result.name, result.seconds, result.description.clone().unwrap_or_default());
+            for failure in result.failures.iter() {
+                println!("\t{}", failure);
+            }
+        }
+    }
+    let failed = results.iter().filter(|r| !r.passed()).count();
+    println!("\tTests: {}\tPassed: {}\tFailed: {}", results.len(), results.len() - failed, failed);
+    println!("#############################################");
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("chgops-testing-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("vars")).unwrap();
+        fs::write(path.join("vars/vars.yaml"), "vars:\n  greeting: hello\n  db:\n    password: s3cr3t-value\n").unwrap();
+        path
+    }
+
+    fn case(yaml: &str) -> TestCase {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    const PLAYBOOK: &str = r#"
+playbook:
+  name: greet
+  settings:
+    name: greet
+  tasks:
+    - dx.core.bash:
+        name: "greet"
+        command: "echo hello"
+        vars:
+          resource: ""
+    - dx.core.print:
+        name: "remember"
+        register: greeting
+        vars:
+          resource: "{{ vars.greeting }}"
+"#;
+
+    #[test]
+    fn a_test_checks_the_report_and_the_unmasked_facts_of_its_run() {
+        let path = workspace("pass");
+        let case = case(&format!("{}{}", PLAYBOOK, r#"
+expect:
+  summary: { executed: 2, failed: 0 }
+  tasks:
+    - name: greet
+      stdout: hello
+      failed: false
+  registered:
+    greeting: hello
+  facts:
+    vars.db.password: s3cr3t-value
+  assertions:
+    - that: "greeting == vars.greeting and summary.failed_counter == 0"
+"#));
+
+        let result = run_test("pass", &case, Some(&path), &[]);
+
+        assert_eq!(result.failures, Vec::<String>::new());
+        assert!(result.output.contains("TASK [greet]"), "{}", result.output);
+        assert!(!path.join(".chgops").exists());
+    }
+
+    #[test]
+    fn every_expectation_the_run_misses_is_a_failure() {
+        let path = workspace("fail");
+        let case = case(&format!("{}{}", PLAYBOOK, r#"
+expect:
+  summary: { executed: 3 }
+  tasks:
+    - name: greet
+      stdout_contains: goodbye
+    - name: missing
+  registered:
+    other: x
+  facts:
+    vars.greeting: goodbye
+  assertions:
+    - that: "vars.greeting == 'goodbye'"
+      msg: the greeting is not goodbye
+"#));
+
+        let result = run_test("fail", &case, Some(&path), &[]);
+
+        assert_eq!(result.failures, vec![
+            "summary.executed: expected 3, found 2".to_string(),
+            "task greet: stdout does not contain \"goodbye\"".to_string(),
+            "task missing not found in the run".to_string(),
+            "nothing was registered as other".to_string(),
+            "fact vars.greeting: expected \"goodbye\", found \"hello\"".to_string(),
+            "the greeting is not goodbye".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn mocks_stubs_and_env_only_apply_to_their_test() {
+        let path = workspace("doubles");
+        let case = case(r#"
+env:
+  CHGOPS_TESTING_GREETING: hi
+stubs:
+  chgops-testing-tool:
+    stdout: "from the stub"
+mocks:
+  - name: mocked
+    output:
+      stdout: canned
+playbook:
+  name: doubles
+  settings:
+    name: doubles
+  tasks:
+    - dx.core.bash:
+        name: stubbed
+        command: "chgops-testing-tool; echo \" $CHGOPS_TESTING_GREETING\""
+        vars:
+          resource: ""
+    - dx.core.bash:
+        name: mocked
+        command: "exit 1"
+        vars:
+          resource: ""
+expect:
+  summary: { executed: 2, failed: 0 }
+  tasks:
+    - name: stubbed
+      stdout: from the stub hi
+    - name: mocked
+      stdout: canned
+      mocked: true
+"#);
+
+        let result = run_test("doubles", &case, Some(&path), &[]);
+
+        assert_eq!(result.failures, Vec::<String>::new());
+        assert!(std::env::var("CHGOPS_TESTING_GREETING").is_err());
+        assert!(!std::env::var("PATH").unwrap().contains("chgops-test-"));
+    }
+
+    #[test]
+    fn a_test_whose_playbook_is_missing_fails_before_running() {
+        let path = workspace("missing");
+        let result = run_test("missing", &case("playbook: nothing\n"), Some(&path), &[]);
+
+        assert_eq!(result.failures, vec!["preparing the test workspace: Playbook not found: nothing.yaml".to_string()]);
+    }
+
+    #[test]
+    fn run_arguments_take_the_stage_from_the_test() {
+        assert_eq!(case("playbook: p\n").run_arguments(), "STAGE=dev");
+        assert_eq!(case("playbook: p\narguments: STAGE=dev, CONFIRM=yes\nstage: prod\n").run_arguments(), "CONFIRM=yes,STAGE=prod");
+    }
+
+    #[test]
+    fn a_collection_test_loads_only_the_collection_under_test() {
+        let dependency = CollectionDependency {
+            name: "dx.azure".to_string(),
+            path: Some(fs::canonicalize("./collections/dx/azure").unwrap().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let case = case(r#"
+stubs:
+  az:
+    stdout: '{"name": "deixei-dev"}'
+vars:
+  owner: ops
+playbook:
+  name: collection
+  settings:
+    name: collection
+  tasks:
+    - dx.core.bash:
+        name: "current subscription"
+        command: "az account show"
+        vars:
+          resource: {}
+expect:
+  tasks:
+    - name: "current subscription"
+      stdout_contains: deixei-dev
+  facts:
+    owner: ops
+  assertions:
+    - that: "common_vars.version == '1.0.0' and demo_basic is undefined"
+"#);
+
+        let result = run_test("collection", &case, None, std::slice::from_ref(&dependency));
+
+        assert_eq!(result.failures, Vec::<String>::new());
+    }
+
+    #[test]
+    fn the_tests_of_a_collection_are_its_yaml_files_by_name() {
+        let folder = workspace("find").join("tests");
+        fs::create_dir_all(&folder).unwrap();
+        for file in ["b.yaml", "a.yaml", "notes.md"] {
+            fs::write(folder.join(file), "").unwrap();
+        }
+
+        let names: Vec<String> = find_tests(&folder).unwrap().iter().map(|test| test_name(test)).collect();
+        assert_eq!(names, vec!["a", "b"]);
+        assert!(find_tests(&folder.join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn junit_has_a_testcase_per_test_and_the_failures_of_the_failed_ones() {
+        let results = vec![
+            TestResult { name: "ok".to_string(), seconds: 0.5, ..Default::default() },
+            TestResult {
+                name: "broken".to_string(),
+                failures: vec!["summary.failed: expected 0, found 1".to_string(), "fact a is not defined".to_string()],
+                output: "<out>".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let xml = to_junit("dx.azure", &results);
+
+        assert!(xml.contains("<testsuite name=\"dx.azure\" tests=\"2\" failures=\"1\" time=\"0.500\">"));
+        assert!(xml.contains("<testcase name=\"ok\" classname=\"dx.azure\" time=\"0.500\">\n    </testcase>"));
+        assert!(xml.contains("<failure message=\"summary.failed: expected 0, found 1\">summary.failed: expected 0, found 1\nfact a is not defined</failure>"));
+        assert!(xml.contains("<system-out>&lt;out&gt;</system-out>"));
+    }
+}
@@ -0,0 +1,475 @@
+// checks playbooks and vars files against the task and collection schemas, before anything is templated
+
+use serde_yaml::Value as YamlValue;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::Marker;
+use crate::collections::dx::collection::{self, CollectionVariable};
+use crate::collections::dx::files_and_dirs;
+use crate::collections::dx::task_registry::{key_text, yaml_kind, TaskDefinition, TaskField, TaskRegistry};
+use crate::collections::dx::vault::Vault;
+
+/// Fields every task accepts besides `command` and `vars`.
+const TASK_FIELDS: [&str; 7] = ["command", "name", "vars", "register", "state", "when", "no_log"];
+
+/// A problem found in a source file.
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.file, self.line, self.column, self.message)
+    }
+}
+
+/// The issues that stop a playbook from loading.
+#[derive(Debug, Clone)]
+pub struct ValidationFailed(pub Vec<ValidationIssue>);
+
+impl fmt::Display for ValidationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in self.0.iter() {
+            writeln!(f, "{}", issue)?;
+        }
+        write!(f, "{} validation errors, see `chgops validate`", self.0.len())
+    }
+}
+
+impl std::error::Error for ValidationFailed {}
+
+/// Where every node of a yaml document starts, by path such as `tasks[0].dx.core.bash.vars`.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    pub file: String,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+enum Frame {
+    /// `locate` until the first key of a list item or document gives the mapping its position
+    Mapping { path: String, key: Option<String>, locate: bool },
+    Sequence { path: String, index: usize },
+}
+
+#[derive(Default)]
+struct PositionRecorder {
+    stack: Vec<Frame>,
+    positions: HashMap<String, (usize, usize)>,
+}
+
+impl PositionRecorder {
+    // path of the node starting now, `None` when it is a mapping key
+    fn node_path(&mut self, event: &Event, mark: Marker) -> Option<String> {
+        let position = (mark.line(), mark.col() + 1);
+        let path = match self.stack.last_mut() {
+            None => String::new(),
+            Some(Frame::Sequence { path, index }) => {
+                *index += 1;
+                format!("{}[{}]", path, *index - 1)
+            },
+            Some(Frame::Mapping { path, key, locate }) => match key.take() {
+                Some(key) => child_path(path, &key),
+                None => {
+                    // a key: the line of the key is where its value is reported
+                    let name = match event {
+                        Event::Scalar(value, ..) => value.clone(),
+                        _ => String::new(),
+                    };
+                    // the start of a block mapping is marked after its first key
+                    if std::mem::take(locate) {
+                        self.positions.insert(path.clone(), position);
+                    }
+                    self.positions.entry(child_path(path, &name)).or_insert(position);
+                    *key = Some(name);
+                    return None;
+                },
+            },
+        };
+        self.positions.entry(path.clone()).or_insert(position);
+        Some(path)
+    }
+}
+
+fn child_path(parent: &str, key: &str) -> String {
+    match parent.is_empty() {
+        true => key.to_string(),
+        false => format!("{}.{}", parent, key),
+    }
+}
+
+impl MarkedEventReceiver for PositionRecorder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        match event {
+            Event::Scalar(..) | Event::Alias(..) => {
+                self.node_path(&event, mark);
+            },
+            Event::MappingStart(..) => {
+                let locate = !matches!(self.stack.last(), Some(Frame::Mapping { .. }));
+                let path = self.node_path(&event, mark).unwrap_or_default();
+                self.stack.push(Frame::Mapping { path, key: None, locate });
+            },
+            Event::SequenceStart(..) => {
+                let path = self.node_path(&event, mark).unwrap_or_default();
+                self.stack.push(Frame::Sequence { path, index: 0 });
+            },
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.stack.pop();
+            },
+            _ => {},
+        }
+    }
+}
+
+impl SourceMap {
+    /// Parses `content` of `file`, returning its value and node positions or the syntax error.
+    pub fn parse(file: &str, content: &str) -> Result<(YamlValue, SourceMap), ValidationIssue> {
+        let mut recorder = PositionRecorder::default();
+        if let Err(e) = Parser::new_from_str(content).load(&mut recorder, false) {
+            return Err(ValidationIssue {
+                file: file.to_string(),
+                line: e.marker().line(),
+                column: e.marker().col() + 1,
+                message: format!("invalid YAML: {}", e.info()),
+            });
+        }
+        let value = serde_yaml::from_str::<Option<YamlValue>>(content).map_err(|e| {
+            let (line, column) = e.location().map(|l| (l.line(), l.column())).unwrap_or((1, 1));
+            ValidationIssue { file: file.to_string(), line, column, message: e.to_string() }
+        })?;
+        let map = SourceMap {
+            file: file.to_string(),
+            positions: recorder.positions,
+        };
+        Ok((value.unwrap_or(YamlValue::Null), map))
+    }
+
+    /// Position of `path`, or of its closest parent found.
+    pub fn position(&self, path: &str) -> (usize, usize) {
+        let mut path = path.to_string();
+        loop {
+            if let Some(position) = self.positions.get(&path) {
+                return *position;
+            }
+            match path.rfind(['.', '[']) {
+                Some(index) => path.truncate(index),
+                None => return self.positions.get("").cloned().unwrap_or((1, 1)),
+            }
+        }
+    }
+
+    pub fn issue(&self, path: &str, message: String) -> ValidationIssue {
+        let (line, column) = self.position(path);
+        ValidationIssue {
+            file: self.file.clone(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// Template strings are rendered later, so they are accepted for any type.
+fn is_template(value: &YamlValue) -> bool {
+    value.as_str().map(|s| s.contains("{{") || s.contains("{%")).unwrap_or(false)
+}
+
+fn check_field(map: &SourceMap, path: &str, field: &TaskField, value: Option<&YamlValue>, issues: &mut Vec<ValidationIssue>) {
+    match value {
+        None if field.required => issues.push(map.issue(path.rsplit_once('.').map(|p| p.0).unwrap_or(path), format!("missing required field {}", field.name))),
+        Some(value) if !is_template(value) && !collection::type_matches(&field.field_type, value) => {
+            issues.push(map.issue(path, format!("{} must be {} {}, found {}", field.name, article(&field.field_type), field.field_type, yaml_kind(value))));
+        },
+        _ => {},
+    }
+}
+
+fn article(field_type: &str) -> &'static str {
+    match field_type {
+        "object" | "any" => "an",
+        _ => "a",
+    }
+}
+
+/// Checks a task body against the schema of its type.
+fn check_task(map: &SourceMap, path: &str, definition: &TaskDefinition, body: &YamlValue, issues: &mut Vec<ValidationIssue>) {
+    let Some(fields) = body.as_mapping() else {
+        issues.push(map.issue(path, format!("{} must be a mapping, found {}", definition.name, yaml_kind(body))));
+        return;
+    };
+    for (key, _) in fields.iter() {
+        let key = key_text(key);
+        if !TASK_FIELDS.contains(&key.as_str()) {
+            issues.push(map.issue(&child_path(path, &key), format!("unknown field {}, expected one of: {}", key, TASK_FIELDS.join(", "))));
+        }
+    }
+    for common in ["name", "register", "state", "when"] {
+        if let Some(value) = body.get(common) {
+            if !value.is_string() && !value.is_bool() {
+                issues.push(map.issue(&child_path(path, common), format!("{} must be a string, found {}", common, yaml_kind(value))));
+            }
+        }
+    }
+    if let Some(no_log) = body.get("no_log").filter(|value| !value.is_bool()) {
+        issues.push(map.issue(&child_path(path, "no_log"), format!("no_log must be a bool, found {}", yaml_kind(no_log))));
+    }
+
+    if let Some(command) = &definition.command {
+        check_field(map, &child_path(path, "command"), command, body.get("command"), issues);
+    }
+
+    let vars_path = child_path(path, "vars");
+    match body.get("vars") {
+        None => issues.push(map.issue(path, "missing required field vars".to_string())),
+        Some(vars) if !definition.vars.is_empty() => match vars.as_mapping() {
+            Some(values) => {
+                for field in definition.vars.iter() {
+                    check_field(map, &child_path(&vars_path, &field.name), field, vars.get(&field.name), issues);
+                }
+                for (key, _) in values.iter() {
+                    let key = key_text(key);
+                    if !definition.vars.iter().any(|f| f.name == key) {
+                        let expected = definition.vars.iter().map(|f| f.name.as_str()).collect::<Vec<_>>().join(", ");
+                        issues.push(map.issue(&child_path(&vars_path, &key), format!("unknown var {} of {}, expected one of: {}", key, definition.name, expected)));
+                    }
+                }
+            },
+            None => issues.push(map.issue(&vars_path, format!("vars must be a mapping, found {}", yaml_kind(vars)))),
+        },
+        Some(_) => {},
+    }
+
+    if let Some(state) = body.get("state").and_then(|s| s.as_str()) {
+        if !definition.states.is_empty() && !is_template(&YamlValue::String(state.to_string())) && !definition.states.iter().any(|s| s == state) {
+            issues.push(map.issue(&child_path(path, "state"), format!("state {} is not accepted by {}, expected one of: {}", state, definition.name, definition.states.join(", "))));
+        }
+    }
+}
+
+/// Checks a playbook file: its layout, then every task against the task registry.
+pub fn validate_playbook(file: &str, registry: &TaskRegistry, collections: &[(String, String)]) -> Vec<ValidationIssue> {
+    let content = match files_and_dirs::read_file(file) {
+        Ok(content) => content,
+        Err(e) => return vec![ValidationIssue { file: file.to_string(), line: 1, column: 1, message: e.to_string() }],
+    };
+    let (playbook, map) = match SourceMap::parse(file, &content) {
+        Ok(parsed) => parsed,
+        Err(issue) => return vec![issue],
+    };
+
+    let mut issues = vec![];
+    if !playbook.is_mapping() {
+        issues.push(map.issue("", format!("a playbook must be a mapping, found {}", yaml_kind(&playbook))));
+        return issues;
+    }
+    if !playbook.get("name").map(|n| n.is_string()).unwrap_or(false) {
+        issues.push(map.issue("name", "name must be a string".to_string()));
+    }
+    match playbook.get("settings") {
+        Some(settings) if settings.get("name").map(|n| n.is_string()).unwrap_or(false) => {},
+        Some(_) => issues.push(map.issue("settings", "missing required field settings.name".to_string())),
+        None => issues.push(map.issue("", "missing required field settings".to_string())),
+    }
+
+    let tasks = match playbook.get("tasks") {
+        Some(YamlValue::Sequence(tasks)) => tasks,
+        Some(other) => {
+            issues.push(map.issue("tasks", format!("tasks must be a list, found {}", yaml_kind(other))));
+            return issues;
+        },
+        None => {
+            issues.push(map.issue("", "missing required field tasks".to_string()));
+            return issues;
+        },
+    };
+
+    for (index, task) in tasks.iter().enumerate() {
+        let path = format!("tasks[{}]", index);
+        let entries = match task.as_mapping() {
+            Some(entries) if entries.len() == 1 => entries,
+            _ => {
+                issues.push(map.issue(&path, format!("task #{}: expected a mapping with exactly one task type key", index + 1)));
+                continue;
+            },
+        };
+        let (key, body) = entries.iter().next().unwrap();
+        let task_type = key_text(key);
+        let task_path = child_path(&path, &task_type);
+        let before = issues.len();
+
+        match registry.get(&task_type) {
+            Some(definition) => check_task(&map, &task_path, definition, body, &mut issues),
+            None if registry.factory(&task_type, collections).is_some() => {
+                if body.get("vars").is_none() {
+                    issues.push(map.issue(&task_path, "missing required field vars".to_string()));
+                }
+            },
+            None => {
+                issues.push(map.issue(&path, registry.unknown_task(&task_type, collections)));
+                continue;
+            },
+        }
+
+        // whatever the schema does not describe, e.g. the fields of a command object
+        if issues.len() == before {
+            if let Err(e) = registry.parse(index, task.clone(), collections) {
+                issues.push(map.issue(&task_path, e));
+            }
+        }
+    }
+    issues
+}
+
+/// Variables declared by the manifests of the loaded collections, with the manifest declaring each.
+pub fn declared_variables(collections: &[(String, String)]) -> Result<BTreeMap<String, (String, CollectionVariable)>, Box<dyn Error>> {
+    let mut declared = BTreeMap::new();
+    for (_, folder) in collections.iter() {
+        if let Some(manifest) = collection::read_manifest(Path::new(folder))? {
+            let manifest_file = Path::new(folder).join(collection::MANIFEST_FILE).to_string_lossy().to_string();
+            for (name, variable) in manifest.variables {
+                declared.insert(name, (manifest_file.clone(), variable));
+            }
+        }
+    }
+    Ok(declared)
+}
+
+/// Checks the top level keys of vars files against the variables the collections declare.
+pub fn validate_vars(files: &[String], declared: &BTreeMap<String, (String, CollectionVariable)>, vault: &Vault) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+    let mut defined: Vec<String> = vec![];
+
+    for file in files.iter() {
+        let content = match vault.read(file) {
+            Ok(content) => content,
+            Err(e) => {
+                issues.push(ValidationIssue { file: file.clone(), line: 1, column: 1, message: e.to_string() });
+                continue;
+            },
+        };
+        let (vars, map) = match SourceMap::parse(file, &content) {
+            Ok(parsed) => parsed,
+            Err(issue) => {
+                issues.push(issue);
+                continue;
+            },
+        };
+        let Some(vars) = vars.as_mapping() else { continue };
+        for (key, value) in vars.iter() {
+            let name = key_text(key);
+            if let Some((_, variable)) = declared.get(&name) {
+                if !is_template(value) && !collection::type_matches(&variable.var_type, value) {
+                    issues.push(map.issue(&name, format!("variable {} is declared as {} but is {}", name, variable.var_type, collection::type_name(value))));
+                }
+            }
+            defined.push(name);
+        }
+    }
+
+    for (name, (manifest_file, variable)) in declared.iter() {
+        if variable.required && variable.default.is_none() && !defined.contains(name) {
+            let message = format!("required variable {} is not defined in any vars file", name);
+            let issue = match files_and_dirs::read_file(manifest_file).ok().and_then(|c| SourceMap::parse(manifest_file, &c).ok()) {
+                Some((_, map)) => map.issue(&format!("variables.{}", name), message),
+                None => ValidationIssue { file: manifest_file.clone(), line: 1, column: 1, message },
+            };
+            issues.push(issue);
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(name: &str, content: &str) -> String {
+        let folder = std::env::temp_dir().join(format!("chgops-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        let path = folder.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn messages(issues: &[ValidationIssue], file: &str) -> Vec<String> {
+        issues.iter().map(|issue| issue.to_string().replace(file, "FILE")).collect()
+    }
+
+    #[test]
+    fn a_valid_playbook_has_no_issues() {
+        let registry = TaskRegistry::with_builtins();
+        assert_eq!(messages(&validate_playbook("./playbooks/workspace2/playbook.yaml", &registry, &[]), ""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn playbook_issues_point_at_their_source_line() {
+        let file = write("playbook.yaml", r#"#!chgops
+name: checks
+settings:
+  name: checks
+tasks:
+  - dx.core.bash:
+      command: 42
+      vars:
+        resource: ""
+        args: "-x"
+  - dx.core.print:
+      name: "state"
+      state: gone
+      retries: 3
+      vars:
+        resource: "{{ vars.message }}"
+  - dx.core.prnt:
+      vars:
+        resource: ""
+  - dx.core.prompt:
+      vars:
+        default: bob
+"#);
+
+        let issues = validate_playbook(&file, &TaskRegistry::with_builtins(), &[]);
+
+        assert_eq!(messages(&issues, &file), vec![
+            "FILE:7:7: command must be a string, found a number",
+            "FILE:10:9: unknown var args of dx.core.bash, expected one of: resource",
+            "FILE:14:7: unknown field retries, expected one of: command, name, vars, register, state, when, no_log",
+            "FILE:13:7: state gone is not accepted by dx.core.print, expected one of: present, absent",
+            "FILE:17:5: unknown task type dx.core.prnt. Did you mean dx.core.print or dx.core.prompt? Run `chgops tasks list` for the available tasks",
+            "FILE:21:7: missing required field question",
+        ]);
+    }
+
+    #[test]
+    fn a_playbook_without_settings_or_tasks_is_reported() {
+        let file = write("empty.yaml", "name: 42\n");
+        let issues = validate_playbook(&file, &TaskRegistry::with_builtins(), &[]);
+        assert_eq!(messages(&issues, &file), vec![
+            "FILE:1:1: name must be a string",
+            "FILE:1:1: missing required field settings",
+            "FILE:1:1: missing required field tasks",
+        ]);
+    }
+
+    #[test]
+    fn vars_are_checked_against_the_variables_the_collections_declare() {
+        let manifest = write("collection.yaml", "namespace: acme\nname: tools\nversion: 1.0.0\nvariables:\n  tags:\n    type: object\n  region:\n    type: string\n    required: true\n  size:\n    type: number\n    required: true\n    default: 1\n");
+        let vars = write("vars.yaml", "tags:\n  - a\nowner: \"{{ vars.owner }}\"\nsize: \"{{ vars.size }}\"\n");
+        let declared: BTreeMap<String, (String, CollectionVariable)> = collection::read_manifest(Path::new(&manifest).parent().unwrap())
+            .unwrap().unwrap().variables.into_iter()
+            .map(|(name, variable)| (name, (manifest.clone(), variable)))
+            .collect();
+
+        let issues = validate_vars(std::slice::from_ref(&vars), &declared, &Vault::default());
+
+        assert_eq!(issues.iter().map(|issue| issue.to_string()).collect::<Vec<_>>(), vec![
+            format!("{}:1:1: variable tags is declared as object but is list", vars),
+            format!("{}:7:3: required variable region is not defined in any vars file", manifest),
+        ]);
+    }
+}
@@ -0,0 +1,404 @@
+// encrypted vars files and the secret values they hold
+//
+// $CHGOPS_VAULT;1.0;AES256-GCM
+// <base64 of salt, nonce and ciphertext, wrapped at 76 columns>
+//
+// The key is derived from the vault password with PBKDF2-HMAC-SHA256 and a random salt per file.
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose, Engine as _};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_yaml::Value as YamlValue;
+use sha2::Sha256;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::sync::{Arc, Mutex, RwLock};
+use crate::collections::dx::{files_and_dirs, provenance};
+use crate::collections::log;
+
+pub const VAULT_HEADER: &str = "$CHGOPS_VAULT;1.0;AES256-GCM";
+pub const PASSWORD_ENV: &str = "CHGOPS_VAULT_PASSWORD";
+pub const PASSWORD_FILE_ENV: &str = "CHGOPS_VAULT_PASSWORD_FILE";
+/// What a secret value is replaced with.
+pub const SECRET_MASK: &str = "********";
+/// Secret values shorter than this are not masked, they would mask ordinary text.
+pub const SECRET_MIN_LENGTH: usize = 4;
+/// Vars with a name matching one of these, or set under one, are secrets; `*` matches any text.
+pub const SECRET_NAME_PATTERNS: [&str; 5] = ["*secret*", "*password*", "*passwd*", "*token*", "*private_key*"];
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const KEY_ITERATIONS: u32 = 100_000;
+
+/// Whether a file content is vault encrypted.
+pub fn is_encrypted(content: &str) -> bool {
+    content.trim_start().starts_with(VAULT_HEADER)
+}
+
+/// Whether a var name matches one of the [`SECRET_NAME_PATTERNS`], ignoring case.
+pub fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_NAME_PATTERNS.iter().any(|pattern| wildcard_match(pattern, &name))
+}
+
+// `*` matches any text, everything else itself
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let parts = pattern.split('*').collect::<Vec<_>>();
+    if parts.len() == 1 {
+        return pattern == text;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || !text[first.len()..].ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Encrypts and decrypts vars files with the vault password.
+///
+/// The password is read when first needed: the given one, then the password file, then
+/// `$CHGOPS_VAULT_PASSWORD_FILE` and `$CHGOPS_VAULT_PASSWORD`. Values of the files it decrypts are secrets.
+#[derive(Default)]
+pub struct Vault {
+    password_file: String,
+    password: Option<String>,
+    keys: Mutex<HashMap<Vec<u8>, [u8; 32]>>,
+    /// decrypted values by file
+    decrypted: Mutex<BTreeMap<String, YamlValue>>,
+}
+
+impl std::fmt::Debug for Vault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Vault({})", if self.password_file.is_empty() { "env" } else { &self.password_file })
+    }
+}
+
+impl Vault {
+    /// A vault reading its password from `password_file`, or from the environment when empty.
+    pub fn new(password_file: &str) -> Vault {
+        Vault {
+            password_file: password_file.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_password(password: &str) -> Vault {
+        Vault {
+            password: Some(password.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a password is available without asking for it.
+    pub fn has_password(&self) -> bool {
+        self.password.is_some()
+            || !self.password_file.is_empty()
+            || std::env::var(PASSWORD_FILE_ENV).is_ok_and(|file| !file.is_empty())
+            || std::env::var(PASSWORD_ENV).is_ok_and(|password| !password.is_empty())
+    }
+
+    fn password(&self) -> Result<String, Box<dyn Error>> {
+        if let Some(password) = &self.password {
+            return Ok(password.clone());
+        }
+        let file = match self.password_file.is_empty() {
+            true => std::env::var(PASSWORD_FILE_ENV).unwrap_or_default(),
+            false => self.password_file.clone(),
+        };
+        if !file.is_empty() {
+            let password = std::fs::read_to_string(&file).map_err(|e| format!("Reading vault password file {}: {}", file, e))?;
+            return Ok(password.trim_end_matches(['\r', '\n']).to_string());
+        }
+        match std::env::var(PASSWORD_ENV) {
+            Ok(password) if !password.is_empty() => Ok(password),
+            _ => Err(format!("No vault password: use --vault-password-file, {} or {}", PASSWORD_FILE_ENV, PASSWORD_ENV).into()),
+        }
+    }
+
+    fn key(&self, salt: &[u8]) -> Result<[u8; 32], Box<dyn Error>> {
+        if let Some(key) = self.keys.lock().unwrap().get(salt) {
+            return Ok(*key);
+        }
+        let mut key = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(self.password()?.as_bytes(), salt, KEY_ITERATIONS, &mut key);
+        self.keys.lock().unwrap().insert(salt.to_vec(), key);
+        Ok(key)
+    }
+
+    pub fn encrypt(&self, plain: &str) -> Result<String, Box<dyn Error>> {
+        let mut salt = [0u8; SALT_LENGTH];
+        OsRng.fill_bytes(&mut salt);
+        let key = self.key(&salt)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, plain.as_bytes()).map_err(|_| "Encrypting with the vault key failed")?;
+
+        let payload = [salt.as_slice(), nonce.as_slice(), ciphertext.as_slice()].concat();
+        let encoded = general_purpose::STANDARD.encode(payload);
+        let mut content = format!("{}\n", VAULT_HEADER);
+        for line in encoded.as_bytes().chunks(76) {
+            content.push_str(&String::from_utf8_lossy(line));
+            content.push('\n');
+        }
+        Ok(content)
+    }
+
+    pub fn decrypt(&self, content: &str) -> Result<String, Box<dyn Error>> {
+        let mut lines = content.trim().lines();
+        if lines.next().map(str::trim) != Some(VAULT_HEADER) {
+            return Err(format!("Not a vault file: the first line is not {}", VAULT_HEADER).into());
+        }
+        let encoded = lines.map(str::trim).collect::<String>();
+        let payload = general_purpose::STANDARD.decode(encoded).map_err(|e| format!("Corrupted vault file: {}", e))?;
+        if payload.len() < SALT_LENGTH + NONCE_LENGTH {
+            return Err("Corrupted vault file: too short".into());
+        }
+        let (salt, rest) = payload.split_at(SALT_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let key = self.key(salt)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let plain = cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| "Decryption failed: wrong vault password or modified file")?;
+        Ok(String::from_utf8(plain)?)
+    }
+
+    /// Reads a vars file, decrypting it when it is vault encrypted.
+    pub fn read(&self, file: &str) -> Result<String, Box<dyn Error>> {
+        let content = files_and_dirs::read_file(file)?;
+        if !is_encrypted(&content) {
+            return Ok(content);
+        }
+        let plain = self.decrypt(&content).map_err(|e| format!("{}: {}", file, e))?;
+        if let Ok(value) = serde_yaml::from_str::<YamlValue>(&plain) {
+            let mut secrets = Secrets::default();
+            secrets.add_yaml(&value);
+            log::redact(&secrets);
+            self.decrypted.lock().unwrap().insert(file.to_string(), value);
+        }
+        Ok(plain)
+    }
+
+    /// The values of the files decrypted so far, and the values `facts` has at their paths once rendered.
+    pub fn secrets(&self, facts: &YamlValue) -> Secrets {
+        let mut secrets = Secrets::default();
+        for value in self.decrypted.lock().unwrap().values() {
+            secrets.add_yaml(value);
+            for path in provenance::leaves(value, "").keys() {
+                if let Some(rendered) = provenance::lookup(facts, path) {
+                    secrets.add_yaml(rendered);
+                }
+            }
+        }
+        secrets
+    }
+}
+
+/// Values that must not be shown: they are masked in banners, events, reports and `as_json` output.
+#[derive(Debug, Default, Clone)]
+pub struct Secrets {
+    values: BTreeSet<String>,
+}
+
+/// The secrets of a run: the tasks add the ones they learn, e.g. a `secret` prompt answer, while the sinks mask with them.
+pub type SharedSecrets = Arc<RwLock<Secrets>>;
+
+impl Secrets {
+    pub fn shared(self) -> SharedSecrets {
+        Arc::new(RwLock::new(self))
+    }
+
+    pub fn add(&mut self, value: &str) {
+        if value.chars().count() >= SECRET_MIN_LENGTH {
+            self.values.insert(value.to_string());
+        }
+    }
+
+    /// Adds the values of `facts` whose name, or the name of a mapping above them, is a secret name.
+    pub fn add_named(&mut self, facts: &YamlValue) {
+        for (path, value) in provenance::leaves(facts, "").iter() {
+            let secret = path.split('.')
+                .map(|segment| segment.split('[').next().unwrap_or_default())
+                .any(is_secret_name);
+            if secret {
+                self.add_yaml(value);
+            }
+        }
+    }
+
+    /// Adds every string under `value`; numbers and booleans would mask ordinary text such as dates.
+    pub fn add_yaml(&mut self, value: &YamlValue) {
+        for leaf in provenance::leaves(value, "").values() {
+            if let YamlValue::String(text) = leaf {
+                self.add(text);
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: &Secrets) {
+        self.values.extend(other.values.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The text with every secret value replaced by the mask, longest values first.
+    pub fn mask(&self, text: &str) -> String {
+        let mut values = self.values.iter().collect::<Vec<_>>();
+        values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+        values.into_iter().fold(text.to_string(), |text, value| text.replace(value.as_str(), SECRET_MASK))
+    }
+
+    /// Masks every string of a JSON value.
+    pub fn mask_json(&self, value: &mut serde_json::Value) {
+        if self.is_empty() {
+            return;
+        }
+        match value {
+            serde_json::Value::String(text) => *text = self.mask(text),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| self.mask_json(item)),
+            serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| self.mask_json(field)),
+            _ => {},
+        }
+    }
+
+    /// Masks every string of a YAML value.
+    pub fn mask_yaml(&self, value: &mut YamlValue) {
+        if self.is_empty() {
+            return;
+        }
+        match value {
+            YamlValue::String(text) => *text = self.mask(text),
+            YamlValue::Sequence(items) => items.iter_mut().for_each(|item| self.mask_yaml(item)),
+            YamlValue::Mapping(fields) => fields.iter_mut().for_each(|(_, field)| self.mask_yaml(field)),
+            _ => {},
+        }
+    }
+
+    /// A copy of `value` with the secrets masked in its strings.
+    pub fn mask_value<T: Serialize + DeserializeOwned>(&self, value: &T) -> Result<T, Box<dyn Error>> {
+        let mut json = serde_json::to_value(value)?;
+        self.mask_json(&mut json);
+        Ok(serde_json::from_value(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: &str = "db:\n  password: hunter2-secret\n  port: 5432\n";
+
+    #[test]
+    fn encrypt_and_decrypt_round_trip() {
+        let vault = Vault::with_password("vault-pass-1");
+        let encrypted = vault.encrypt(PLAIN).unwrap();
+
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(encrypted.lines().next(), Some(VAULT_HEADER));
+        assert!(encrypted.lines().all(|line| line.len() <= 76));
+        assert!(!encrypted.contains("hunter2"));
+        assert_eq!(vault.decrypt(&encrypted).unwrap(), PLAIN);
+        // a fresh salt and nonce per encryption
+        assert_ne!(vault.encrypt(PLAIN).unwrap(), encrypted);
+    }
+
+    #[test]
+    fn decrypt_fails_with_another_password_or_a_modified_file() {
+        let encrypted = Vault::with_password("vault-pass-1").encrypt(PLAIN).unwrap();
+
+        let err = Vault::with_password("vault-pass-2").decrypt(&encrypted).unwrap_err();
+        assert!(err.to_string().contains("wrong vault password"));
+
+        let mut lines = encrypted.lines().map(str::to_string).collect::<Vec<_>>();
+        let last = lines.len() - 1;
+        lines[last] = lines[last].chars().rev().collect();
+        assert!(Vault::with_password("vault-pass-1").decrypt(&lines.join("\n")).is_err());
+    }
+
+    #[test]
+    fn decrypt_rejects_plain_files() {
+        assert!(!is_encrypted(PLAIN));
+        assert!(Vault::with_password("vault-pass-1").decrypt(PLAIN).is_err());
+    }
+
+    #[test]
+    fn secret_names_match_the_patterns_ignoring_case() {
+        assert!(is_secret_name("DB_Password"));
+        assert!(is_secret_name("api_token"));
+        assert!(is_secret_name("ssh_private_key"));
+        assert!(is_secret_name("secrets"));
+        assert!(!is_secret_name("username"));
+        assert!(!is_secret_name("port"));
+    }
+
+    #[test]
+    fn wildcard_matches_any_text_for_a_star() {
+        assert!(wildcard_match("abc", "abc"));
+        assert!(!wildcard_match("abc", "abcd"));
+        assert!(wildcard_match("*secret*", "mysecretkey"));
+        assert!(wildcard_match("a*c", "abc"));
+        assert!(!wildcard_match("a*c", "abd"));
+        assert!(wildcard_match("a*b*c", "axbyc"));
+        assert!(!wildcard_match("a*b*c", "ac"));
+        assert!(!wildcard_match("ab*ba", "aba"));
+    }
+
+    #[test]
+    fn mask_replaces_the_longest_values_first_and_skips_short_ones() {
+        let mut secrets = Secrets::default();
+        secrets.add("secret");
+        secrets.add("secret-long");
+        secrets.add("abc");
+
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(secrets.mask("x secret-long y secret abc"), "x ******** y ******** abc");
+    }
+
+    #[test]
+    fn add_yaml_keeps_numbers_and_booleans_out() {
+        let value: YamlValue = serde_yaml::from_str("port: 2026\nenabled: true\nkey: value-1234\n").unwrap();
+        let mut secrets = Secrets::default();
+        secrets.add_yaml(&value);
+
+        assert_eq!(secrets.mask("2026-01-01 true value-1234"), "2026-01-01 true ********");
+    }
+
+    #[test]
+    fn add_named_takes_the_values_under_a_secret_name() {
+        let value: YamlValue = serde_yaml::from_str(
+            "db:\n  password: pw-98765\n  host: db.local\napi_token: tok-12345\nsecrets:\n  anything: any-value\n"
+        ).unwrap();
+        let mut secrets = Secrets::default();
+        secrets.add_named(&value);
+
+        assert_eq!(secrets.mask("pw-98765 tok-12345 any-value db.local"), "******** ******** ******** db.local");
+    }
+
+    #[test]
+    fn mask_json_and_yaml_reach_every_string() {
+        let mut secrets = Secrets::default();
+        secrets.add("hunter2-secret");
+
+        let mut json = serde_json::json!({ "a": ["x hunter2-secret"], "b": { "c": "hunter2-secret" }, "d": 1 });
+        secrets.mask_json(&mut json);
+        assert_eq!(json, serde_json::json!({ "a": ["x ********"], "b": { "c": "********" }, "d": 1 }));
+
+        let mut yaml: YamlValue = serde_yaml::from_str("a: [x hunter2-secret]\nb:\n  c: hunter2-secret\n").unwrap();
+        secrets.mask_yaml(&mut yaml);
+        assert_eq!(yaml, serde_yaml::from_str::<YamlValue>("a: [x ********]\nb:\n  c: '********'\n").unwrap());
+    }
+}
@@ -19,7 +19,7 @@ impl fmt::Display for YamlMergeError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             YamlMergeError::ParseError(err, yaml_content) => {
-                let error_location = format!("line {}, column {}", err.marker().line().to_string(), err.marker().col().to_string());
+                let error_location = format!("line {}, column {}", err.marker().line(), err.marker().col());
                 let snippet = get_error_snippet(yaml_content, err.marker().line(), err.marker().col());
                 write!(f, "YAML Parse Error at {}: {}\nSnippet:\n{}", error_location, err, snippet)
             },
@@ -38,7 +38,7 @@ impl From<(yaml_rust2::ScanError, String)> for YamlMergeError {
 
 pub fn get_error_snippet(yaml_content: &str, line: usize, _col: usize) -> String {
     let lines: Vec<&str> = yaml_content.lines().collect();
-    let start = if line > 2 { line - 2 } else { 0 };
+    let start = line.saturating_sub(2);
     let end = if line + 3 < lines.len() { line + 3 } else { lines.len() };
     
     let snippet: Vec<String> = lines[start..end]
@@ -74,7 +74,7 @@ pub fn combine_yaml(a: &mut Yaml, b: Yaml) {
 }
 
 pub fn load_yaml(yaml_str: &str) -> Result<Yaml, YamlMergeError> {
-    let yaml = YamlLoader::load_from_str(&yaml_str).map_err(|err| YamlMergeError::from((err, yaml_str.to_string())));
+    let yaml = YamlLoader::load_from_str(yaml_str).map_err(|err| YamlMergeError::from((err, yaml_str.to_string())));
     match yaml {
         Ok(yaml) => {
             Ok(join_yaml(yaml)?)
@@ -175,12 +175,13 @@ pub fn join_yaml(yamls: Vec<Yaml>) -> Result<Yaml, YamlMergeError> {
     Ok(merged_yaml)
 }
 
-use crate::collections::dx::files_and_dirs;
+use crate::collections::dx::vault::Vault;
 
-pub fn load(files: Vec<String>, destination: &str) -> Result<Yaml, Box<dyn std::error::Error> > {
+/// Merges the YAML files in order, later files overriding earlier ones; `vault` decrypts the encrypted ones.
+pub fn load(files: Vec<String>, vault: &Vault) -> Result<Yaml, Box<dyn std::error::Error> > {
     let mut merged_yaml = Yaml::Null;
     for file in files {
-        let file_data = files_and_dirs::read_file(&file)?;
+        let file_data = vault.read(&file)?;
         match load_yaml(&file_data) {
             Ok(documents) => {
                 combine_yaml(&mut merged_yaml, merge_keys(documents).unwrap());
@@ -190,8 +191,5 @@ pub fn load(files: Vec<String>, destination: &str) -> Result<Yaml, Box<dyn std::
             }
         }
     }
-    let out_str = yaml_to_string_pretty(&merged_yaml).unwrap_or("".to_string());
-    let _ = files_and_dirs::write_file(destination, &out_str)?;
-
     Ok(merged_yaml)
 }
\ No newline at end of file
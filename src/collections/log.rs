@@ -0,0 +1,198 @@
+// messages about the engine internals, on stderr when `CHGOPS_LOG` lets them through
+//
+// CHGOPS_LOG=debug                  every target at debug
+// CHGOPS_LOG=files=debug,template   file reads at debug, template renders at trace
+//
+// targets: files, config, template, timing, mock, registry
+//
+// a workspace loading or running installs its own scope on the thread doing it: the filter
+// with the targets its verbosity adds, and the secrets masked in its messages
+
+use std::cell::RefCell;
+use lazy_static::lazy_static;
+use crate::collections::dx::vault::{Secrets, SharedSecrets};
+
+pub const LOG_ENV: &str = "CHGOPS_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Level {
+    #[default]
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn parse(level: &str) -> Option<Level> {
+        match level.trim().to_lowercase().as_str() {
+            "off" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "OFF",
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// The most detailed level shown per target, and for the targets not named; nothing by default.
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    default: Level,
+    targets: Vec<(String, Level)>,
+}
+
+impl LogFilter {
+    /// Parses a filter such as "info,template=trace"; a target without a level is traced.
+    pub fn parse(spec: &str) -> LogFilter {
+        let mut filter = LogFilter { default: Level::Off, targets: vec![] };
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            match item.split_once('=') {
+                Some((target, level)) => filter.targets.push((target.trim().to_string(), Level::parse(level).unwrap_or(Level::Trace))),
+                None => match Level::parse(item) {
+                    Some(level) => filter.default = level,
+                    None => filter.targets.push((item.to_string(), Level::Trace)),
+                },
+            }
+        }
+        filter
+    }
+
+    pub fn from_env() -> LogFilter {
+        LogFilter::parse(&std::env::var(LOG_ENV).unwrap_or_default())
+    }
+
+    pub fn level(&self, target: &str) -> Level {
+        self.targets.iter().rev()
+            .find(|(name, _)| name == target)
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    /// Shows `target` up to `level`, unless the filter already shows it in more detail.
+    pub fn enable(&mut self, target: &str, level: Level) {
+        if self.level(target) < level {
+            self.targets.push((target.to_string(), level));
+        }
+    }
+}
+
+/// What the messages written on a thread show: the targets of `filter`, with `secrets` masked.
+#[derive(Debug, Clone, Default)]
+pub struct LogScope {
+    pub filter: LogFilter,
+    pub secrets: SharedSecrets,
+}
+
+lazy_static! {
+    /// The filter outside of any scope, e.g. for the commands that run no playbook.
+    static ref ENV_FILTER: LogFilter = LogFilter::from_env();
+}
+
+thread_local! {
+    static SCOPE: RefCell<Option<LogScope>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with the messages written on this thread filtered and masked by `scope`.
+pub fn scoped<T>(scope: LogScope, f: impl FnOnce() -> T) -> T {
+    let previous = SCOPE.with(|current| current.replace(Some(scope)));
+    let result = f();
+    SCOPE.with(|current| current.replace(previous));
+    result
+}
+
+pub fn enabled(target: &str, level: Level) -> bool {
+    let shown = SCOPE.with(|current| match current.borrow().as_ref() {
+        Some(scope) => scope.filter.level(target),
+        None => ENV_FILTER.level(target),
+    });
+    level != Level::Off && shown >= level
+}
+
+/// Masks `secrets` in the messages of the current scope from now on.
+pub fn redact(secrets: &Secrets) {
+    SCOPE.with(|current| {
+        if let Some(scope) = current.borrow().as_ref() {
+            scope.secrets.write().unwrap().extend(secrets);
+        }
+    });
+}
+
+pub fn write(target: &str, level: Level, message: &str) {
+    use colored::*;
+    let message = SCOPE.with(|current| match current.borrow().as_ref() {
+        Some(scope) => scope.secrets.read().unwrap().mask(message),
+        None => message.to_string(),
+    });
+    eprintln!("{} {}: {}", level.name().dimmed(), target, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_filter_takes_a_default_level_and_target_levels() {
+        let filter = LogFilter::parse("info, files=debug, template");
+
+        assert_eq!(filter.level("files"), Level::Debug);
+        assert_eq!(filter.level("template"), Level::Trace);
+        assert_eq!(filter.level("mock"), Level::Info);
+        assert_eq!(LogFilter::parse("").level("files"), Level::Off);
+    }
+
+    #[test]
+    fn enabling_a_target_never_lowers_its_level() {
+        let mut filter = LogFilter::parse("files=trace");
+        filter.enable("files", Level::Debug);
+        filter.enable("timing", Level::Debug);
+
+        assert_eq!(filter.level("files"), Level::Trace);
+        assert_eq!(filter.level("timing"), Level::Debug);
+    }
+
+    #[test]
+    fn a_scope_applies_to_its_thread_until_it_ends() {
+        let mut filter = LogFilter::default();
+        filter.enable("scoped-target", Level::Trace);
+        let scope = LogScope { filter, ..Default::default() };
+
+        let inside = scoped(scope.clone(), || {
+            let other_thread = std::thread::spawn(|| enabled("scoped-target", Level::Trace)).join().unwrap();
+            (enabled("scoped-target", Level::Trace), other_thread)
+        });
+
+        assert_eq!(inside, (true, false));
+        assert!(!enabled("scoped-target", Level::Trace));
+    }
+
+    #[test]
+    fn redaction_adds_to_the_secrets_of_the_current_scope_only() {
+        let first = LogScope::default();
+        let second = LogScope::default();
+        let mut secrets = Secrets::default();
+        secrets.add("hunter2-password");
+
+        scoped(first.clone(), || redact(&secrets));
+        scoped(second.clone(), || redact(&Secrets::default()));
+        redact(&secrets);
+
+        assert_eq!(first.secrets.read().unwrap().mask("pw=hunter2-password"), "pw=********");
+        assert_eq!(second.secrets.read().unwrap().mask("pw=hunter2-password"), "pw=hunter2-password");
+    }
+}
@@ -1,23 +1,66 @@
 pub mod dx;
+pub mod log;
 
 
-#[derive(Debug)]
+/// How much a run shows: `-q` for failures and the summary only, then one level per `-v`.
+///
+/// `-v` adds the command and vars of each task, `-vv` complete outputs and the output object,
+/// `-vvv` template renders and timings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum Verbose {
+    Quiet,
+    #[default]
     Empty,
     V,
     VV,
     VVV,
 }
 
-pub fn print_verbose(verbose: Verbose) {
-    match verbose {
-        Verbose::Empty => println!("No verbosity"),
-        Verbose::V => println!("Some verbosity"),
-        Verbose::VV => println!("More verbosity"),
-        Verbose::VVV => println!("Maximum verbosity"),
+impl Verbose {
+    /// The level of `-v` given `count` times.
+    pub fn from_count(count: u8) -> Verbose {
+        match count {
+            0 => Verbose::Empty,
+            1 => Verbose::V,
+            2 => Verbose::VV,
+            _ => Verbose::VVV,
+        }
+    }
+}
+
+impl std::fmt::Display for Verbose {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let text = match self {
+            Verbose::Quiet => "quiet",
+            Verbose::Empty => "",
+            Verbose::V => "v",
+            Verbose::VV => "vv",
+            Verbose::VVV => "vvv",
+        };
+        write!(f, "{}", text)
     }
 }
 
+#[macro_export]
+macro_rules! log_debug {
+    ($target:expr, $($arg:tt)*) => ({
+        use $crate::collections::log::{self, Level};
+        if log::enabled($target, Level::Debug) {
+            log::write($target, Level::Debug, &format!($($arg)*));
+        }
+    });
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($target:expr, $($arg:tt)*) => ({
+        use $crate::collections::log::{self, Level};
+        if log::enabled($target, Level::Trace) {
+            log::write($target, Level::Trace, &format!($($arg)*));
+        }
+    });
+}
+
 #[macro_export]
 macro_rules! print_error {
     ($($arg:tt)*) => ({
@@ -42,6 +85,15 @@ macro_rules! print_info {
     });
 }
 
+/// `print_info!` on stderr, for engine messages of a run whose stdout belongs to the `--output-format` sink.
+#[macro_export]
+macro_rules! eprint_info {
+    ($($arg:tt)*) => ({
+        use colored::*;
+        eprintln!("{} {}", "INFO:".blue(), format!($($arg)*));
+    });
+}
+
 #[macro_export]
 macro_rules! print_success {
     ($($arg:tt)*) => ({
@@ -0,0 +1,59 @@
+use chgops::collections::dx::{package, registry};
+use chgops::collections::dx::ChgOpsWorkspace;
+use chgops::{print_error, print_info, print_success};
+use std::path::Path;
+
+// cargo run -- build -p ./playbooks/workspace2 -n az_deixei --version 1.1.0 -c 1234
+pub fn action_build(workspace_path: &str, name: &str, version: &str, change_id: &str, output_dir: &str, debug: bool) -> bool {
+    let name = if name.is_empty() {
+        match Path::new(workspace_path).canonicalize().ok().and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string())) {
+            Some(name) => name,
+            None => {
+                print_error!("Cannot derive a package name from workspace path: {}, use --name", workspace_path);
+                return false;
+            }
+        }
+    } else {
+        name.to_string()
+    };
+
+    if let Err(e) = registry::validate_package_name(&name) {
+        print_error!("{}", e);
+        return false;
+    }
+
+    if let Err(e) = semver::Version::parse(version) {
+        print_error!("Invalid version: {}. Expected format: major.minor.patch ({})", version, e);
+        return false;
+    }
+
+    if change_id.is_empty() {
+        print_error!("A change id is required to build a package");
+        return false;
+    }
+
+    if let Err(e) = registry::validate_change_id(change_id) {
+        print_error!("{}", e);
+        return false;
+    }
+
+    print_info!("Building package {} {} for change {} from workspace: {}", name, version, change_id, workspace_path);
+
+    let collections_path = ChgOpsWorkspace::new().collection_path();
+    match package::build_package(workspace_path, &collections_path, &name, version, change_id, output_dir) {
+        Ok((package_path, manifest)) => {
+            if debug {
+                for file in manifest.files.iter() {
+                    println!("\t{} {} ({} bytes)", file.sha256, file.path, file.size);
+                }
+            }
+            manifest.display();
+            print_success!("Package built: {}", package_path);
+            true
+        },
+        Err(e) => {
+            print_error!("Building package: {}", e);
+            false
+        }
+    }
+}
@@ -25,8 +25,13 @@
 /// ./collections/my_namespace/vars.yaml
 /// ./collections/my_namespace/my_collection/vars.yaml
 /// ./collections/my_namespace/my_collection/core/vars.yaml
+/// ./collections/my_namespace/my_collection/collection.yaml
 /// ```
-use crate::{print_error, print_info, print_success, print_warning};
+use chgops::{print_error, print_info, print_success, print_warning};
+use chgops::collections::dx::collection::{self, CollectionManifest};
+use chgops::collections::dx::dependencies::{self, CollectionDependency};
+use chgops::collections::dx::external;
+use chgops::collections::dx::testing;
 use std::fs;
 use std::path::Path;
 
@@ -108,15 +113,23 @@ pub fn collection_init(namespace_name: &str, collection_name: &str, force_update
     create_or_update_file(&collection_vars_file, &collection_vars, "collection vars", force_update);
     create_or_update_file(&core_vars_file, &core_vars, "core vars", force_update);
 
+    let manifest_file = collection_folder.join(collection::MANIFEST_FILE);
+    match CollectionManifest::new(namespace_name, collection_name).to_yaml() {
+        Ok(manifest) => create_or_update_file(&manifest_file, &manifest, "collection manifest", force_update),
+        Err(e) => print_error!("Failed to render collection manifest: {}", e),
+    }
+
     print_success!("Collection {}.{} initialized.", namespace_name, collection_name);
 }
 
-pub fn collection_test(scope: &str) {
+/// Checks the collection layout and manifest, then runs every playbook in its `tests` folder.
+/// Returns false when anything failed.
+pub fn collection_test(scope: &str) -> bool {
     // scope is a combination of namespace and collection names, separated by a dot, e.g. "my_namespace.my_collection"
     let parts: Vec<&str> = scope.split('.').collect();
     if parts.len() != 2 {
         print_error!("Invalid scope format: {}. Expected format: namespace.collection", scope);
-        return;
+        return false;
     }
     let namespace_name = parts[0];
     let collection_name = parts[1];
@@ -125,72 +138,158 @@ pub fn collection_test(scope: &str) {
     //namespace_name must be a non empty string with more than 2 characters
     if namespace_name.len() < 2 {
         print_error!("Invalid namespace name: {}. Namespace name must be a non empty string with more than 2 characters", namespace_name);
-        return;
+        return false;
     }
 
     if collection_name.len() < 2 {
         print_error!("Invalid collection name: {}. Collection name must be a non empty string with more than 2 characters", collection_name);
-        return;
+        return false;
     }
 
     let collections_folder = Path::new("./collections");
     let namespace_folder = collections_folder.join(namespace_name);
     let collection_folder = namespace_folder.join(collection_name);
     let core_folder = collection_folder.join("core");
+    let mut passed = true;
 
-    if !collections_folder.exists() {
-        print_error!("Collections folder not found: {:?}", collections_folder);
-    }
-    else {
-        print_success!("Collections folder found: {:?}", collections_folder);
-    }
-
-    if !namespace_folder.exists() {
-        print_error!("Namespace folder not found: {:?}", namespace_folder);
-    }
-    else {
-        print_success!("Namespace folder found: {:?}", namespace_folder);
-    }
-
-    if !collection_folder.exists() {
-        print_error!("Collection folder not found: {:?}", collection_folder);
+    for (folder, description) in [(collections_folder.to_path_buf(), "Collections"), (namespace_folder.clone(), "Namespace"), (collection_folder.clone(), "Collection")] {
+        if folder.exists() {
+            print_success!("{} folder found: {:?}", description, folder);
+        } else {
+            print_error!("{} folder not found: {:?}", description, folder);
+            passed = false;
+        }
     }
-    else {
-        print_success!("Collection folder found: {:?}", collection_folder);
+    if !passed {
+        return false;
     }
 
-    if !core_folder.exists() {
-        print_error!("Core folder not found: {:?}", core_folder);
-    }
-    else {
+    // core and namespace vars are only scaffolding, collections may organise their vars differently
+    if core_folder.exists() {
         print_success!("Core folder found: {:?}", core_folder);
+    } else {
+        print_warning!("Core folder not found: {:?}", core_folder);
     }
 
     let namespace_vars_file = namespace_folder.join("vars.yaml");
     let collection_vars_file = collection_folder.join("vars.yaml");
-    let core_vars_file = core_folder.join("vars.yaml");
 
     if namespace_vars_file.exists() {
         print_success!("Namespace vars file found: {:?}", namespace_vars_file);
-    }
-    else {
-        print_error!("Namespace vars file not found: {:?}", namespace_vars_file);
+    } else {
+        print_warning!("Namespace vars file not found: {:?}", namespace_vars_file);
     }
 
     if collection_vars_file.exists() {
         print_success!("Collection vars file found: {:?}", collection_vars_file);
-    }
-    else {
+    } else {
         print_error!("Collection vars file not found: {:?}", collection_vars_file);
+        passed = false;
     }
 
-    if core_vars_file.exists() {
-        print_success!("Core vars file found: {:?}", core_vars_file);
+    match collection::read_manifest(&collection_folder) {
+        Ok(Some(manifest)) => {
+            let errors = manifest.validate(&collection_folder);
+            if errors.is_empty() {
+                print_success!("Collection manifest is valid: {} {}", manifest.full_name(), manifest.version);
+            }
+            for error in errors {
+                print_error!("Collection manifest: {}", error);
+                passed = false;
+            }
+        },
+        Ok(None) => {
+            print_error!("Collection manifest not found: {:?}", collection_folder.join(collection::MANIFEST_FILE));
+            passed = false;
+        },
+        Err(e) => {
+            print_error!("Collection manifest: {}", e);
+            passed = false;
+        },
     }
-    else {
-        print_error!("Core vars file not found: {:?}", core_vars_file);
+
+    let tests_folder = collection_folder.join(collection::TESTS_FOLDER);
+    let tests = match testing::find_tests(&tests_folder) {
+        Ok(tests) => tests,
+        Err(e) => {
+            print_error!("Reading tests: {}", e);
+            return false;
+        }
+    };
+    if tests.is_empty() {
+        print_warning!("No test playbooks found in {:?}", tests_folder);
+        return passed;
+    }
+
+    // the collection is loaded alone, from its own folder
+    let collection_path = fs::canonicalize(&collection_folder).unwrap_or(collection_folder.clone());
+    let dependency = CollectionDependency {
+        name: scope.to_string(),
+        path: Some(collection_path.to_string_lossy().to_string()),
+        ..Default::default()
+    };
+
+    let mut results = vec![];
+    for test in tests.iter() {
+        let name = test.file_stem().unwrap().to_string_lossy().to_string();
+        print_info!("Running test: {}", name);
+        let result = match testing::read_test_case(test) {
+            Ok(case) => testing::run_test(&name, &case, None, std::slice::from_ref(&dependency)),
+            Err(e) => testing::TestResult {
+                name: name.clone(),
+                failures: vec![e.to_string()],
+                ..Default::default()
+            },
+        };
+        results.push(result);
     }
 
+    let failed = testing::display_results(scope, &results);
+
+    passed && failed == 0
+}
+
+pub fn collection_list() {
+    let collections = match collection::discover(Path::new("./collections")) {
+        Ok(collections) => collections,
+        Err(e) => {
+            print_error!("Listing collections: {}", e);
+            return;
+        }
+    };
+
+    println!("{:<24} {:<10} DESCRIPTION", "COLLECTION", "VERSION");
+    for (name, folder) in collections {
+        match collection::read_manifest(&folder) {
+            Ok(Some(manifest)) => {
+                println!("{:<24} {:<10} {}", name, manifest.version, manifest.description.unwrap_or_default());
+            },
+            Ok(None) => {
+                let version = dependencies::collection_version(&folder).unwrap_or("-".to_string());
+                println!("{:<24} {:<10} (no {})", name, version, collection::MANIFEST_FILE);
+            },
+            Err(e) => print_error!("{}: {}", name, e),
+        }
+    }
+}
 
+pub fn collection_info(scope: &str) {
+    let Some((namespace_name, collection_name)) = scope.split_once('.') else {
+        print_error!("Invalid scope format: {}. Expected format: namespace.collection", scope);
+        return;
+    };
+    let collection_folder = Path::new("./collections").join(namespace_name).join(collection_name);
+    if !collection_folder.is_dir() {
+        print_error!("Collection folder not found: {:?}", collection_folder);
+        return;
+    }
 
+    match collection::read_manifest(&collection_folder) {
+        Ok(Some(manifest)) => manifest.display(&collection_folder),
+        Ok(None) => print_warning!("Collection {} has no {}", scope, collection::MANIFEST_FILE),
+        Err(e) => print_error!("{}", e),
+    }
+    for module in external::list_modules(&collection_folder) {
+        println!("\tModule: {}.{}", scope, module);
+    }
 }
@@ -0,0 +1,35 @@
+use chgops::collections::dx::dependencies;
+use chgops::collections::dx::ChgOpsWorkspace;
+use chgops::{print_error, print_info, print_success};
+
+// cargo run -- deps lock -p ./playbooks/workspace2
+pub fn deps_lock(workspace_path: &str) -> bool {
+    let mut workspace = ChgOpsWorkspace::new();
+    workspace.workspace_path = workspace_path.to_string();
+    workspace.write_lock = true;
+    workspace.update_lock = true;
+    let workspace_path = workspace.workspace_path();
+
+    match dependencies::read_dependencies(&workspace_path) {
+        Ok(Some(declared)) if !declared.collections.is_empty() => {},
+        Ok(_) => {
+            print_info!("No collections declared in {}, nothing to lock", workspace_path);
+            return true;
+        },
+        Err(e) => {
+            print_error!("Reading dependencies: {}", e);
+            return false;
+        }
+    }
+
+    match workspace.collection_files() {
+        Ok(_) => {
+            print_success!("Locked {} collections in {}/{}", workspace.collections.len(), workspace_path, dependencies::LOCK_FILE);
+            true
+        },
+        Err(e) => {
+            print_error!("Locking collections: {}", e);
+            false
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use chgops::collections::dx::history::{RunFilter, RunStore};
+use chgops::collections::dx::ChgOpsWorkspace;
+use chgops::{print_banner_yellow, print_error, print_info};
+use chrono::NaiveDate;
+
+fn runs_path() -> String {
+    ChgOpsWorkspace::new().runs_path()
+}
+
+fn parse_date(value: Option<&str>, description: &str) -> Result<Option<NaiveDate>, ()> {
+    match value {
+        Some(value) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => Ok(Some(date)),
+            Err(e) => {
+                print_error!("Invalid {} date: {}. Expected format: YYYY-MM-DD ({})", description, value, e);
+                Err(())
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+// cargo run -- history --stage dev --status failed --since 2024-01-01
+pub fn history_list(playbook: Option<String>, stage: Option<String>, status: Option<String>, since: Option<&str>, until: Option<&str>) {
+    let Ok(since) = parse_date(since, "since") else { return };
+    let Ok(until) = parse_date(until, "until") else { return };
+
+    let filter = RunFilter {
+        playbook,
+        stage,
+        status,
+        since,
+        until,
+    };
+
+    let records = match RunStore::new(&runs_path()).list(&filter) {
+        Ok(records) => records,
+        Err(e) => {
+            print_error!("Reading run history: {}", e);
+            return;
+        }
+    };
+
+    if records.is_empty() {
+        print_info!("No runs found");
+        return;
+    }
+
+    println!("{:<26} {:<20} {:<20} {:<6} {:<8} {:>5} {:>5} {:<12} USER", "RUN ID", "START", "PLAYBOOK", "STAGE", "STATUS", "TASKS", "FAIL", "CHANGE");
+    for record in records.iter() {
+        let start = record.report.summary.start_time
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        println!("{:<26} {:<20} {:<20} {:<6} {:<8} {:>5} {:>5} {:<12} {}",
+            record.run_id,
+            start,
+            record.report.engine.playbook_name,
+            record.report.stage.clone().unwrap_or_default(),
+            record.status,
+            record.report.summary.tasks_counter,
+            record.report.summary.failed_counter,
+            record.change_id.clone().unwrap_or_default(),
+            record.user
+        );
+    }
+}
+
+// cargo run -- show 20240101-120000-0000abcd
+pub fn history_show(run_id: &str) {
+    let record = match RunStore::new(&runs_path()).load(run_id) {
+        Ok(record) => record,
+        Err(e) => {
+            print_error!("{}", e);
+            return;
+        }
+    };
+
+    print_banner_yellow!("ChgOps - Run {}", record.run_id);
+    println!("Run Information #############################");
+    println!("\tStatus: {}", record.status);
+    println!("\tUser: {}", record.user);
+    println!("\tHost: {}", record.host);
+    println!("\tGit Commit: {}", record.git_commit.clone().unwrap_or_default());
+    println!("\tChange Id: {}", record.change_id.clone().unwrap_or_default());
+    println!("Engine Parameters ###########################");
+    println!("\tPlaybook Name: {}", record.report.engine.playbook_name);
+    println!("\tWorkspace Path: {}", record.report.engine.workspace_path);
+    println!("\tArguments: {}", record.report.engine.arguments);
+    println!("\tStage: {}", record.report.stage.clone().unwrap_or_default());
+    println!("#############################################");
+    println!("Playbook: {} #####################################", record.report.playbook);
+    println!("\tTasks count: {:?}", record.report.tasks.len());
+    println!("#############################################");
+
+    for task in record.report.tasks.iter() {
+        task.output.display_task(&task.info.name);
+        if record.report.engine.diff {
+            task.output.display_diff();
+        }
+    }
+
+    if record.report.summary.start_time.is_some() && record.report.summary.end_time.is_some() {
+        record.report.summary.display();
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{print_error, print_info, print_success, print_warning};
+use chgops::{print_error, print_info, print_success, print_warning};
 use std::fs;
 use std::path::Path;
 
@@ -78,12 +78,12 @@ tasks:
 "#;
     }
 
-    create_folder(&playbooks_path, "playbooks");
+    create_folder(playbooks_path, "playbooks");
     create_folder(&playbook_path, "playbook");
     create_folder(&playbook_vars_path, "playbook vars");
 
-    create_or_update_file(&playbook_vars_file_path, &content1, "playbook variables", force_update);
-    create_or_update_file(&playbook_files_path, &content2, "playbook file", force_update);
+    create_or_update_file(&playbook_vars_file_path, content1, "playbook variables", force_update);
+    create_or_update_file(&playbook_files_path, content2, "playbook file", force_update);
 
 
 }
\ No newline at end of file
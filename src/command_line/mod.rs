@@ -1,2 +1,12 @@
+pub mod build;
 pub mod collection;
-pub mod init;
\ No newline at end of file
+pub mod deps;
+pub mod history;
+pub mod init;
+pub mod registry;
+pub mod schema;
+pub mod tasks;
+pub mod test;
+pub mod validate;
+pub mod vars;
+pub mod vault;
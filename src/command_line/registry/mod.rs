@@ -0,0 +1,37 @@
+use chgops::collections::dx::registry;
+use chgops::{print_error, print_info, print_success};
+
+// cargo run -- publish -p ./dist/az_deixei-1_1_0-1234.zip -r /mnt/share/chgops
+pub fn action_publish(package_path: &str, feed: Option<String>, registry_argument: Option<&str>) -> bool {
+    let location = registry::registry_location(registry_argument);
+    print_info!("Publishing package {} to registry: {}", package_path, location);
+
+    match registry::open_registry(&location).publish(package_path, feed) {
+        Ok(entry) => {
+            print_success!("Published {} version {} (sha256 {})", entry.file, entry.version, entry.sha256);
+            true
+        },
+        Err(e) => {
+            print_error!("Publishing package: {}", e);
+            false
+        }
+    }
+}
+
+// cargo run -- download -n az_deixei -v latest
+pub fn action_download(name: &str, version: &str, registry_argument: Option<&str>, destination: &str) -> bool {
+    let location = registry::registry_location(registry_argument);
+    print_info!("Downloading package {} {} from registry: {}", name, version, location);
+
+    match registry::open_registry(&location).download(name, version, destination) {
+        Ok((entry, manifest)) => {
+            manifest.display();
+            print_success!("Downloaded {} version {} into {}/{}", entry.file, entry.version, destination, name);
+            true
+        },
+        Err(e) => {
+            print_error!("Downloading package: {}", e);
+            false
+        }
+    }
+}
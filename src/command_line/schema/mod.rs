@@ -0,0 +1,23 @@
+use chgops::collections::dx::{external, schema};
+use chgops::collections::dx::task_registry::TaskRegistry;
+use crate::command_line::tasks::load_collections;
+use chgops::{print_error, print_info, print_success};
+
+// cargo run -- schema export -o ./schemas/playbook.schema.json
+pub fn schema_export(output: &str) -> bool {
+    let collections = load_collections();
+    let registry = TaskRegistry::with_builtins();
+    let modules = external::loaded_modules(&collections);
+
+    print_info!("Exporting the playbook schema: {} tasks, {} modules", registry.definitions().count(), modules.len());
+    match schema::export(&registry, &modules, output) {
+        Ok(_) => {
+            print_success!("Playbook schema written: {}", output);
+            true
+        },
+        Err(e) => {
+            print_error!("Exporting the playbook schema: {}", e);
+            false
+        }
+    }
+}
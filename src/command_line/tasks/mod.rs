@@ -0,0 +1,59 @@
+use chgops::collections::dx::{collection, external};
+use chgops::collections::dx::task_registry::TaskRegistry;
+use chgops::{print_error, print_warning};
+use std::path::Path;
+
+// modules of every collection in ./collections count as tasks, as they would in a run
+pub fn load_collections() -> Vec<(String, String)> {
+    match collection::discover(Path::new("./collections")) {
+        Ok(collections) => collections.into_iter()
+            .map(|(name, folder)| (name, folder.to_string_lossy().to_string()))
+            .collect(),
+        Err(e) => {
+            print_warning!("Listing collection modules: {}", e);
+            vec![]
+        }
+    }
+}
+
+// cargo run -- tasks list
+pub fn tasks_list() {
+    let collections = load_collections();
+
+    println!("{:<28} {:<10} DESCRIPTION", "TASK", "KIND");
+    let registry = TaskRegistry::with_builtins();
+    for definition in registry.definitions() {
+        println!("{:<28} {:<10} {}", definition.name, "built-in", definition.description);
+    }
+    for module in external::loaded_modules(&collections) {
+        let path = external::find_module(&module, &collections).map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        println!("{:<28} {:<10} {}", module, "module", path);
+    }
+}
+
+// cargo run -- tasks describe -n dx.core.bash
+pub fn tasks_describe(name: &str) -> bool {
+    let collections = load_collections();
+
+    let registry = TaskRegistry::with_builtins();
+    if let Some(definition) = registry.get(name) {
+        definition.display();
+        return true;
+    }
+    if let Ok(path) = external::find_module(name, &collections) {
+        println!("Task: {} #####################################", name);
+        println!("\tModule: {:?}", path);
+        println!("\tInput: the task as JSON on stdin (task, name, command, state, register, vars, context, non_interactive)");
+        println!("\tOutput: a JSON object of output fields on stdout, a non-zero exit code fails the task");
+        println!("\tCommon fields: name, register, state, when");
+        println!("#############################################");
+        return true;
+    }
+
+    let suggestions = registry.suggestions(name, &collections);
+    match suggestions.is_empty() {
+        true => print_error!("Unknown task type {}", name),
+        false => print_error!("Unknown task type {}. Did you mean {}?", name, suggestions.join(" or ")),
+    }
+    false
+}
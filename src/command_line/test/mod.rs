@@ -0,0 +1,54 @@
+use chgops::collections::dx::{files_and_dirs, testing};
+use chgops::{print_error, print_info, print_success, print_warning};
+use std::path::Path;
+
+// a test file belongs to the workspace it sits in, or above its tests folder
+fn workspace_of(test: &Path) -> &Path {
+    let folder = test.parent().unwrap_or(Path::new("."));
+    if folder.file_name().map(|n| n == "tests").unwrap_or(false) {
+        folder.parent().unwrap_or(folder)
+    } else {
+        folder
+    }
+}
+
+// cargo run -- test --scope ./playbooks/workspace2 --junit ./temp/tests.xml
+pub fn action_test(scope: &str, junit: Option<&str>) -> bool {
+    let tests = match testing::find_workspace_tests(Path::new(scope)) {
+        Ok(tests) => tests,
+        Err(e) => {
+            print_error!("Finding tests: {}", e);
+            return false;
+        }
+    };
+    if tests.is_empty() {
+        print_warning!("No *.test.yaml files found in {}", scope);
+        return true;
+    }
+
+    let mut results = vec![];
+    for test in tests.iter() {
+        let name = testing::test_name(test);
+        print_info!("Running test: {} ({:?})", name, test);
+        let result = match testing::read_test_case(test) {
+            Ok(case) => testing::run_test(&name, &case, Some(workspace_of(test)), &[]),
+            Err(e) => testing::TestResult {
+                name: name.clone(),
+                failures: vec![e.to_string()],
+                ..Default::default()
+            },
+        };
+        results.push(result);
+    }
+
+    let failed = testing::display_results(scope, &results);
+
+    if let Some(junit) = junit.filter(|j| !j.is_empty()) {
+        match files_and_dirs::write_file(junit, &testing::to_junit(scope, &results)) {
+            Ok(_) => print_success!("JUnit report written: {}", junit),
+            Err(e) => print_error!("Writing JUnit report {}: {}", junit, e),
+        }
+    }
+
+    failed == 0
+}
@@ -0,0 +1,36 @@
+use chgops::collections::dx::ChgOpsWorkspace;
+use chgops::{print_error, print_info, print_success};
+
+// cargo run -- validate -p ./playbooks/workspace2 -n playbook
+pub fn action_validate(workspace_path: &str, playbook_name: &str) -> bool {
+    let mut workspace = ChgOpsWorkspace::new();
+    workspace.workspace_path = workspace_path.to_string();
+    workspace.playbook_name = playbook_name.to_string();
+
+    let playbooks = match workspace.playbook_files() {
+        Ok(playbooks) => playbooks,
+        Err(e) => {
+            print_error!("Listing playbooks in {}: {}", workspace.workspace_path(), e);
+            return false;
+        }
+    };
+    print_info!("Validating {} playbooks and their vars in: {}", playbooks.len(), workspace.workspace_path());
+
+    let issues = match workspace.validate() {
+        Ok(issues) => issues,
+        Err(e) => {
+            print_error!("Validating workspace: {}", e);
+            return false;
+        }
+    };
+
+    if issues.is_empty() {
+        print_success!("No validation errors");
+        return true;
+    }
+    for issue in issues.iter() {
+        print_error!("{}", issue);
+    }
+    print_error!("{} validation errors", issues.len());
+    false
+}
@@ -0,0 +1,171 @@
+use serde_yaml::Value as YamlValue;
+use std::error::Error;
+use chgops::collections::dx::provenance::{self, Provenance};
+use chgops::collections::dx::vault::Secrets;
+use chgops::collections::dx::{config_proc, ChgOpsWorkspace};
+use chgops::{print_error, print_info, print_success, print_warning};
+
+/// Vars files of a workspace for a stage: the collection ones, then the workspace ones.
+struct StageVars {
+    workspace: ChgOpsWorkspace,
+    collection_files: Vec<String>,
+    workspace_files: Vec<String>,
+}
+
+impl StageVars {
+    fn load(workspace_path: &str, stage: Option<&str>) -> Result<StageVars, Box<dyn Error>> {
+        let mut workspace = ChgOpsWorkspace::new();
+        workspace.workspace_path = workspace_path.to_string();
+        if let Some(stage) = stage {
+            workspace.arguments = format!("STAGE={}", stage);
+        }
+        Ok(StageVars {
+            collection_files: workspace.collection_files()?,
+            workspace_files: workspace.workspace_vars_files()?,
+            workspace,
+        })
+    }
+
+    fn files(&self) -> Vec<String> {
+        self.collection_files.iter().chain(self.workspace_files.iter()).cloned().collect()
+    }
+
+    /// The merged and rendered facts, as a run sees them, with their secret values masked.
+    fn render(&mut self) -> Result<YamlValue, Box<dyn Error>> {
+        let yaml = config_proc::process_configuration_files(self.collection_files.clone(), self.workspace_files.clone(), None, &self.workspace.vault)?;
+        self.workspace.facts.from_yaml2(&yaml);
+        let mut facts = self.workspace.facts.yaml.clone();
+        self.workspace.secrets().mask_yaml(&mut facts);
+        Ok(facts)
+    }
+
+    /// The secret values of the files read so far.
+    fn secrets(&self) -> Secrets {
+        self.workspace.vault.secrets(&YamlValue::Null)
+    }
+}
+
+fn yaml_inline(value: &YamlValue) -> String {
+    match value {
+        YamlValue::String(text) => text.clone(),
+        other => serde_json::to_string(other).unwrap_or_default(),
+    }
+}
+
+fn select<'a>(facts: &'a YamlValue, path: &str) -> Option<&'a YamlValue> {
+    match path.is_empty() {
+        true => Some(facts),
+        false => provenance::lookup(facts, path),
+    }
+}
+
+fn stage_name(stage: Option<&str>) -> String {
+    stage.map(|s| format!("stage {}", s)).unwrap_or("every stage".to_string())
+}
+
+// cargo run -- vars show -w ./playbooks/workspace2 --stage dev --path tags
+pub fn vars_show(workspace_path: &str, stage: Option<&str>, path: &str, format: &str) -> bool {
+    let facts = match StageVars::load(workspace_path, stage).and_then(|mut vars| vars.render()) {
+        Ok(facts) => facts,
+        Err(e) => {
+            print_error!("Rendering vars of {}: {}", stage_name(stage), e);
+            return false;
+        }
+    };
+    let Some(value) = select(&facts, path) else {
+        print_error!("{} is not set for {}", path, stage_name(stage));
+        return false;
+    };
+
+    let output = match format {
+        "json" => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        _ => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+    };
+    match output {
+        Ok(text) => {
+            println!("{}", text.trim_end());
+            true
+        },
+        Err(e) => {
+            print_error!("Writing vars as {}: {}", format, e);
+            false
+        }
+    }
+}
+
+// cargo run -- vars explain -w ./playbooks/workspace2 tags.Owner
+pub fn vars_explain(workspace_path: &str, stage: Option<&str>, path: &str) -> bool {
+    let files = StageVars::load(workspace_path, stage)
+        .and_then(|vars| Ok((Provenance::from_files(&vars.files(), &vars.workspace.vault)?, vars)));
+    let (provenance, mut vars) = match files {
+        Ok(files) => files,
+        Err(e) => {
+            print_error!("Reading vars files of {}: {}", workspace_path, e);
+            return false;
+        }
+    };
+
+    let paths = provenance.paths(path);
+    if paths.is_empty() {
+        print_error!("{} is not set by any vars file", path);
+        return false;
+    }
+
+    let rendered = match vars.render() {
+        Ok(facts) => Some(facts),
+        Err(e) => {
+            print_warning!("Vars could not be rendered: {}", e);
+            None
+        }
+    };
+
+    let secrets = vars.secrets();
+    for path in paths.iter() {
+        let layers = provenance.layers(path);
+        print_info!("{}", path);
+        for (index, origin) in layers.iter().enumerate() {
+            let state = if index + 1 == layers.len() { "final" } else { "overridden" };
+            println!("\t{} {}: {}", origin, state, secrets.mask(&yaml_inline(&origin.value)));
+        }
+        if let Some(value) = rendered.as_ref().and_then(|facts| provenance::lookup(facts, path)) {
+            println!("\trendered: {}", yaml_inline(value));
+        }
+    }
+    true
+}
+
+// cargo run -- vars diff -w ./playbooks/workspace2 --stage dev --stage prd
+pub fn vars_diff(workspace_path: &str, from: &str, to: &str, path: &str) -> bool {
+    let mut facts = vec![];
+    for stage in [from, to] {
+        match StageVars::load(workspace_path, Some(stage)).and_then(|mut vars| vars.render()) {
+            Ok(rendered) => facts.push(select(&rendered, path).map(|v| provenance::leaves(v, path)).unwrap_or_default()),
+            Err(e) => {
+                print_error!("Rendering vars of stage {}: {}", stage, e);
+                return false;
+            }
+        }
+    }
+    let (from_leaves, to_leaves) = (&facts[0], &facts[1]);
+
+    let mut differences = 0;
+    let mut paths = from_leaves.keys().chain(to_leaves.keys()).collect::<Vec<_>>();
+    paths.sort();
+    paths.dedup();
+    for key in paths {
+        match (from_leaves.get(key), to_leaves.get(key)) {
+            (Some(a), Some(b)) if a == b => continue,
+            (Some(a), Some(b)) => println!("~ {}: {} -> {}", key, yaml_inline(a), yaml_inline(b)),
+            (Some(a), None) => println!("- {}: {}", key, yaml_inline(a)),
+            (None, Some(b)) => println!("+ {}: {}", key, yaml_inline(b)),
+            (None, None) => continue,
+        }
+        differences += 1;
+    }
+
+    match differences {
+        0 => print_success!("No differences between {} and {}", from, to),
+        n => print_info!("{} differences between {} and {}", n, from, to),
+    }
+    true
+}
@@ -0,0 +1,150 @@
+use std::error::Error;
+use chgops::collections::dx::files_and_dirs;
+use chgops::collections::dx::vault::{self, Vault};
+use chgops::{print_error, print_success};
+
+/// The vault of `password_file`, asking for the password when neither the file nor the environment gives one.
+fn open_vault(password_file: &str, confirm: bool) -> Result<Vault, Box<dyn Error>> {
+    let vault = Vault::new(password_file);
+    if vault.has_password() {
+        return Ok(vault);
+    }
+    let password = rpassword::prompt_password("Vault password: ")?;
+    if password.is_empty() {
+        return Err("The vault password cannot be empty".into());
+    }
+    if confirm && rpassword::prompt_password("Confirm vault password: ")? != password {
+        return Err("The passwords do not match".into());
+    }
+    Ok(Vault::with_password(&password))
+}
+
+fn write_file(file: &str, content: &str) -> Result<(), Box<dyn Error>> {
+    std::fs::write(file, content).map_err(|e| format!("Writing {}: {}", file, e).into())
+}
+
+fn encrypt(file: &str, password_file: &str) -> Result<(), Box<dyn Error>> {
+    let content = files_and_dirs::read_file(file)?;
+    if vault::is_encrypted(&content) {
+        return Err(format!("{} is already encrypted", file).into());
+    }
+    serde_yaml::from_str::<serde_yaml::Value>(&content).map_err(|e| format!("{} is not a YAML file: {}", file, e))?;
+    let vault = open_vault(password_file, true)?;
+    write_file(file, &vault.encrypt(&content)?)
+}
+
+fn decrypt(file: &str, password_file: &str) -> Result<String, Box<dyn Error>> {
+    let content = files_and_dirs::read_file(file)?;
+    if !vault::is_encrypted(&content) {
+        return Err(format!("{} is not encrypted", file).into());
+    }
+    open_vault(password_file, false)?.decrypt(&content)
+}
+
+/// Opens the decrypted file in $EDITOR and encrypts it again when it was saved.
+fn edit(file: &str, password_file: &str) -> Result<bool, Box<dyn Error>> {
+    let content = files_and_dirs::read_file(file)?;
+    if !vault::is_encrypted(&content) {
+        return Err(format!("{} is not encrypted", file).into());
+    }
+    let vault = open_vault(password_file, false)?;
+    let plain = vault.decrypt(&content)?;
+
+    let name = std::path::Path::new(file).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let temp = std::env::temp_dir().join(format!("chgops-vault-{}-{}", std::process::id(), name));
+    write_private(&temp, &plain)?;
+
+    let editor = std::env::var("EDITOR").ok().filter(|e| !e.is_empty()).unwrap_or("vi".to_string());
+    let edited: Result<String, Box<dyn Error>> = std::process::Command::new(&editor)
+        .arg(&temp)
+        .status()
+        .map_err(|e| format!("Starting {}: {}", editor, e).into())
+        .and_then(|status| match status.success() {
+            true => Ok(std::fs::read_to_string(&temp)?),
+            false => Err(format!("{} exited with {}", editor, status).into()),
+        });
+    let _ = std::fs::remove_file(&temp);
+    let edited = edited?;
+
+    if edited == plain {
+        return Ok(false);
+    }
+    serde_yaml::from_str::<serde_yaml::Value>(&edited).map_err(|e| format!("The edited file is not YAML, {} was not changed: {}", file, e))?;
+    write_file(file, &vault.encrypt(&edited)?)?;
+    Ok(true)
+}
+
+/// Writes a file only the current user can read.
+fn write_private(path: &std::path::Path, content: &str) -> Result<(), Box<dyn Error>> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path).map_err(|e| format!("Creating {}: {}", path.display(), e))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+// cargo run -- vault encrypt ./playbooks/workspace2/vars/dev/secrets.yaml
+pub fn vault_encrypt(file: &str, password_file: &str) -> bool {
+    match encrypt(file, password_file) {
+        Ok(_) => {
+            print_success!("Encrypted {}", file);
+            true
+        },
+        Err(e) => {
+            print_error!("Encrypting {}: {}", file, e);
+            false
+        }
+    }
+}
+
+// cargo run -- vault decrypt ./playbooks/workspace2/vars/dev/secrets.yaml
+pub fn vault_decrypt(file: &str, password_file: &str) -> bool {
+    match decrypt(file, password_file).and_then(|plain| write_file(file, &plain)) {
+        Ok(_) => {
+            print_success!("Decrypted {}", file);
+            true
+        },
+        Err(e) => {
+            print_error!("Decrypting {}: {}", file, e);
+            false
+        }
+    }
+}
+
+// cargo run -- vault view ./playbooks/workspace2/vars/dev/secrets.yaml
+pub fn vault_view(file: &str, password_file: &str) -> bool {
+    match decrypt(file, password_file) {
+        Ok(plain) => {
+            print!("{}", plain);
+            true
+        },
+        Err(e) => {
+            print_error!("Viewing {}: {}", file, e);
+            false
+        }
+    }
+}
+
+// EDITOR=nano cargo run -- vault edit ./playbooks/workspace2/vars/dev/secrets.yaml
+pub fn vault_edit(file: &str, password_file: &str) -> bool {
+    match edit(file, password_file) {
+        Ok(true) => {
+            print_success!("Encrypted {}", file);
+            true
+        },
+        Ok(false) => {
+            print_success!("{} not changed", file);
+            true
+        },
+        Err(e) => {
+            print_error!("Editing {}: {}", file, e);
+            false
+        }
+    }
+}
@@ -0,0 +1,13 @@
+//! ChgOps - Change management and operations engine.
+//!
+//! The `chgops` binary is a command line over this crate; [`Workspace`] runs playbooks in-process.
+
+pub mod api;
+pub mod collections;
+
+pub use api::{Plan, PlannedTask, Workspace};
+pub use collections::Verbose;
+pub use collections::dx::mock::{MockRegistry, TaskMock};
+pub use collections::dx::observer::{MessageLevel, NoObserver, OutputStream, RunObserver};
+pub use collections::dx::report::RunReport;
+pub use collections::dx::vault::Vault;
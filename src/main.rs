@@ -1,7 +1,8 @@
 use clap::{Command, Arg};
-use collections::dx::yaml_handler;
-pub mod collections;
-use crate::collections::dx::WORKSPACE;
+use chgops::collections::dx::ChgOpsWorkspace;
+use chgops::collections::dx::vault::Vault;
+use chgops::collections::Verbose;
+use chgops::print_error;
 
 pub mod command_line;
 //use crate::command_line::collection;
@@ -46,49 +47,174 @@ fn cli() -> Command {
                 .arg(Arg::new("verbose")
                     .long("verbose")
                     .short('v')
-                    .help("Sets the level of verbosity")
+                    .help("Shows more, once per level: task command and vars, complete outputs, template renders and timings")
+                    .action(clap::ArgAction::Count))
+                .arg(Arg::new("quiet")
+                    .long("quiet")
+                    .short('q')
+                    .help("Shows only the failed tasks and the summary")
+                    .conflicts_with("verbose")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("diff")
+                    .long("diff")
+                    .help("Shows the before/after difference of every changed resource")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("non_interactive")
+                    .long("non-interactive")
+                    .help("Never wait for input: prompts read answers from --arguments or fail")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("report")
+                    .long("report")
+                    .help("Writes a run report, e.g. json=report.json,junit=report.xml")
                     .default_value("")
                     .required(false))
+                .arg(Arg::new("change_id")
+                    .long("change-id")
+                    .short('c')
+                    .help("Change ticket this run belongs to")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("mocks")
+                    .long("mocks")
+                    .help("Answers tasks from a mocks file instead of executing them, defaults to $CHGOPS_MOCKS")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("dump_intermediates")
+                    .long("dump-intermediates")
+                    .help("Writes the merged vars, rendered vars and playbook of each pipeline step to this folder")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("no_history")
+                    .long("no-history")
+                    .help("Does not record the run in .chgops/runs, e.g. for test runs")
+                    .action(clap::ArgAction::SetTrue))
+                .arg(Arg::new("vault_password_file")
+                    .long("vault-password-file")
+                    .help("File holding the password of the encrypted vars files, defaults to $CHGOPS_VAULT_PASSWORD_FILE or $CHGOPS_VAULT_PASSWORD")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("output_format")
+                    .long("output-format")
+                    .help("How the run is shown: console banners, json (one event per line) or dots")
+                    .value_parser(chgops::collections::dx::sinks::OUTPUT_FORMATS)
+                    .default_value("console")
+                    .required(false))
                 .arg(Arg::new("arguments")
                     .long("arguments")
                     .short('a')
                     .default_value("STAGE=dev")
                     .required(false)),
         )
+        .subcommand(
+            Command::new("validate")
+                .about("Checks playbooks and vars against the task and collection schemas without running anything")
+                .arg(Arg::new("name")
+                    .long("name")
+                    .short('n')
+                    .help("Playbook to check, every playbook of the workspace when not given")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("path")
+                    .long("path")
+                    .short('p')
+                    .default_value("")
+                    .required(false)),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Lists previous playbook runs")
+                .arg(Arg::new("playbook")
+                    .long("playbook")
+                    .short('n')
+                    .required(false))
+                .arg(Arg::new("stage")
+                    .long("stage")
+                    .short('s')
+                    .required(false))
+                .arg(Arg::new("status")
+                    .long("status")
+                    .value_parser(["success", "failed"])
+                    .required(false))
+                .arg(Arg::new("since")
+                    .long("since")
+                    .help("Only runs started on or after this date (YYYY-MM-DD)")
+                    .required(false))
+                .arg(Arg::new("until")
+                    .long("until")
+                    .help("Only runs started on or before this date (YYYY-MM-DD)")
+                    .required(false)),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Shows a previous playbook run")
+                .arg(Arg::new("run_id")
+                    .required(true)),
+        )
         .subcommand(
             Command::new("build")
-                .about("Builds chgops")
+                .about("Builds a versioned, deployable package of a workspace")
                 .arg(Arg::new("debug")
                     .long("debug")
                     .short('d')
-                    .required(true))
+                    .help("Lists every packaged file with its hash")
+                    .action(clap::ArgAction::SetTrue))
                 .arg(Arg::new("change_id")
-                    .long("change_id")
+                    .long("change-id")
                     .short('c')
-                    .required(true)),
+                    .required(true))
+                .arg(Arg::new("path")
+                    .long("path")
+                    .short('p')
+                    .default_value(".")
+                    .required(false))
+                .arg(Arg::new("name")
+                    .long("name")
+                    .short('n')
+                    .help("Package name, defaults to the workspace folder name")
+                    .default_value("")
+                    .required(false))
+                .arg(Arg::new("version")
+                    .long("version")
+                    .default_value("1.0.0")
+                    .required(false))
+                .arg(Arg::new("output")
+                    .long("output")
+                    .short('o')
+                    .default_value("./dist")
+                    .required(false)),
         )
         .subcommand(
             Command::new("test")
-                .about("Tests chgops")
+                .about("Runs the *.test.yaml test cases of a workspace, or a single test file")
                 .arg(Arg::new("scope")
                     .long("scope")
                     .short('s')
-                    .required(true)),
+                    .required(true))
+                .arg(Arg::new("junit")
+                    .long("junit")
+                    .help("Writes the results as JUnit XML to this file")
+                    .required(false)),
         )
         .subcommand(
             Command::new("publish")
-                .about("Publishes chgops")
+                .about("Publishes a package to a registry")
                 .arg(Arg::new("ado_pack")
                     .long("ado_pack")
-                    .required(true))
+                    .help("Feed name recorded with the published version")
+                    .required(false))
                 .arg(Arg::new("package")
                     .long("package")
                     .short('p')
-                    .required(true)),
+                    .required(true))
+                .arg(Arg::new("registry")
+                    .long("registry")
+                    .short('r')
+                    .help("Registry directory or http(s) URL, defaults to $CHGOPS_REGISTRY or ./.chgops/registry")
+                    .required(false)),
         )
         .subcommand(
             Command::new("download")
-                .about("Downloads chgops")
+                .about("Downloads a package from a registry into ./playbooks")
                 .arg(Arg::new("name")
                     .long("name")
                     .short('n')
@@ -96,7 +222,180 @@ fn cli() -> Command {
                 .arg(Arg::new("version")
                     .long("version")
                     .short('v')
-                    .required(true)),
+                    .help("latest, an exact version or a semver range such as ^1.1")
+                    .required(true))
+                .arg(Arg::new("registry")
+                    .long("registry")
+                    .short('r')
+                    .help("Registry directory or http(s) URL, defaults to $CHGOPS_REGISTRY or ./.chgops/registry")
+                    .required(false))
+                .arg(Arg::new("destination")
+                    .long("destination")
+                    .short('d')
+                    .default_value("./playbooks")
+                    .required(false)),
+        )
+        .subcommand(
+            Command::new("deps")
+                .about("Manages the collections a workspace depends on")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("lock")
+                        .about("Resolves the declared collections again and writes chgops.lock")
+                        .arg(Arg::new("path")
+                            .long("path")
+                            .short('p')
+                            .default_value("")
+                            .required(false))),
+        )
+        .subcommand(
+            Command::new("schema")
+                .about("Exports the JSON Schema of playbooks for editors")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("export")
+                        .about("Writes the schema of playbooks, their tasks and vars")
+                        .arg(Arg::new("output")
+                            .long("output")
+                            .short('o')
+                            .default_value("./schemas/playbook.schema.json")
+                            .required(false)),
+                ),
+        )
+        .subcommand(
+            Command::new("tasks")
+                .about("Lists and documents the task types playbooks can use")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("list")
+                        .about("Lists the built-in tasks and the modules of ./collections"),
+                )
+                .subcommand(
+                    Command::new("describe")
+                        .about("Describes the fields of a task type")
+                        .arg(Arg::new("name")
+                            .long("name")
+                            .short('n')
+                            .required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("vars")
+                .about("Inspects the vars the playbooks are run with")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Prints the merged and rendered vars of a stage")
+                        .arg(Arg::new("stage")
+                            .long("stage")
+                            .short('s')
+                            .help("Stage whose vars/<stage> folder is used, every folder when not given")
+                            .required(false))
+                        .arg(Arg::new("path")
+                            .long("path")
+                            .short('p')
+                            .help("Dotted path to print, e.g. tags, everything when not given")
+                            .default_value("")
+                            .required(false))
+                        .arg(Arg::new("format")
+                            .long("format")
+                            .short('f')
+                            .value_parser(["yaml", "json"])
+                            .default_value("yaml")
+                            .required(false))
+                        .arg(Arg::new("workspace")
+                            .long("workspace")
+                            .short('w')
+                            .default_value("")
+                            .required(false)),
+                )
+                .subcommand(
+                    Command::new("explain")
+                        .about("Shows which vars files set a dotted path, e.g. tags.Owner, and which one wins")
+                        .arg(Arg::new("path")
+                            .help("Dotted path of the value, e.g. tags.Owner or locations[0]")
+                            .required(true))
+                        .arg(Arg::new("stage")
+                            .long("stage")
+                            .short('s')
+                            .required(false))
+                        .arg(Arg::new("workspace")
+                            .long("workspace")
+                            .short('w')
+                            .default_value("")
+                            .required(false)),
+                )
+                .subcommand(
+                    Command::new("diff")
+                        .about("Shows the vars that differ between two stages")
+                        .arg(Arg::new("stage")
+                            .long("stage")
+                            .short('s')
+                            .help("The two stages to compare, e.g. --stage dev --stage prd")
+                            .action(clap::ArgAction::Append)
+                            .num_args(1)
+                            .required(true))
+                        .arg(Arg::new("path")
+                            .long("path")
+                            .short('p')
+                            .help("Dotted path to compare, everything when not given")
+                            .default_value("")
+                            .required(false))
+                        .arg(Arg::new("workspace")
+                            .long("workspace")
+                            .short('w')
+                            .default_value("")
+                            .required(false)),
+                ),
+        )
+        .subcommand(
+            Command::new("vault")
+                .about("Encrypts vars files holding secrets")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("encrypt")
+                        .about("Encrypts a vars file in place")
+                        .arg(Arg::new("file")
+                            .required(true))
+                        .arg(Arg::new("vault_password_file")
+                            .long("vault-password-file")
+                            .help("File holding the vault password, defaults to $CHGOPS_VAULT_PASSWORD_FILE, $CHGOPS_VAULT_PASSWORD or a prompt")
+                            .default_value("")
+                            .required(false)),
+                )
+                .subcommand(
+                    Command::new("decrypt")
+                        .about("Decrypts a vars file in place")
+                        .arg(Arg::new("file")
+                            .required(true))
+                        .arg(Arg::new("vault_password_file")
+                            .long("vault-password-file")
+                            .help("File holding the vault password, defaults to $CHGOPS_VAULT_PASSWORD_FILE, $CHGOPS_VAULT_PASSWORD or a prompt")
+                            .default_value("")
+                            .required(false)),
+                )
+                .subcommand(
+                    Command::new("view")
+                        .about("Prints a decrypted vars file")
+                        .arg(Arg::new("file")
+                            .required(true))
+                        .arg(Arg::new("vault_password_file")
+                            .long("vault-password-file")
+                            .help("File holding the vault password, defaults to $CHGOPS_VAULT_PASSWORD_FILE, $CHGOPS_VAULT_PASSWORD or a prompt")
+                            .default_value("")
+                            .required(false)),
+                )
+                .subcommand(
+                    Command::new("edit")
+                        .about("Edits a decrypted vars file in $EDITOR and encrypts it again")
+                        .arg(Arg::new("file")
+                            .required(true))
+                        .arg(Arg::new("vault_password_file")
+                            .long("vault-password-file")
+                            .help("File holding the vault password, defaults to $CHGOPS_VAULT_PASSWORD_FILE, $CHGOPS_VAULT_PASSWORD or a prompt")
+                            .default_value("")
+                            .required(false)),
+                ),
         )
         .subcommand(
             Command::new("collection")
@@ -125,6 +424,18 @@ fn cli() -> Command {
                             .long("scope")
                             .short('s')
                             .required(true)),
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("Lists the collections in ./collections"),
+                )
+                .subcommand(
+                    Command::new("info")
+                        .about("Shows the manifest of a collection")
+                        .arg(Arg::new("scope")
+                            .long("scope")
+                            .short('s')
+                            .required(true)),
                 ),
         )
 }
@@ -132,7 +443,14 @@ fn cli() -> Command {
 fn main() {
     let matches: clap::ArgMatches = cli().get_matches();
 
-    println!("ChgOps -----------------------------------------");
+    // a run in json or dots owns stdout, the banner is for the console
+    let console = match matches.subcommand() {
+        Some(("run", sub_matches)) => sub_matches.get_one::<String>("output_format").is_none_or(|format| format == "console"),
+        _ => true,
+    };
+    if console {
+        println!("ChgOps -----------------------------------------");
+    }
 
     match matches.subcommand() {
         Some(("init", sub_matches)) => {
@@ -142,7 +460,7 @@ fn main() {
             
             println!("Initializing with name: {}, template: {}", name, template);
             {
-                let _ = command_line::init::action_init(name.as_str(), template.as_str(), force);
+                command_line::init::action_init(name.as_str(), template.as_str(), force);
             }
 
         }
@@ -150,52 +468,221 @@ fn main() {
             
             let playbook_name = sub_matches.get_one::<String>("name").expect("required");
             let workspace_path = sub_matches.get_one::<String>("path").expect("required");
-            let verbose = sub_matches.get_one::<String>("verbose").expect("required");
+            let verbose = match sub_matches.get_flag("quiet") {
+                true => Verbose::Quiet,
+                false => Verbose::from_count(sub_matches.get_count("verbose")),
+            };
             let arguments = sub_matches.get_one::<String>("arguments").expect("required");
+            let diff = sub_matches.get_flag("diff");
+            let non_interactive = sub_matches.get_flag("non_interactive");
+            let report = sub_matches.get_one::<String>("report").expect("required");
+            let change_id = sub_matches.get_one::<String>("change_id").expect("required");
+            let mocks = sub_matches.get_one::<String>("mocks").expect("required");
+            let dump_intermediates = sub_matches.get_one::<String>("dump_intermediates").expect("required");
+            let output_format = sub_matches.get_one::<String>("output_format").expect("required");
+            let vault_password_file = sub_matches.get_one::<String>("vault_password_file").expect("required");
+            let no_history = sub_matches.get_flag("no_history");
             
-            println!(
-                "Running playbook: {}, verbose: {}, arguments: {}",
-                playbook_name,
-                verbose,
-                arguments
-            );
+            if output_format == "console" && verbose > Verbose::Quiet {
+                println!(
+                    "Running playbook: {}, verbose: {}, arguments: {}",
+                    playbook_name,
+                    verbose,
+                    arguments
+                );
+            }
             {
-                let mut workspace = WORKSPACE.lock().unwrap();
+                let mut workspace = ChgOpsWorkspace::new();
                 workspace.playbook_name = playbook_name.to_string();
                 workspace.workspace_path = workspace_path.to_string();
-                workspace.verbose = verbose.to_string();
+                workspace.verbose = verbose;
                 workspace.arguments = arguments.to_string();
+                workspace.diff = diff;
+                workspace.non_interactive = non_interactive;
+                workspace.report = report.to_string();
+                workspace.change_id = change_id.to_string();
+                workspace.mocks = mocks.to_string();
+                workspace.dump_intermediates = dump_intermediates.to_string();
+                workspace.output_format = output_format.to_string();
+                workspace.vault = Vault::new(vault_password_file);
+                workspace.history = !no_history;
+                workspace.write_lock = true;
 
-                workspace.load_workspace();
+                if !workspace.load_workspace() {
+                    std::process::exit(1);
+                }
 
-                workspace.run_playbook();
+                match workspace.run_playbook() {
+                    Ok(report) if report.summary.failed_counter == 0 => {}
+                    _ => std::process::exit(1),
+                }
             }
         }
-        Some(("build", sub_matches)) => {
-            println!(
-                "Building with debug: {}, change_id: {}",
-                sub_matches.get_one::<String>("debug").expect("required"),
-                sub_matches.get_one::<String>("change_id").expect("required")
+        Some(("validate", sub_matches)) => {
+            let playbook_name = sub_matches.get_one::<String>("name").expect("required");
+            let workspace_path = sub_matches.get_one::<String>("path").expect("required");
+            if !command_line::validate::action_validate(workspace_path, playbook_name) {
+                std::process::exit(1);
+            }
+        }
+        Some(("history", sub_matches)) => {
+            command_line::history::history_list(
+                sub_matches.get_one::<String>("playbook").cloned(),
+                sub_matches.get_one::<String>("stage").cloned(),
+                sub_matches.get_one::<String>("status").cloned(),
+                sub_matches.get_one::<String>("since").map(|s| s.as_str()),
+                sub_matches.get_one::<String>("until").map(|s| s.as_str()),
             );
         }
+        Some(("show", sub_matches)) => {
+            let run_id = sub_matches.get_one::<String>("run_id").expect("required");
+            command_line::history::history_show(run_id.as_str());
+        }
+        Some(("build", sub_matches)) => {
+            let debug = sub_matches.get_flag("debug");
+            let change_id = sub_matches.get_one::<String>("change_id").expect("required");
+            let workspace_path = sub_matches.get_one::<String>("path").expect("required");
+            let name = sub_matches.get_one::<String>("name").expect("required");
+            let version = sub_matches.get_one::<String>("version").expect("required");
+            let output = sub_matches.get_one::<String>("output").expect("required");
+
+            if !command_line::build::action_build(workspace_path, name, version, change_id, output, debug) {
+                std::process::exit(1);
+            }
+        }
         Some(("test", sub_matches)) => {
             let scope = sub_matches.get_one::<String>("scope").expect("required");
-            println!("Testing with scope: {}", scope);
-            let _ = yaml_handler::example();
+            let junit = sub_matches.get_one::<String>("junit").map(|j| j.as_str());
+            if !command_line::test::action_test(scope, junit) {
+                std::process::exit(1);
+            }
         }
         Some(("publish", sub_matches)) => {
-            println!(
-                "Publishing with ado_pack: {}, package: {}",
-                sub_matches.get_one::<String>("ado_pack").expect("required"),
-                sub_matches.get_one::<String>("package").expect("required")
-            );
+            let package = sub_matches.get_one::<String>("package").expect("required");
+            let feed = sub_matches.get_one::<String>("ado_pack").cloned();
+            let registry = sub_matches.get_one::<String>("registry").map(|r| r.as_str());
+
+            if !command_line::registry::action_publish(package, feed, registry) {
+                std::process::exit(1);
+            }
         }
         Some(("download", sub_matches)) => {
-            println!(
-                "Downloading with name: {}, version: {}",
-                sub_matches.get_one::<String>("name").expect("required"),
-                sub_matches.get_one::<String>("version").expect("required")
-            );
+            let name = sub_matches.get_one::<String>("name").expect("required");
+            let version = sub_matches.get_one::<String>("version").expect("required");
+            let registry = sub_matches.get_one::<String>("registry").map(|r| r.as_str());
+            let destination = sub_matches.get_one::<String>("destination").expect("required");
+
+            if !command_line::registry::action_download(name, version, registry, destination) {
+                std::process::exit(1);
+            }
+        }
+        Some(("deps", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("lock", sub_matches)) => {
+                    let workspace_path = sub_matches.get_one::<String>("path").expect("required");
+                    if !command_line::deps::deps_lock(workspace_path) {
+                        std::process::exit(1);
+                    }
+                }
+
+                _ => unreachable!(),
+            }
+        }
+        Some(("schema", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("export", sub_matches)) => {
+                    let output = sub_matches.get_one::<String>("output").expect("required");
+                    if !command_line::schema::schema_export(output.as_str()) {
+                        std::process::exit(1);
+                    }
+                }
+
+                _ => unreachable!(),
+            }
+        }
+        Some(("tasks", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("list", _)) => {
+                    command_line::tasks::tasks_list();
+                }
+                Some(("describe", sub_matches)) => {
+                    let name = sub_matches.get_one::<String>("name").expect("required");
+                    if !command_line::tasks::tasks_describe(name.as_str()) {
+                        std::process::exit(1);
+                    }
+                }
+
+                _ => unreachable!(),
+            }
+        }
+        Some(("vars", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("show", sub_matches)) => {
+                    let stage = sub_matches.get_one::<String>("stage").map(|s| s.as_str());
+                    let key = sub_matches.get_one::<String>("path").expect("required");
+                    let format = sub_matches.get_one::<String>("format").expect("required");
+                    let workspace_path = sub_matches.get_one::<String>("workspace").expect("required");
+                    if !command_line::vars::vars_show(workspace_path, stage, key, format) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("explain", sub_matches)) => {
+                    let key = sub_matches.get_one::<String>("path").expect("required");
+                    let stage = sub_matches.get_one::<String>("stage").map(|s| s.as_str());
+                    let workspace_path = sub_matches.get_one::<String>("workspace").expect("required");
+                    if !command_line::vars::vars_explain(workspace_path, stage, key) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("diff", sub_matches)) => {
+                    let stages = sub_matches.get_many::<String>("stage").expect("required").collect::<Vec<_>>();
+                    let key = sub_matches.get_one::<String>("path").expect("required");
+                    let workspace_path = sub_matches.get_one::<String>("workspace").expect("required");
+                    if stages.len() != 2 {
+                        print_error!("vars diff compares two stages, e.g. --stage dev --stage prd");
+                        std::process::exit(1);
+                    }
+                    if !command_line::vars::vars_diff(workspace_path, stages[0], stages[1], key) {
+                        std::process::exit(1);
+                    }
+                }
+
+                _ => unreachable!(),
+            }
+        }
+        Some(("vault", sub_matches)) => {
+            match sub_matches.subcommand() {
+                Some(("encrypt", sub_matches)) => {
+                    let file = sub_matches.get_one::<String>("file").expect("required");
+                    let password_file = sub_matches.get_one::<String>("vault_password_file").expect("required");
+                    if !command_line::vault::vault_encrypt(file, password_file) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("decrypt", sub_matches)) => {
+                    let file = sub_matches.get_one::<String>("file").expect("required");
+                    let password_file = sub_matches.get_one::<String>("vault_password_file").expect("required");
+                    if !command_line::vault::vault_decrypt(file, password_file) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("view", sub_matches)) => {
+                    let file = sub_matches.get_one::<String>("file").expect("required");
+                    let password_file = sub_matches.get_one::<String>("vault_password_file").expect("required");
+                    if !command_line::vault::vault_view(file, password_file) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("edit", sub_matches)) => {
+                    let file = sub_matches.get_one::<String>("file").expect("required");
+                    let password_file = sub_matches.get_one::<String>("vault_password_file").expect("required");
+                    if !command_line::vault::vault_edit(file, password_file) {
+                        std::process::exit(1);
+                    }
+                }
+
+                _ => unreachable!(),
+            }
         }
         Some(("collection", sub_matches)) => {
             // ./chgops collection init -n demo -c basic 
@@ -205,11 +692,20 @@ fn main() {
                     let collection = sub_matches.get_one::<String>("collection").expect("required");
                     let force = sub_matches.get_flag("force");
 
-                    let _ = command_line::collection::collection_init(name.as_str(), collection.as_str(), force);
+                    command_line::collection::collection_init(name.as_str(), collection.as_str(), force);
                 }
                 Some(("test", sub_matches)) => {
                     let scope = sub_matches.get_one::<String>("scope").expect("required");
-                    let _ = command_line::collection::collection_test(scope.as_str());
+                    if !command_line::collection::collection_test(scope.as_str()) {
+                        std::process::exit(1);
+                    }
+                }
+                Some(("list", _)) => {
+                    command_line::collection::collection_list();
+                }
+                Some(("info", sub_matches)) => {
+                    let scope = sub_matches.get_one::<String>("scope").expect("required");
+                    command_line::collection::collection_info(scope.as_str());
                 }
 
                 _ => unreachable!(), // If all subcommands are defined above, anything else is unreachable!
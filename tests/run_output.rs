@@ -0,0 +1,72 @@
+// what `chgops run` writes to stdout, where scripts read it
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn workspace(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chgops-run-output-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(path.join("vars")).unwrap();
+    std::fs::write(path.join("vars/config.yaml"), format!(
+        "#!chgops.config\ndependencies:\n  collections:\n    - name: dx.azure\n      path: {}/collections/dx/azure\n",
+        env!("CARGO_MANIFEST_DIR")
+    )).unwrap();
+    std::fs::write(path.join("vars/vars.yaml"), "vars: { greeting: hello }\n").unwrap();
+    std::fs::write(path.join("playbook.yaml"), r#"name: json
+settings:
+  name: json
+tasks:
+  - dx.core.bash:
+      name: greet
+      command: "echo {{ vars.greeting }}"
+      vars:
+        resource: ""
+  - dx.core.print:
+      name: shown
+      vars:
+        resource: "{{ vars.greeting }} world"
+  - dx.core.print:
+      command: success
+      name: done
+      vars:
+        resource: "all done"
+"#).unwrap();
+    path
+}
+
+#[test]
+fn every_stdout_line_of_a_json_run_is_an_event() {
+    let path = workspace("json");
+    let output = Command::new(env!("CARGO_BIN_EXE_chgops"))
+        .args(["run", "-p", path.to_str().unwrap(), "-n", "playbook", "--output-format", "json", "--no-history", "--non-interactive"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    let events = stdout.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap_or_else(|e| panic!("not JSON: {}: {}", e, line)))
+        .map(|event| event["event"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(events.first().map(|e| e.as_str()), Some("playbook_start"));
+    assert_eq!(events.last().map(|e| e.as_str()), Some("playbook_end"));
+    assert_eq!(events.iter().filter(|e| *e == "task_message").count(), 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Collection dx.azure"));
+}
+
+#[test]
+fn a_quiet_run_prints_no_task_messages() {
+    let path = workspace("quiet");
+    let output = Command::new(env!("CARGO_BIN_EXE_chgops"))
+        .args(["run", "-p", path.to_str().unwrap(), "-n", "playbook", "-q", "--no-history", "--non-interactive"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", stdout);
+    assert!(!stdout.contains("hello world"), "{}", stdout);
+    assert!(!stdout.contains("all done"), "{}", stdout);
+    assert!(stdout.contains("Executed: 3"), "{}", stdout);
+}
@@ -0,0 +1,98 @@
+// what `chgops vars` prints about the facts of a workspace and its stages
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn workspace(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("chgops-vars-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(path.join("vars/prd")).unwrap();
+    std::fs::write(path.join("vars/config.yaml"), format!(
+        "#!chgops.config\ndependencies:\n  collections:\n    - name: dx.azure\n      path: {}/collections/dx/azure\n",
+        env!("CARGO_MANIFEST_DIR")
+    )).unwrap();
+    std::fs::write(path.join("vars/vars.yaml"), r#"app:
+  owner: dev-team
+  size: 1
+  region: westeurope
+db:
+  password: s3cr3t-value
+"#).unwrap();
+    std::fs::write(path.join("vars/prd/vars.yaml"), r#"app:
+  owner: prd-team
+  size: 3
+  replicas: 2
+"#).unwrap();
+    path
+}
+
+fn vars(path: &Path, args: &[&str]) -> (Output, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_chgops"))
+        .arg("vars")
+        .args(args)
+        .args(["-w", path.to_str().unwrap()])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    (output, stdout)
+}
+
+#[test]
+fn show_merges_the_stage_folder_over_the_workspace_vars() {
+    let path = workspace("show");
+    let (output, stdout) = vars(&path, &["show", "-s", "prd", "-p", "app", "-f", "json"]);
+    assert!(output.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+
+    let json = &stdout[stdout.find('{').expect("no JSON in the output")..];
+    let app: serde_json::Value = serde_json::from_str(json).unwrap_or_else(|e| panic!("not JSON: {}: {}", e, json));
+    assert_eq!(app, serde_json::json!({ "owner": "prd-team", "size": 3, "region": "westeurope", "replicas": 2 }));
+}
+
+#[test]
+fn show_masks_the_secrets() {
+    let path = workspace("secrets");
+    let (output, stdout) = vars(&path, &["show", "-p", "db"]);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("password: \"********\""), "{}", stdout);
+    assert!(!stdout.contains("s3cr3t-value"), "{}", stdout);
+}
+
+#[test]
+fn explain_lists_every_layer_that_sets_a_var() {
+    let path = workspace("explain");
+    let (output, stdout) = vars(&path, &["explain", "-s", "prd", "app.owner"]);
+    assert!(output.status.success(), "{}", stdout);
+
+    let base = path.join("vars/vars.yaml");
+    let stage = path.join("vars/prd/vars.yaml");
+    assert!(stdout.contains(&format!("{}:2:3 overridden: dev-team", base.display())), "{}", stdout);
+    assert!(stdout.contains(&format!("{}:2:3 final: prd-team", stage.display())), "{}", stdout);
+    assert!(stdout.contains("rendered: prd-team"), "{}", stdout);
+}
+
+#[test]
+fn diff_lists_what_changes_between_two_stages() {
+    let path = workspace("diff");
+    let (output, stdout) = vars(&path, &["diff", "-s", "dev", "-s", "prd", "-p", "app"]);
+    assert!(output.status.success(), "{}", stdout);
+
+    let changes = stdout.lines()
+        .filter(|line| line.starts_with("~ ") || line.starts_with("+ ") || line.starts_with("- "))
+        .collect::<Vec<_>>();
+    assert_eq!(changes, vec![
+        "~ app.owner: dev-team -> prd-team",
+        "+ app.replicas: 2",
+        "~ app.size: 1 -> 3",
+    ]);
+    assert!(stdout.contains("3 differences between dev and prd"), "{}", stdout);
+}
+
+#[test]
+fn a_path_that_is_not_set_fails() {
+    let path = workspace("missing");
+    let (output, stdout) = vars(&path, &["show", "-p", "missing"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "{}", stdout);
+    assert!(stderr.contains("missing is not set"), "{}\n{}", stdout, stderr);
+}